@@ -0,0 +1,32 @@
+//! Switching projects when entering a directory.
+//!
+//! A directory is mapped to a project by a `.temps` file in it, or in one of
+//! its parents.  The file holds the name of the project, and may have
+//! comments on lines starting with `#`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Name of the files mapping directories to projects.
+pub const FILE_NAME: &str = ".temps";
+
+/// The project of a directory, if there's one.
+pub fn project(dir: &Path) -> Result<Option<String>> {
+    for dir in dir.ancestors() {
+        let file = dir.join(FILE_NAME);
+        if !file.is_file() {
+            continue;
+        }
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Could not read '{}'", file.display()))?;
+        let project = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'));
+        // An empty file stops the search, e.g. to leave a subdirectory out
+        return Ok(project.map(str::to_owned));
+    }
+    Ok(None)
+}
@@ -0,0 +1,148 @@
+//! Cache of the time tracked on each project, for each day.
+//!
+//! Summaries over long ranges (full, yearly, past weeks) are computed from
+//! these daily totals.  The cache lives in the platform-specific cache
+//! directory, and is recomputed whenever the tracking file changes.  Ongoing
+//! entries aren't cached, since their duration changes all the time.
+
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use time::macros::format_description;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::error::Failure;
+use crate::summary::{self, DailyTotals};
+use crate::{paths, storage, Entry};
+
+const VERSION: &str = "temps-cache-v1";
+
+/// Compute the daily totals of a tracking file, using the cache if it's fresh.
+///
+/// The ongoing entry, if any, is counted up to `now` and returned as well.
+pub fn daily_totals(
+    path: &Path,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> Result<(DailyTotals, Option<Entry>)> {
+    let key = key(path, midnight_offset, now)?;
+    let cache_path = paths::daily_totals(path);
+
+    let (mut totals, ongoing) = match load(&cache_path, &key) {
+        Ok(Some(totals)) => (
+            totals,
+            storage::read_last(path)?.filter(|entry| entry.is_ongoing()),
+        ),
+        _ => {
+            // The whole file is aggregated as it's read, without keeping its
+            // entries around
+            let mut totals = DailyTotals::new();
+            let mut ongoing = false;
+            storage::for_each_span(path, |span| match span.end {
+                Some(_) => summary::add_to_daily_totals(&mut totals, &span, midnight_offset, now),
+                None => ongoing = true,
+            })?;
+            // The cache is only an optimisation, failing to write it is fine
+            let _ = save(&cache_path, &key, &totals);
+            let ongoing = if ongoing {
+                storage::read_last(path)?.filter(|entry| entry.is_ongoing())
+            } else {
+                None
+            };
+            (totals, ongoing)
+        }
+    };
+
+    if let Some(entry) = &ongoing {
+        for (day, projects) in
+            summary::daily_totals(std::slice::from_ref(entry), midnight_offset, now)
+        {
+            let day_totals = totals.entry(day).or_default();
+            for (project, duration) in projects {
+                *day_totals.entry(project).or_default() += duration;
+            }
+        }
+    }
+    Ok((totals, ongoing))
+}
+
+/// Identify the state of a tracking file: its length and modification time
+/// (in nanoseconds since the Unix epoch).
+pub fn fingerprint(path: &Path) -> Result<(u64, u128)> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok((
+            metadata.len(),
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_nanos()),
+        )),
+        Err(_) if !path.exists() => Ok((0, 0)),
+        Err(err) => Err(err).context(Failure::DataFile("Could not read tracking file")),
+    }
+}
+
+/// Identify the state of the tracking file and the settings the totals depend on.
+fn key(path: &Path, midnight_offset: Duration, now: OffsetDateTime) -> Result<StringRecord> {
+    let (len, modified) = fingerprint(path)?;
+    Ok(StringRecord::from(vec![
+        VERSION.to_owned(),
+        len.to_string(),
+        modified.to_string(),
+        midnight_offset.whole_seconds().to_string(),
+        now.offset().whole_seconds().to_string(),
+    ]))
+}
+
+/// Load the cached totals, if they were computed for the same key.
+fn load(path: &Path, key: &StringRecord) -> Result<Option<DailyTotals>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+    let mut records = reader.records();
+    if records.next().transpose()?.as_ref() != Some(key) {
+        return Ok(None);
+    }
+
+    let mut totals = DailyTotals::new();
+    for record in records {
+        let record = record?;
+        let (Some(day), Some(project), Some(seconds)) =
+            (record.get(0), record.get(1), record.get(2))
+        else {
+            return Ok(None);
+        };
+        let day = Date::parse(day, format_description!("[year]-[month]-[day]"))?;
+        totals
+            .entry(day)
+            .or_default()
+            .insert(project.to_owned(), Duration::seconds(seconds.parse()?));
+    }
+    Ok(Some(totals))
+}
+
+fn save(path: &Path, key: &StringRecord, totals: &DailyTotals) -> Result<()> {
+    paths::create_parent(path)?;
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .from_path(path)?;
+    writer.write_record(key)?;
+    for (day, projects) in totals {
+        let day = day.format(format_description!("[year]-[month]-[day]"))?;
+        for (project, duration) in projects {
+            writer.write_record([&day, project, &duration.whole_seconds().to_string()])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Shell completions, printed or installed in the shell's per-user directory.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap_complete::{generate, Shell};
+use directories::BaseDirs;
+
+/// Write the completions for a shell.
+///
+/// With Bash, Zsh and Fish, project names are completed too, by calling back
+/// `temps completions projects`.
+pub fn write(shell: Shell, mut command: clap::Command, out: &mut dyn Write) -> Result<()> {
+    let bin_name = command.get_name().to_string();
+    let (commands, options) = project_args(&command);
+    let mut script = vec![];
+    generate(shell, &mut command, bin_name.clone(), &mut script);
+    let script = String::from_utf8(script).context("Completions aren't valid UTF-8")?;
+    let fill = |template: &str| {
+        template
+            .replace("{{commands}}", &commands.join(" "))
+            .replace("{{options}}", &options.join(" "))
+    };
+
+    match shell {
+        Shell::Bash => {
+            write!(out, "{}", script)?;
+            write!(out, "{}", fill(include_str!("shell/projects.bash")))?;
+        }
+        Shell::Zsh => {
+            // `_arguments` is given no action for projects: they're completed
+            // by a helper, defined before the script's final call to `_temps`
+            let call = format!("_{} \"$@\"\n", bin_name);
+            let body = script.strip_suffix(&call).unwrap_or(&script);
+            for line in body.trim_end().lines() {
+                writeln!(out, "{}", zsh_with_projects(line))?;
+            }
+            write!(out, "{}", include_str!("shell/projects.zsh"))?;
+            if body.len() < script.len() {
+                write!(out, "\n{}", call)?;
+            }
+        }
+        Shell::Fish => {
+            // For fish shell, never complete on file names
+            writeln!(out, "complete -c {} -f", bin_name)?;
+            write!(out, "{}", script)?;
+            write!(out, "{}", fill(include_str!("shell/projects.fish")))?;
+        }
+        _ => write!(out, "{}", script)?,
+    }
+    Ok(())
+}
+
+/// Subcommands taking a project as their argument, e.g. `start`, and options
+/// taking a project, e.g. `--exclude`.
+fn project_args(command: &clap::Command) -> (Vec<String>, Vec<String>) {
+    let mut commands = vec![];
+    let mut options = BTreeSet::new();
+    for subcommand in command.get_subcommands() {
+        for arg in subcommand.get_arguments() {
+            let is_project = arg.get_id() == "project"
+                || arg
+                    .get_value_names()
+                    .is_some_and(|names| names.iter().any(|name| name == "PROJECT"));
+            if !is_project {
+                continue;
+            }
+            match arg.get_long() {
+                Some(long) => {
+                    options.insert(format!("--{}", long));
+                }
+                None => commands.push(subcommand.get_name().to_owned()),
+            }
+        }
+    }
+    (commands, options.into_iter().collect())
+}
+
+/// A line of the Zsh completions, completing project names where it specifies
+/// a project argument.
+fn zsh_with_projects(line: &str) -> String {
+    let positional = line.starts_with("':project -- ") || line.starts_with("'::project -- ");
+    if positional && line.ends_with(":' \\") {
+        let rest = &line[..line.len() - "' \\".len()];
+        format!("{}_temps_projects' \\", rest)
+    } else {
+        line.replace(":PROJECT: '", ":PROJECT:_temps_projects'")
+    }
+}
+
+/// Write the completions for a shell where it looks for them, and return the
+/// path of the file.
+pub fn install(shell: Shell, command: clap::Command) -> Result<PathBuf> {
+    let path = install_path(shell)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create '{}'", parent.display()))?;
+    }
+    let mut file =
+        File::create(&path).with_context(|| format!("Could not write '{}'", path.display()))?;
+    write(shell, command, &mut file)?;
+    Ok(path)
+}
+
+/// Say where completions were installed, and how to load them if needed.
+pub fn print_installed(path: &Path) {
+    println!("Installed completions in {}.", path.display());
+    if path.ends_with(".zfunc/_temps") {
+        println!("Add `fpath+=~/.zfunc` before `compinit` in ~/.zshrc to use them.");
+    }
+}
+
+/// Where a shell looks for the completions of the current user.
+///
+/// Zsh has no such directory by default, so the completions go in
+/// `~/.zfunc`, which must be added to `$fpath`.
+pub fn install_path(shell: Shell) -> Result<PathBuf> {
+    let Some(dirs) = BaseDirs::new() else {
+        bail!("Could not determine the home directory");
+    };
+    Ok(match shell {
+        Shell::Bash => dirs.data_dir().join("bash-completion/completions/temps"),
+        Shell::Zsh => dirs.home_dir().join(".zfunc/_temps"),
+        Shell::Fish => dirs.config_dir().join("fish/completions/temps.fish"),
+        _ => bail!(
+            "Completions for {} can't be installed, print them with --generate-completions instead",
+            shell
+        ),
+    })
+}
+
+/// The shell of the current user, if it's one completions can be installed for.
+pub fn current_shell() -> Option<Shell> {
+    Shell::from_env().filter(|shell| matches!(shell, Shell::Bash | Shell::Zsh | Shell::Fish))
+}
@@ -0,0 +1,687 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use time::macros::format_description;
+use time::{Date, Duration, Weekday};
+
+use crate::locale::{Clock, DateOrder};
+use crate::parse::{parse_duration, parse_window};
+use crate::table::Style;
+use crate::viz::{Glyphs, Labels, Theme};
+
+/// User configuration, read from a TOML file.
+///
+/// Every field is optional; a missing file is the same as an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub summary: SummaryConfig,
+    pub storage: StorageConfig,
+    pub table: TableConfig,
+    pub balance: BalanceConfig,
+    pub holidays: HolidaysConfig,
+    pub context: ContextConfig,
+    pub viz: VizConfig,
+    pub email: EmailConfig,
+    pub auto_switch: AutoSwitchConfig,
+    pub watch: WatchConfig,
+    pub mqtt: MqttConfig,
+    pub display: DisplayConfig,
+    pub invoice: InvoiceConfig,
+    pub timesheet: TimesheetConfig,
+    /// Daily goals of some projects.
+    pub goals: BTreeMap<String, Goal>,
+    /// Time budgeted on some projects, all in all.
+    pub budgets: BTreeMap<String, Goal>,
+    /// Projects listed even before anything was tracked on them.
+    pub projects: Vec<String>,
+    /// Blocks of time that `temps fill` records, e.g. a daily meeting.
+    pub recurring: Vec<Recurring>,
+}
+
+/// Time to spend on something, e.g. `"6:00"` a day on a project.
+#[derive(Debug)]
+pub struct Goal(pub Duration);
+
+impl<'de> Deserialize<'de> for Goal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        parse_duration(&src)
+            .map(Goal)
+            .map_err(|err| D::Error::custom(format!("{:#}", err)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SummaryConfig {
+    /// Projects that are never included in summaries (e.g. breaks), only the
+    /// time away from the computer by default.
+    pub exclude: Vec<String>,
+    /// Time to work each day, against which the weekly grid marks each day.
+    pub daily_target: Option<Goal>,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        SummaryConfig {
+            exclude: vec![AFK.to_owned()],
+            daily_target: None,
+        }
+    }
+}
+
+/// How dates, times and numbers are displayed, instead of following the
+/// locale.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DisplayConfig {
+    pub clock: Option<Clock>,
+    pub date_order: Option<DateOrder>,
+    /// Between the year, month and day, e.g. `"."`.
+    pub date_separator: Option<String>,
+    /// Between the whole and decimal parts of numbers, e.g. `","`.
+    pub decimal_separator: Option<String>,
+    /// Between groups of three digits, e.g. `"."` or `" "`.
+    pub thousands_separator: Option<String>,
+}
+
+/// How amounts of money are written by templates, e.g. in invoices.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct InvoiceConfig {
+    /// Currency of amounts, e.g. `"€"` or `"USD"`.
+    pub currency: Option<String>,
+    /// Where the currency goes, from the locale by default.
+    pub currency_position: Option<CurrencyPosition>,
+    /// Currency of some clients, by project.  A project also covers those
+    /// under it, e.g. `client-a` covers `client-a/design`.
+    pub currencies: BTreeMap<String, String>,
+    /// Hourly rate, in the currency of each project.
+    pub rate: Option<f64>,
+    /// Hourly rate of some clients, by project, like `currencies`.
+    pub rates: BTreeMap<String, f64>,
+}
+
+/// Where the currency goes, around an amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurrencyPosition {
+    /// `€1,234.50`
+    Before,
+    /// `1.234,50 €`
+    After,
+}
+
+impl InvoiceConfig {
+    /// Currency of the amounts of a project: that of the project or the
+    /// closest one above it, or the default one.
+    pub fn currency_of(&self, project: &str) -> Option<&str> {
+        closest(&self.currencies, project)
+            .or(self.currency.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Hourly rate of a project, like [`InvoiceConfig::currency_of`].
+    pub fn rate_of(&self, project: &str) -> Option<f64> {
+        closest(&self.rates, project)
+            .or(self.rate.as_ref())
+            .copied()
+    }
+}
+
+/// How timesheets are laid out, to match what an HR system imports.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TimesheetConfig {
+    /// Name or ID of the employee, in every row (the user name by default).
+    pub employee: Option<String>,
+    /// Between columns, e.g. `';'`.
+    pub delimiter: char,
+    /// Between the whole and decimal parts of hours, e.g. `","`.
+    pub decimal_separator: String,
+    /// Code of some projects in the HR system, like `[invoice.currencies]`.
+    pub codes: BTreeMap<String, String>,
+    /// Columns of the file, in order.
+    pub columns: Vec<TimesheetColumn>,
+}
+
+impl Default for TimesheetConfig {
+    fn default() -> Self {
+        let column = |header: &str, field| TimesheetColumn {
+            header: header.to_owned(),
+            field: Some(field),
+            value: None,
+        };
+        TimesheetConfig {
+            employee: None,
+            delimiter: ',',
+            decimal_separator: ".".to_owned(),
+            codes: BTreeMap::new(),
+            columns: vec![
+                column("Employee", TimesheetField::Employee),
+                column("Week", TimesheetField::Week),
+                column("Date", TimesheetField::Date),
+                column("Project", TimesheetField::Project),
+                column("Hours", TimesheetField::Hours),
+            ],
+        }
+    }
+}
+
+impl TimesheetConfig {
+    /// Code of a project in the HR system: that of the project or the closest
+    /// one above it, or else its name.
+    pub fn code_of<'a>(&'a self, project: &'a str) -> &'a str {
+        closest(&self.codes, project).map_or(project, String::as_str)
+    }
+}
+
+/// A column of timesheets: its header, and either what's in it or a value
+/// that's always the same (e.g. a cost center).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TimesheetColumn {
+    pub header: String,
+    pub field: Option<TimesheetField>,
+    pub value: Option<String>,
+}
+
+/// What a column of timesheets holds, for each project and day.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimesheetField {
+    /// `employee` of `[timesheet]`
+    Employee,
+    /// ISO week, e.g. `2021-W37`
+    Week,
+    /// `YYYY-MM-DD`
+    Date,
+    /// e.g. `Monday`
+    Weekday,
+    Project,
+    /// Code of the project in the HR system (see `codes`)
+    Code,
+    /// Decimal hours, e.g. `1.50`
+    Hours,
+    Minutes,
+}
+
+/// The value of a project, or of the closest project above it.
+fn closest<'a, T>(values: &'a BTreeMap<String, T>, project: &str) -> Option<&'a T> {
+    let parents = project.rmatch_indices('/').map(|(i, _)| &project[..i]);
+    iter::once(project)
+        .chain(parents)
+        .find_map(|project| values.get(project))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TableConfig {
+    /// How tables are drawn.
+    pub style: Style,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BalanceConfig {
+    /// Time to work each week, spread evenly over Monday to Friday.
+    pub weekly_target: Option<Goal>,
+    /// Day from which the balance is computed, e.g. `"2024-01-01"`.
+    #[serde(deserialize_with = "date")]
+    pub since: Option<Date>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HolidaysConfig {
+    /// Non-working days, e.g. `["2024-12-25"]`.
+    #[serde(deserialize_with = "dates")]
+    pub dates: Vec<Date>,
+    /// File listing more non-working days, one `YYYY-MM-DD` per line (relative
+    /// to the config file).
+    pub file: Option<PathBuf>,
+}
+
+impl HolidaysConfig {
+    /// All the non-working days, from the config and the holiday file.
+    ///
+    /// In the holiday file, a date may be followed by the name of the holiday,
+    /// and lines starting with `#` are ignored.
+    pub fn days(&self) -> Result<BTreeSet<Date>> {
+        let mut days = self.dates.iter().copied().collect::<BTreeSet<_>>();
+        if let Some(file) = &self.file {
+            let contents = fs::read_to_string(file)
+                .with_context(|| format!("Could not read holiday file {}", file.display()))?;
+            for (i, line) in contents.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let date = line.split_whitespace().next().unwrap_or_default();
+                days.insert(parse_day(date).with_context(|| {
+                    format!(
+                        "Could not parse holiday file {}, line {}",
+                        file.display(),
+                        i + 1
+                    )
+                })?);
+            }
+        }
+        Ok(days)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ContextConfig {
+    /// Shell command printing the context of new entries, e.g. by checking
+    /// the Wi-Fi network.
+    pub command: Option<String>,
+}
+
+impl ContextConfig {
+    /// Run the configured command, if any, and return what it printed.
+    ///
+    /// Nothing (or only whitespace) means there's no context.
+    pub fn detect(&self) -> Result<Option<String>> {
+        let Some(command) = &self.command else {
+            return Ok(None);
+        };
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .with_context(|| format!("Could not run context command '{}'", command))?;
+        if !output.status.success() {
+            bail!("Context command '{}' failed ({})", command, output.status);
+        }
+        let context = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        Ok(Some(context).filter(|context| !context.is_empty()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EmailConfig {
+    /// Shell command sending the message it reads on stdin, with its
+    /// recipients in the headers (e.g. `msmtp -t`, which handles SMTP
+    /// servers, passwords and keyrings).
+    pub command: String,
+    /// Sender of the reports, if the command doesn't set it.
+    pub from: Option<String>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            command: "sendmail -t".to_owned(),
+            from: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutoSwitchConfig {
+    /// How long an entry is kept before entering a directory switches away
+    /// from it, so that walking through directories doesn't leave a trail of
+    /// tiny entries.
+    pub cooldown: Goal,
+}
+
+impl Default for AutoSwitchConfig {
+    fn default() -> Self {
+        AutoSwitchConfig {
+            cooldown: Goal(Duration::minutes(5)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MqttConfig {
+    /// Broker to publish to; nothing is published without one.
+    pub host: Option<String>,
+    pub port: u16,
+    /// Topic under which events and the status are published.
+    pub topic: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: None,
+            port: 1883,
+            topic: "temps".to_owned(),
+        }
+    }
+}
+
+/// A block of time spent on a project on some days, always at the same time.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Recurring {
+    pub project: String,
+    /// Days of the week on which the block happens, every day by default.
+    #[serde(default)]
+    pub days: Days,
+    /// Time of day of the block, e.g. `"09:30-09:45"`.
+    pub time: Window,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Days of the week, from Monday.
+const WEEK: [Weekday; 7] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+/// Days of the week, e.g. `"weekdays"` or `["monday", "thu"]`.
+#[derive(Debug)]
+pub struct Days(pub Vec<Weekday>);
+
+impl Days {
+    pub fn contains(&self, weekday: Weekday) -> bool {
+        self.0.contains(&weekday)
+    }
+}
+
+impl Default for Days {
+    fn default() -> Self {
+        Days(WEEK.to_vec())
+    }
+}
+
+impl<'de> Deserialize<'de> for Days {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Src {
+            Named(String),
+            List(Vec<String>),
+        }
+        let days = match Src::deserialize(deserializer)? {
+            Src::Named(name) => match name.as_str() {
+                "every day" | "daily" => WEEK.to_vec(),
+                "weekdays" => WEEK[..5].to_vec(),
+                "weekends" => WEEK[5..].to_vec(),
+                _ => vec![parse_weekday(&name).map_err(D::Error::custom)?],
+            },
+            Src::List(names) => names
+                .iter()
+                .map(|name| parse_weekday(name).map_err(D::Error::custom))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Days(days))
+    }
+}
+
+/// Parse the name of a day of the week, e.g. `monday` or `mon`.
+fn parse_weekday(src: &str) -> Result<Weekday, String> {
+    let src = src.to_lowercase();
+    WEEK.into_iter()
+        .find(|weekday| {
+            let name = weekday.to_string().to_lowercase();
+            src == name || src == name[..3]
+        })
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a day of the week, expected e.g. \"monday\", \"weekdays\" or \"every day\"",
+                src
+            )
+        })
+}
+
+/// A window of time of day, e.g. `"09:30-09:45"`, as durations since midnight.
+#[derive(Debug)]
+pub struct Window(pub Duration, pub Duration);
+
+impl<'de> Deserialize<'de> for Window {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        parse_window(&src)
+            .map(|(start, end)| Window(start, end))
+            .map_err(|err| D::Error::custom(format!("{:#}", err)))
+    }
+}
+
+/// Pseudo-project of the time away from the computer.
+pub const AFK: &str = "afk";
+
+/// Where the focused window comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    /// X11, with `xdotool`
+    #[default]
+    X11,
+    /// The Sway compositor, with `swaymsg`
+    Sway,
+    /// The Hyprland compositor, with `hyprctl`
+    Hyprland,
+    /// The configured command
+    Command,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WatchConfig {
+    pub provider: Provider,
+    /// Shell command printing the focused app, then its title, on two lines
+    /// (with `provider = "command"`).
+    pub command: Option<String>,
+    /// Seconds between two looks at the focused window.
+    pub interval: u64,
+    /// How long a window stays focused before switching to its project.
+    pub switch_after: Goal,
+    /// How long without input counts as being away, if idle time is watched.
+    pub idle_after: Option<Goal>,
+    /// Shell command printing the idle time, in milliseconds.
+    pub idle_command: String,
+    /// Record the time away as entries of the `afk` project, instead of
+    /// stopping the ongoing entry.
+    pub afk: bool,
+    /// Time of day at which the ongoing entry is stopped, e.g. `"19:00"`.
+    pub stop_at: Option<Goal>,
+    /// Shell command asking whether to stop at `stop_at` (e.g. with `zenity
+    /// --question`), which only happens if it succeeds.
+    pub ask_command: Option<String>,
+    /// Projects of windows, the first matching rule wins.
+    pub rules: Vec<WatchRule>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            provider: Provider::default(),
+            command: None,
+            interval: 10,
+            switch_after: Goal(Duration::minutes(1)),
+            idle_after: None,
+            idle_command: "xprintidle".to_owned(),
+            afk: false,
+            stop_at: None,
+            ask_command: None,
+            rules: vec![],
+        }
+    }
+}
+
+/// A project, for windows whose app and title match some patterns.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WatchRule {
+    pub app: Option<Pattern>,
+    pub title: Option<Pattern>,
+    pub project: String,
+}
+
+impl WatchRule {
+    /// Whether a window matches the rule, i.e. all of its patterns.
+    pub fn matches(&self, app: &str, title: &str) -> bool {
+        self.app
+            .as_ref()
+            .is_none_or(|Pattern(app_re)| app_re.is_match(app))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|Pattern(title_re)| title_re.is_match(title))
+    }
+}
+
+/// A regular expression.
+#[derive(Debug)]
+pub struct Pattern(pub Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        Regex::new(&src)
+            .map(Pattern)
+            .map_err(|err| D::Error::custom(format!("{:#}", err)))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct VizConfig {
+    /// Characters of the bars, replacing the default block characters.
+    pub full: Option<char>,
+    pub upper_half: Option<char>,
+    pub lower_half: Option<char>,
+    /// Character of the bars of the ongoing entry.
+    pub ongoing: Option<char>,
+    /// Character of the line under the time labels.
+    pub border: Option<char>,
+    /// Character of the untracked quarter-hours in the compact timeline.
+    pub empty: Option<char>,
+    /// Width of the bars.
+    pub bar_width: Option<usize>,
+    /// Where the names of projects go.
+    pub labels: Labels,
+}
+
+impl VizConfig {
+    /// The theme of charts, drawn with some glyphs unless overridden.
+    pub fn theme(&self, glyphs: Glyphs) -> Theme {
+        let default = Theme::default();
+        Theme {
+            glyphs: Glyphs {
+                full: self.full.unwrap_or(glyphs.full),
+                upper_half: self.upper_half.unwrap_or(glyphs.upper_half),
+                lower_half: self.lower_half.unwrap_or(glyphs.lower_half),
+                ongoing: self.ongoing.unwrap_or(glyphs.ongoing),
+                border: self.border.unwrap_or(glyphs.border),
+                empty: self.empty.unwrap_or(glyphs.empty),
+                ..glyphs
+            },
+            bar_width: self.bar_width.unwrap_or(default.bar_width).max(1),
+            labels: self.labels,
+            ..default
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StorageConfig {
+    /// Where entries are kept (relative to the config file), unless the
+    /// `TEMPS_FILE` environment variable or `--temps-file` says otherwise.
+    pub file: Option<PathBuf>,
+    /// Keep an index of the tracking file, to speed up queries on past dates.
+    pub index: bool,
+    /// Cache daily totals, to speed up summaries over long ranges.
+    pub cache: bool,
+    /// Keep a binary snapshot of the entries, to skip parsing the tracking
+    /// file when it hasn't changed.
+    pub snapshot: bool,
+    /// Record the machine each entry was recorded on.
+    pub source: bool,
+    /// Name of this machine in the entries (the hostname by default).
+    pub device_name: Option<String>,
+}
+
+impl StorageConfig {
+    /// The machine to record on new entries, if it's recorded at all.
+    pub fn source(&self) -> Option<String> {
+        if !self.source {
+            return None;
+        }
+        self.device_name
+            .clone()
+            .or_else(|| gethostname::gethostname().into_string().ok())
+            .filter(|name| !name.is_empty())
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            index: false,
+            cache: true,
+            snapshot: false,
+            source: false,
+            device_name: None,
+        }
+    }
+}
+
+/// Parse a date with format `YYYY-MM-DD`.
+fn parse_day(src: &str) -> Result<Date> {
+    Date::parse(src, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{}' is not a valid date, expected YYYY-MM-DD", src))
+}
+
+/// Deserialize an optional date with format `YYYY-MM-DD`.
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Date>, D::Error> {
+    let src = String::deserialize(deserializer)?;
+    parse_day(&src)
+        .map(Some)
+        .map_err(|err| D::Error::custom(format!("{:#}", err)))
+}
+
+/// Deserialize a list of dates with format `YYYY-MM-DD`.
+fn dates<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Date>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|src| parse_day(src).map_err(|err| D::Error::custom(format!("{:#}", err))))
+        .collect()
+}
+
+impl Config {
+    /// Load the configuration from a file, if it exists.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(path).context("Could not read config file")?;
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Could not parse config file {}", path.display()))?;
+        // Paths are relative to the config file
+        if let Some(dir) = path.parent() {
+            for file in [&mut config.holidays.file, &mut config.storage.file]
+                .into_iter()
+                .flatten()
+            {
+                *file = dir.join(&file);
+            }
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,76 @@
+//! Previews of changes to the tracking file.
+//!
+//! Commands that rewrite many entries at once show a unified diff of the
+//! file's rows, and only write the changes back once they're confirmed.
+
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use similar::TextDiff;
+
+use crate::table::Color;
+use crate::Entry;
+use crate::{prompt, storage};
+
+/// Lines of context around each change.
+const CONTEXT: usize = 1;
+
+/// A unified diff between two versions of a tracking file.
+pub fn unified(path: &Path, old: &[Entry], new: &[Entry], colored: bool) -> Result<String> {
+    let old = storage::to_string(old)?;
+    let new = storage::to_string(new)?;
+    let name = path.display().to_string();
+    let diff = TextDiff::from_lines(&old, &new);
+    let diff = diff
+        .unified_diff()
+        .context_radius(CONTEXT)
+        .header(&name, &name)
+        .to_string();
+    if !colored {
+        return Ok(diff);
+    }
+    Ok(diff
+        .lines()
+        .map(|line| {
+            let color = if ["---", "+++", "@@"].iter().any(|p| line.starts_with(p)) {
+                Some(Color::Bold)
+            } else if line.starts_with('+') {
+                Some(Color::Added)
+            } else if line.starts_with('-') {
+                Some(Color::Removed)
+            } else {
+                None
+            };
+            let line = color.map_or_else(|| line.to_owned(), |color| color.paint(line));
+            line + "\n"
+        })
+        .collect())
+}
+
+/// Show the changes to the tracking file, and ask whether to write them.
+///
+/// Returns whether the changes were written back; `yes` skips the question.
+pub fn confirm(
+    path: &Path,
+    old: &[Entry],
+    new: &[Entry],
+    yes: bool,
+    colored: bool,
+) -> Result<bool> {
+    print!("{}", unified(path, old, new, colored)?);
+    if !yes {
+        if !io::stdin().is_terminal() {
+            bail!("Not writing changes without confirmation, pass --yes to write them anyway");
+        }
+        if !prompt::ask(
+            &format!("Write these changes to '{}'?", path.display()),
+            false,
+        )? {
+            eprintln!("Changes discarded.");
+            return Ok(false);
+        }
+    }
+    storage::write_back(path, new)?;
+    Ok(true)
+}
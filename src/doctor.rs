@@ -0,0 +1,124 @@
+//! Checks of the setup, for `temps doctor`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use time::{Duration, OffsetDateTime};
+
+use crate::{show, storage, MAX_DURATION};
+
+/// Check the tracking file and the configuration, and report what's wrong.
+pub fn run(path: &Path, config: &Path) -> Result<()> {
+    let mut problems = 0;
+    let mut problem = |message: String| {
+        println!("problem  {}", message);
+        problems += 1;
+    };
+
+    if !path.exists() {
+        println!(
+            "ok       {} doesn't exist yet, `temps start` creates it",
+            path.display()
+        );
+    } else {
+        match storage::read_numbered(path) {
+            Ok(entries) => {
+                println!("ok       {} entries in {}", entries.len(), path.display());
+                let now = OffsetDateTime::now_local()?;
+                let wrong = (0..entries.len())
+                    .filter(|&i| {
+                        show::warnings(&entries, i, now).is_ok_and(|warnings| !warnings.is_empty())
+                    })
+                    .map(|i| (i + 1).to_string())
+                    .collect::<Vec<_>>();
+                if !wrong.is_empty() {
+                    problem(format!(
+                        "{} entries look wrong, see `temps show` for entries {}",
+                        wrong.len(),
+                        wrong.join(", ")
+                    ));
+                }
+                // These throw totals off, so they're worth pointing out on their own
+                let durations = entries
+                    .iter()
+                    .map(|(_, entry)| entry.end.unwrap_or(now) - entry.start);
+                let backwards = numbers(durations.clone(), |duration| duration.is_negative());
+                if !backwards.is_empty() {
+                    problem(format!(
+                        "{} entries end before they start and count for nothing, \
+                         see `temps show` for entries {}",
+                        backwards.len(),
+                        backwards.join(", ")
+                    ));
+                }
+                let endless = numbers(durations, |duration| *duration > MAX_DURATION);
+                if !endless.is_empty() {
+                    problem(format!(
+                        "{} entries last more than {} hours, see `temps show` for entries {}",
+                        endless.len(),
+                        MAX_DURATION.whole_hours(),
+                        endless.join(", ")
+                    ));
+                }
+            }
+            Err(err) => problem(format!("Could not read {}: {:#}", path.display(), err)),
+        }
+
+        if storage::check_writable(path).is_err() {
+            println!(
+                "note     {} is read-only, commands that change it will fail",
+                path.display()
+            );
+        }
+        if let Some(mode) = shared_mode(path)? {
+            problem(format!(
+                "{} can be changed by other users (mode {:o}), fix it with `chmod go-w {}`",
+                path.display(),
+                mode,
+                path.display()
+            ));
+        }
+    }
+
+    if config.exists() {
+        println!("ok       Configuration read from {}", config.display());
+    } else {
+        println!(
+            "ok       No configuration at {}, using the defaults",
+            config.display()
+        );
+    }
+
+    if problems > 0 {
+        bail!("Found {} problem(s)", problems);
+    }
+    println!("No problems found.");
+    Ok(())
+}
+
+/// Numbers of the entries (from 1) whose duration is picked by `pick`.
+fn numbers(
+    durations: impl Iterator<Item = Duration>,
+    pick: impl Fn(&Duration) -> bool,
+) -> Vec<String> {
+    durations
+        .enumerate()
+        .filter(|(_, duration)| pick(duration))
+        .map(|(i, _)| (i + 1).to_string())
+        .collect()
+}
+
+/// The permissions of a file, if it's writable by its group or everyone.
+#[cfg(unix)]
+fn shared_mode(path: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+    Ok(Some(mode).filter(|mode| mode & 0o022 != 0))
+}
+
+#[cfg(not(unix))]
+fn shared_mode(_path: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
@@ -0,0 +1,248 @@
+//! Editing entries in a friendlier format than the tracking file.
+//!
+//! Entries are converted to TOML or YAML in a temporary file, with plain
+//! dates and times instead of RFC3339 timestamps.  Once the editor exits, the
+//! file is checked and converted back, or opened again if it's invalid.
+
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::{self, Command};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::parse::parse_datetime;
+use crate::{diff, locked, prompt, storage, Entry, Kind, Note};
+
+/// Format in which entries are edited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EditFormat {
+    /// The tracking file itself
+    #[default]
+    Tsv,
+    /// A TOML document, with an `[[entries]]` table per entry
+    Toml,
+    /// A YAML document, with a list of entries
+    Yaml,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    #[serde(default)]
+    entries: Vec<Editable>,
+}
+
+/// An entry, as it's edited.
+///
+/// Times are local (`YYYY-MM-DD HH:MM:SS`) unless they were recorded with
+/// another UTC offset, in which case they're RFC3339 timestamps.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Editable {
+    project: String,
+    start: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default, skip_serializing_if = "is_work")]
+    kind: Kind,
+    #[serde(default, skip_serializing_if = "is_false")]
+    locked: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<EditableNote>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditableNote {
+    at: String,
+    text: String,
+}
+
+fn is_work(kind: &Kind) -> bool {
+    *kind == Kind::Work
+}
+
+fn is_false(flag: &bool) -> bool {
+    !flag
+}
+
+/// Edit the entries of a tracking file in another format, with an editor.
+///
+/// The changes are shown as a diff of the tracking file, and only written
+/// back once confirmed (or right away, with `yes`).  Locked entries can only
+/// be changed with `force`.
+pub fn edit(
+    path: &Path,
+    format: EditFormat,
+    editor: &str,
+    yes: bool,
+    force: bool,
+    colored: bool,
+) -> Result<()> {
+    let local = UtcOffset::current_local_offset()?;
+    let old = storage::read_all(path)?;
+    let document = Document {
+        entries: old
+            .iter()
+            .cloned()
+            .map(|entry| editable(entry, local))
+            .collect::<Result<_>>()?,
+    };
+    let (contents, extension) = match format {
+        EditFormat::Toml => (toml::to_string(&document)?, "toml"),
+        EditFormat::Yaml => (serde_norway::to_string(&document)?, "yaml"),
+        EditFormat::Tsv => unreachable!("the tracking file is edited directly"),
+    };
+
+    let temporary = env::temp_dir().join(format!("temps-{}.{}", process::id(), extension));
+    fs::write(&temporary, &contents).context("Could not write temporary file")?;
+    let result = edit_until_valid(&temporary, &contents, format, editor, |entries| {
+        locked::check(locked::changed(&old, entries), force)
+    });
+    let _ = fs::remove_file(&temporary);
+
+    match result? {
+        Some(entries) => {
+            diff::confirm(path, &old, &entries, yes, colored)?;
+            Ok(())
+        }
+        None => {
+            println!("No changes.");
+            Ok(())
+        }
+    }
+}
+
+/// Run the editor until the file is valid and the edited entries pass
+/// `check`, and return them (or nothing if the file wasn't changed).
+fn edit_until_valid(
+    temporary: &Path,
+    original: &str,
+    format: EditFormat,
+    editor: &str,
+    check: impl Fn(&[Entry]) -> Result<()>,
+) -> Result<Option<Vec<Entry>>> {
+    loop {
+        let status = Command::new(editor)
+            .arg(temporary)
+            .status()
+            .with_context(|| format!("Could not run editor '{}'", editor))?;
+        if !status.success() {
+            bail!("Editor '{}' failed ({}), changes discarded", editor, status);
+        }
+
+        let edited = fs::read_to_string(temporary).context("Could not read temporary file")?;
+        if edited == original {
+            return Ok(None);
+        }
+        match parse(&edited, format).and_then(|entries| check(&entries).map(|()| entries)) {
+            Ok(entries) => return Ok(Some(entries)),
+            Err(err) => {
+                eprintln!("Error: {:#}", err);
+                // Without a terminal, the same file would be edited forever
+                if !io::stdin().is_terminal() || !prompt::ask("Edit again?", true)? {
+                    bail!("Changes discarded");
+                }
+            }
+        }
+    }
+}
+
+/// Convert back and check edited entries.
+fn parse(contents: &str, format: EditFormat) -> Result<Vec<Entry>> {
+    let document: Document = match format {
+        EditFormat::Toml => toml::from_str(contents)?,
+        EditFormat::Yaml => serde_norway::from_str(contents)?,
+        EditFormat::Tsv => unreachable!("the tracking file is edited directly"),
+    };
+    let count = document.entries.len();
+    document
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, editable)| {
+            let entry = entry(editable)?;
+            if entry.end.is_some_and(|end| end < entry.start) {
+                bail!("ends before it starts");
+            }
+            if entry.end.is_none() && i + 1 < count {
+                bail!("only the last entry may be ongoing (without an end)");
+            }
+            Ok(entry)
+        })
+        .enumerate()
+        .map(|(i, entry)| entry.with_context(|| format!("Invalid entry #{}", i + 1)))
+        .collect()
+}
+
+fn editable(entry: Entry, local: UtcOffset) -> Result<Editable> {
+    Ok(Editable {
+        project: entry.project,
+        start: format_datetime(entry.start, local)?,
+        end: entry
+            .end
+            .map(|end| format_datetime(end, local))
+            .transpose()?,
+        tags: entry.tags,
+        context: entry.context,
+        source: entry.source,
+        kind: entry.kind,
+        locked: entry.locked,
+        notes: entry
+            .notes
+            .into_iter()
+            .map(|note| {
+                Ok(EditableNote {
+                    at: format_datetime(note.at, local)?,
+                    text: note.text,
+                })
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn entry(editable: Editable) -> Result<Entry> {
+    Ok(Entry {
+        project: editable.project,
+        start: parse_datetime(&editable.start)?,
+        end: editable.end.as_deref().map(parse_datetime).transpose()?,
+        tags: editable.tags,
+        notes: editable
+            .notes
+            .into_iter()
+            .map(|note| {
+                Ok(Note {
+                    at: parse_datetime(&note.at)?,
+                    text: note.text,
+                })
+            })
+            .collect::<Result<_>>()?,
+        kind: editable.kind,
+        context: editable.context,
+        source: editable.source,
+        locked: editable.locked,
+    })
+}
+
+/// Write a date and time, leaving out the UTC offset if it's the local one.
+fn format_datetime(datetime: OffsetDateTime, local: UtcOffset) -> Result<String> {
+    Ok(if datetime.offset() == local {
+        datetime.format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))?
+    } else {
+        datetime.format(&Rfc3339)?
+    })
+}
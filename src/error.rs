@@ -0,0 +1,40 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Errors that scripts may want to tell apart, through the exit code.
+///
+/// Any other error is a user error, reported with exit code 1.
+#[derive(Debug)]
+pub enum Failure {
+    /// The tracking file could not be read or written (exit code 2).
+    DataFile(&'static str),
+    /// There is no ongoing entry to report on or stop (exit code 3).
+    NoOngoingEntry,
+    /// The tracking file can't be changed, because of `--read-only` or its
+    /// permissions (exit code 4).
+    ReadOnly(String),
+}
+
+impl Failure {
+    pub const USER_ERROR: u8 = 1;
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Failure::DataFile(_) => ExitCode::from(2),
+            Failure::NoOngoingEntry => ExitCode::from(3),
+            Failure::ReadOnly(_) => ExitCode::from(4),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::DataFile(message) => write!(f, "{}", message),
+            Failure::NoOngoingEntry => write!(f, "No ongoing entry"),
+            Failure::ReadOnly(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
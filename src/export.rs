@@ -0,0 +1,161 @@
+//! Machine-readable reports, as CSV or JSON.
+//!
+//! Each report is a list of records with the same fields, written as CSV rows
+//! (with a header) or as a JSON array of objects.  Durations are in seconds.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use time::Date;
+
+use crate::summary::{BalanceWeek, DailyTotals};
+use crate::Format;
+
+/// Time tracked on a project during a period, e.g. a day or a month.
+#[derive(Serialize)]
+pub struct Total<'a> {
+    pub period: String,
+    pub project: &'a str,
+    pub seconds: i64,
+}
+
+/// Time tracked during a calendar week, against the target.
+#[derive(Serialize)]
+pub struct Week {
+    pub week: String,
+    pub days_off: u32,
+    pub tracked: i64,
+    pub target: i64,
+    pub difference: i64,
+    pub balance: i64,
+}
+
+/// Sum up daily totals by period, leaving out the days without one.
+///
+/// Periods are sorted by their first day, and projects by name.
+pub fn totals(totals: &DailyTotals, period: impl Fn(Date) -> Option<String>) -> Vec<Total<'_>> {
+    let mut summary = BTreeMap::<(Date, &str), (String, i64)>::new();
+    let mut firsts = BTreeMap::<String, Date>::new();
+    for (day, projects) in totals {
+        let Some(period) = period(*day) else {
+            continue;
+        };
+        let first = *firsts.entry(period.clone()).or_insert(*day);
+        for (project, duration) in projects {
+            summary
+                .entry((first, project))
+                .or_insert_with(|| (period.clone(), 0))
+                .1 += duration.whole_seconds();
+        }
+    }
+    summary
+        .into_iter()
+        .map(|((_, project), (period, seconds))| Total {
+            period,
+            project,
+            seconds,
+        })
+        .collect()
+}
+
+/// Describe each week of a balance, with the balance up to that week.
+pub fn balance(weeks: &[BalanceWeek]) -> Vec<Week> {
+    let mut balance = 0;
+    weeks
+        .iter()
+        .map(|week| {
+            let (year, number, _) = week.monday.to_iso_week_date();
+            let difference = (week.tracked - week.target).whole_seconds();
+            balance += difference;
+            Week {
+                week: format!("{}-W{:02}", year, number),
+                days_off: week.days_off,
+                tracked: week.tracked.whole_seconds(),
+                target: week.target.whole_seconds(),
+                difference,
+                balance,
+            }
+        })
+        .collect()
+}
+
+/// Write records in a machine-readable format.
+pub fn write<T: Serialize>(records: &[T], format: Format, output: &Output) -> Result<()> {
+    let mut contents = vec![];
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut contents);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut contents, records)?;
+            contents.push(b'\n');
+        }
+        Format::Text | Format::Markdown => unreachable!("not a machine-readable format"),
+    }
+    output.write(&contents)
+}
+
+/// Where a report goes: stdout, or a file.
+#[derive(Debug, Default, clap::Args)]
+pub struct Output {
+    #[clap(
+        long,
+        short,
+        value_name = "PATH",
+        help = "Write the report to a file instead of stdout (CSV and JSON only)"
+    )]
+    pub output: Option<PathBuf>,
+    #[clap(
+        long,
+        requires = "output",
+        help = "Overwrite the output file if it exists"
+    )]
+    pub force: bool,
+}
+
+impl Output {
+    /// Fail unless the report can be written to the output.
+    ///
+    /// Only machine-readable reports can be written to a file, since the
+    /// others are mixed with status lines.
+    pub fn check(&self, machine_readable: bool) -> Result<()> {
+        if self.output.is_some() && !machine_readable {
+            bail!("Only CSV and JSON reports can be written to a file, see `--format`");
+        }
+        Ok(())
+    }
+
+    /// Write a report to the output.
+    ///
+    /// An existing file is only overwritten with `--force`, and it's replaced
+    /// at once, so that it's never left half-written.
+    pub fn write(&self, contents: &[u8]) -> Result<()> {
+        let Some(path) = &self.output else {
+            io::stdout().write_all(contents)?;
+            return Ok(());
+        };
+        if path.exists() && !self.force {
+            bail!(
+                "'{}' already exists, pass --force to overwrite it",
+                path.display()
+            );
+        }
+        let mut temporary = OsString::from(path.as_os_str());
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        fs::write(&temporary, contents)
+            .with_context(|| format!("Could not write '{}'", temporary.display()))?;
+        fs::rename(&temporary, path)
+            .with_context(|| format!("Could not write '{}'", path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Recording the recurring blocks of the configuration, e.g. daily meetings.
+//!
+//! Blocks are only recorded once they're over, and never on holidays, days
+//! off, or over time that's already tracked.  Running `temps fill` again on
+//! the same day adds nothing.
+
+use std::path::Path;
+
+use anyhow::Result;
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::config::Config;
+use crate::{assume_local, diff, locale, storage, Entry, Kind};
+
+/// Record the recurring blocks of a day, after confirmation.
+///
+/// Returns whether the tracking file was changed.
+pub fn fill(
+    path: &Path,
+    config: &Config,
+    date: Date,
+    midnight_offset: Duration,
+    dry_run: bool,
+    yes: bool,
+    colored: bool,
+) -> Result<bool> {
+    let day = locale::date(date);
+    if config.holidays.days()?.contains(&date) {
+        eprintln!("{} is a holiday, nothing to fill.", day);
+        return Ok(false);
+    }
+    let old = storage::read_all(path)?;
+    if let Some(off) = old
+        .iter()
+        .find(|entry| entry.kind == Kind::DayOff && (entry.start - midnight_offset).date() == date)
+    {
+        eprintln!("{} is a day off ({}), nothing to fill.", day, off.project);
+        return Ok(false);
+    }
+
+    let now = OffsetDateTime::now_local()?;
+    // The date's own offset, in case daylight saving time changed since
+    let at = |time| assume_local(date.with_time(Time::MIDNIGHT) + time);
+    let mut added = vec![];
+    for block in &config.recurring {
+        if !block.days.contains(date.weekday()) {
+            continue;
+        }
+        let (start, end) = (at(block.time.0)?, at(block.time.1)?);
+        if old
+            .iter()
+            .any(|entry| entry.project == block.project && entry.start == start)
+        {
+            // Filled already
+            continue;
+        }
+        if end > now {
+            eprintln!("Skipped '{}', it isn't over yet.", block.project);
+            continue;
+        }
+        if let Some(overlap) = old.iter().find(|entry| {
+            entry.kind != Kind::Unscheduled && entry.start < end && entry.end.unwrap_or(now) > start
+        }) {
+            eprintln!(
+                "Skipped '{}', it overlaps '{}' which is already tracked.",
+                block.project, overlap.project
+            );
+            continue;
+        }
+        added.push(Entry {
+            project: block.project.clone(),
+            start,
+            end: Some(end),
+            tags: block.tags.clone(),
+            notes: vec![],
+            kind: Kind::Work,
+            context: None,
+            source: config.storage.source(),
+            locked: false,
+        });
+    }
+
+    if added.is_empty() {
+        eprintln!("Nothing to fill.");
+        return Ok(false);
+    }
+    let count = added.len();
+    let mut entries = old.clone();
+    entries.extend(added);
+    entries.sort_by_key(|entry| entry.start);
+
+    if dry_run {
+        print!("{}", diff::unified(path, &old, &entries, colored)?);
+        eprintln!("Would add {} entries (dry run).", count);
+        return Ok(false);
+    }
+    let written = diff::confirm(path, &old, &entries, yes, colored)?;
+    if written {
+        eprintln!("Added {} entries.", count);
+    }
+    Ok(written)
+}
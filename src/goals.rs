@@ -0,0 +1,82 @@
+//! Daily goals, e.g. spending six hours on a project.
+//!
+//! Goals are checked after every command that changes the tracking file, and
+//! each goal that's been reached
+//! is reported once per day.  The goals already reported today are kept in
+//! the state directory (see [`crate::paths`]).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use time::macros::format_description;
+use time::{Duration, OffsetDateTime, Time};
+
+use crate::config::Goal;
+use crate::{duration_to_string, paths, storage, summary};
+
+/// Report the goals that were reached today, unless they've been reported already.
+///
+/// Failing to do so doesn't fail the command, which already did its job.
+pub fn check(path: &Path, goals: &BTreeMap<String, Goal>, midnight_offset: Duration) {
+    if let Err(err) = try_check(path, goals, midnight_offset) {
+        eprintln!("Warning: Could not check goals: {:#}", err);
+    }
+}
+
+fn try_check(path: &Path, goals: &BTreeMap<String, Goal>, midnight_offset: Duration) -> Result<()> {
+    if goals.is_empty() || !path.exists() {
+        return Ok(());
+    }
+
+    let now = OffsetDateTime::now_local()?;
+    let today = (now - midnight_offset).date();
+    let since = today.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + midnight_offset;
+    let entries = storage::read_since(path, since)?;
+    let totals = summary::daily_totals(&entries, midnight_offset, now);
+    let Some(totals) = totals.get(&today) else {
+        return Ok(());
+    };
+
+    let today = today.format(format_description!("[year]-[month]-[day]"))?;
+    let mut reported = read_reported(path, &today)?;
+    let mut changed = false;
+    for (project, Goal(target)) in goals {
+        if totals.get(project).is_some_and(|total| total >= target) && !reported.contains(project) {
+            eprintln!(
+                "Goal: '{}' reached {} today.",
+                project,
+                duration_to_string(*target)?
+            );
+            reported.push(project.clone());
+            changed = true;
+        }
+    }
+
+    if changed {
+        let mut contents = String::new();
+        for project in &reported {
+            contents.push_str(&format!("{}\t{}\n", today, project));
+        }
+        let reported = paths::reported_goals(path);
+        paths::create_parent(&reported).context("Could not create state directory")?;
+        fs::write(reported, contents).context("Could not write reported goals")?;
+    }
+    Ok(())
+}
+
+/// Read the projects whose goals were already reported on a given day.
+fn read_reported(path: &Path, day: &str) -> Result<Vec<String>> {
+    let reported = paths::reported_goals(path);
+    if !reported.exists() {
+        return Ok(vec![]);
+    }
+    let contents = fs::read_to_string(reported).context("Could not read reported goals")?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(date, _)| *date == day)
+        .map(|(_, project)| project.to_owned())
+        .collect())
+}
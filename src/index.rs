@@ -0,0 +1,309 @@
+//! Optional index of a tracking file, mapping dates to byte offsets.
+//!
+//! The index lives in the cache directory (see [`crate::paths`]).  It is
+//! refreshed incrementally: only the records added since the last refresh are
+//! scanned.  Commands that rewrite the whole file remove it, so that it gets
+//! rebuilt from scratch the next time it's needed, and so does any other change
+//! to the part of the file that was indexed, e.g. by hand or by syncing it.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, StringRecord};
+use time::macros::format_description;
+use time::Date;
+
+use crate::error::Failure;
+use crate::{paths, Entry};
+
+const VERSION: &str = "temps-index-v2";
+
+/// How much of the file, right before the last indexed record, the index
+/// checks to tell whether it's still in sync.
+const FINGERPRINT_LEN: u64 = 4096;
+
+#[derive(Debug)]
+pub struct Index {
+    /// Offset of the last indexed record.
+    ///
+    /// That record is indexed again on every refresh, since `stop` rewrites it.
+    indexed: u64,
+    /// Hash of the bytes that precede the last indexed record, up to
+    /// [`FINGERPRINT_LEN`] of them.
+    fingerprint: u64,
+    /// Offset of the first record starting on each date, in order.
+    dates: Vec<(Date, u64)>,
+}
+
+/// Remove the index of a tracking file, if there is one.
+pub fn invalidate(path: &Path) -> Result<()> {
+    let index = paths::index(path);
+    if index.exists() {
+        fs::remove_file(index).context("Could not remove index file")?;
+    }
+    Ok(())
+}
+
+impl Index {
+    /// Load the index of a tracking file, updating it with new records.
+    pub fn refresh(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).context(Failure::DataFile("Could not open tracking file"))?;
+        let len = file
+            .metadata()
+            .context(Failure::DataFile("Could not read tracking file"))?
+            .len();
+
+        // Start over if the index is missing, unreadable, or out of sync
+        let mut index = match Self::load(&paths::index(path)) {
+            Ok(Some(index)) if index.is_valid(&mut file, len)? => index,
+            _ => Self {
+                indexed: 0,
+                fingerprint: 0,
+                dates: vec![],
+            },
+        };
+        index.update(&mut file)?;
+        index.save(&paths::index(path))?;
+        Ok(index)
+    }
+
+    /// Index the records from the last indexed one onwards.
+    fn update(&mut self, file: &mut File) -> Result<()> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(BufReader::new(&mut *file));
+        let headers = reader
+            .headers()
+            .context(Failure::DataFile("Could not read entries"))?
+            .clone();
+        if self.indexed == 0 {
+            self.indexed = reader.position().byte();
+        }
+
+        // Scan the records from the last indexed one onwards
+        let start = self.indexed;
+        self.dates.retain(|&(_, offset)| offset < start);
+        let file = reader.into_inner().into_inner();
+        file.seek(SeekFrom::Start(start))
+            .context(Failure::DataFile("Could not read tracking file"))?;
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_reader(BufReader::new(&mut *file));
+        let mut record = StringRecord::new();
+        loop {
+            let offset = start + reader.position().byte();
+            if !reader
+                .read_record(&mut record)
+                .context(Failure::DataFile("Could not read entries"))?
+            {
+                break;
+            }
+            let entry: Entry = record
+                .deserialize(Some(&headers))
+                .context(Failure::DataFile("Could not read entries"))?;
+            let date = entry.start.date();
+            if self.dates.last().is_none_or(|&(last, _)| date > last) {
+                self.dates.push((date, offset));
+            }
+            self.indexed = offset;
+        }
+        drop(reader);
+        self.fingerprint = fingerprint(file, self.indexed)?;
+        Ok(())
+    }
+
+    /// Offset of the first record starting on or after a date, if any.
+    pub fn offset(&self, date: Date) -> Option<u64> {
+        let i = self.dates.partition_point(|&(d, _)| d < date);
+        self.dates.get(i).map(|&(_, offset)| offset)
+    }
+
+    /// Check that what precedes the last indexed record hasn't changed since
+    /// it was indexed.
+    fn is_valid(&self, file: &mut File, len: u64) -> Result<bool> {
+        if self.indexed == 0 || self.indexed > len {
+            return Ok(false);
+        }
+        let fingerprint = fingerprint(file, self.indexed)?;
+        file.rewind()
+            .context(Failure::DataFile("Could not read tracking file"))?;
+        Ok(fingerprint == self.fingerprint)
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path).context("Could not read index file")?;
+        let mut lines = contents.lines();
+        let (indexed, fingerprint) = match lines
+            .next()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .as_deref()
+        {
+            Some([VERSION, indexed, fingerprint]) => (
+                indexed.parse().context("Invalid index file")?,
+                u64::from_str_radix(fingerprint, 16).context("Invalid index file")?,
+            ),
+            _ => return Ok(None),
+        };
+        let dates = lines
+            .map(|line| {
+                let (date, offset) = line.split_once('\t').context("Invalid index file")?;
+                Ok((
+                    Date::parse(date, format_description!("[year]-[month]-[day]"))?,
+                    offset.parse()?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self {
+            indexed,
+            fingerprint,
+            dates,
+        }))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let mut contents = format!("{}\t{}\t{:016x}\n", VERSION, self.indexed, self.fingerprint);
+        for (date, offset) in &self.dates {
+            contents.push_str(&format!(
+                "{}\t{}\n",
+                date.format(format_description!("[year]-[month]-[day]"))?,
+                offset
+            ));
+        }
+        paths::create_parent(path).context("Could not create cache directory")?;
+        fs::write(path, contents).context("Could not write index file")
+    }
+}
+
+/// Hash of the bytes that precede an offset, up to [`FINGERPRINT_LEN`] of them.
+fn fingerprint(file: &mut File, offset: u64) -> Result<u64> {
+    let start = offset.saturating_sub(FINGERPRINT_LEN);
+    let mut bytes = vec![0; (offset - start) as usize];
+    file.seek(SeekFrom::Start(start))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    Ok(paths::fnv1a(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use time::macros::date;
+
+    use super::*;
+
+    const HEADER: &str = "project\tstart\tend\n";
+
+    fn record(project: &str, day: u8, end: bool) -> String {
+        let start = format!("2021-09-{:02}T09:00:00+02:00", day);
+        let end = if end {
+            format!("2021-09-{:02}T10:00:00+02:00", day)
+        } else {
+            String::new()
+        };
+        format!("{}\t{}\t{}\n", project, start, end)
+    }
+
+    /// A tracking file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "temps-test-{}-{}.tsv",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        fn open(&self) -> (File, u64) {
+            let file = File::open(&self.0).unwrap();
+            let len = file.metadata().unwrap().len();
+            (file, len)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn build(file: &TempFile) -> Index {
+        let mut index = Index {
+            indexed: 0,
+            fingerprint: 0,
+            dates: vec![],
+        };
+        index.update(&mut file.open().0).unwrap();
+        index
+    }
+
+    #[test]
+    fn offsets_of_dates() {
+        let records = [
+            record("a", 14, true),
+            record("b", 14, true),
+            record("c", 16, false),
+        ];
+        let contents = HEADER.to_owned() + &records.concat();
+        let file = TempFile::new("index-offsets", &contents);
+        let index = build(&file);
+
+        let first = HEADER.len() as u64;
+        let third = first + (records[0].len() + records[1].len()) as u64;
+        assert_eq!(index.indexed, third);
+        assert_eq!(index.offset(date!(2021 - 09 - 13)), Some(first));
+        assert_eq!(index.offset(date!(2021 - 09 - 14)), Some(first));
+        assert_eq!(index.offset(date!(2021 - 09 - 15)), Some(third));
+        assert_eq!(index.offset(date!(2021 - 09 - 17)), None);
+    }
+
+    #[test]
+    fn stays_valid_when_records_are_added_or_stopped() {
+        let before = HEADER.to_owned() + &record("a", 14, true) + &record("b", 15, false);
+        let file = TempFile::new("index-appended", &before);
+        let index = build(&file);
+
+        let after = HEADER.to_owned()
+            + &record("a", 14, true)
+            + &record("b", 15, true)
+            + &record("c", 16, false);
+        fs::write(&file.0, after).unwrap();
+        let (mut reader, len) = file.open();
+        assert!(index.is_valid(&mut reader, len).unwrap());
+    }
+
+    #[test]
+    fn invalid_when_earlier_records_change() {
+        let before = HEADER.to_owned() + &record("a", 14, true) + &record("b", 15, false);
+        let file = TempFile::new("index-rewritten", &before);
+        let index = build(&file);
+
+        // Same length, so that the last record still starts at the same offset
+        let after = HEADER.to_owned() + &record("x", 13, true) + &record("b", 15, false);
+        fs::write(&file.0, after).unwrap();
+        let (mut reader, len) = file.open();
+        assert!(!index.is_valid(&mut reader, len).unwrap());
+
+        OpenOptions::new()
+            .append(true)
+            .open(&file.0)
+            .unwrap()
+            .write_all(record("c", 16, false).as_bytes())
+            .unwrap();
+        let (mut reader, len) = file.open();
+        assert!(!index.is_valid(&mut reader, len).unwrap());
+    }
+}
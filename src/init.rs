@@ -0,0 +1,103 @@
+//! First-run setup: the configuration file, where entries are kept, and
+//! shell completions.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use directories::BaseDirs;
+
+use crate::locale::Locale;
+use crate::{completions, paths, prompt};
+
+/// Ask how to set things up, then write the configuration file.
+///
+/// `temps_file` is where entries would be kept without a configuration.
+pub fn init(config: &Path, temps_file: &Path, command: clap::Command) -> Result<()> {
+    if config.exists()
+        && !prompt::ask(
+            &format!("'{}' already exists, overwrite it?", config.display()),
+            false,
+        )?
+    {
+        eprintln!("Nothing changed.");
+        return Ok(());
+    }
+
+    let file = prompt::read(
+        "Where should entries be kept?",
+        Some(&temps_file.display().to_string()),
+    )?;
+    let file = expand_home(&file);
+    let projects = prompt::read(
+        "Projects to start with, separated by commas (optional):",
+        None,
+    )?;
+    let projects = projects
+        .split(',')
+        .map(str::trim)
+        .filter(|project| !project.is_empty())
+        .collect::<Vec<_>>();
+
+    let completions = match completions::current_shell() {
+        Some(shell) => {
+            if prompt::ask(&format!("Install completions for {}?", shell), true)? {
+                Some(completions::install(shell, command)?)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+
+    paths::create_parent(config).context("Could not create configuration directory")?;
+    fs::write(config, contents(&file, &projects)).context("Could not write config file")?;
+    paths::create_parent(&file).context("Could not create data directory")?;
+
+    println!("Wrote {}.", config.display());
+    println!("Entries will be kept in {}.", file.display());
+    if let Some(completions) = completions {
+        completions::print_installed(&completions);
+    }
+    Ok(())
+}
+
+/// The configuration file written by `init`, with comments.
+fn contents(file: &Path, projects: &[&str]) -> String {
+    let string = |s: &str| toml::Value::String(s.to_owned()).to_string();
+    let mut contents = String::from(
+        "# Written by `temps init`, see the README for everything that can be set here\n",
+    );
+    if !projects.is_empty() {
+        contents.push_str(&format!(
+            "\n# Projects listed by `temps projects`, even before anything was tracked on them\nprojects = [{}]\n",
+            projects
+                .iter()
+                .map(|project| string(project))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    contents.push_str(&format!(
+        "\n[storage]\n# Where entries are kept (TEMPS_FILE and --temps-file take precedence)\nfile = {}\n",
+        string(&file.display().to_string())
+    ));
+    let locale = Locale::detect();
+    contents.push_str(&format!(
+        "\n[display]\n# Detected from the locale, uncomment to change how dates and times look\n# clock = {}\n# date-order = {}\n# date-separator = {}\n# decimal-separator = {}\n# thousands-separator = {}\n",
+        string(locale.clock.name()),
+        string(locale.date_order.name()),
+        string(&locale.date_separator),
+        string(&locale.decimal_separator),
+        string(&locale.thousands_separator)
+    ));
+    contents
+}
+
+/// Expand a leading `~` to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), BaseDirs::new()) {
+        (Some(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => PathBuf::from(path),
+    }
+}
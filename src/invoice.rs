@@ -0,0 +1,91 @@
+//! Invoices, numbered and recorded in a ledger, for `temps invoice`.
+//!
+//! Each invoice gets the number after the last one of the ledger, which is
+//! kept next to the tracking file (see [`paths::invoices`]).  The entries it
+//! covers are tagged with it (e.g. `invoice:12`), so that they aren't invoiced
+//! twice by mistake, and can be listed with `temps list --tag invoice:12`.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{paths, Entry};
+
+/// Prefix of the tag of invoiced entries, followed by the invoice's number.
+const TAG_PREFIX: &str = "invoice:";
+
+/// An invoice, as recorded in the ledger.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub number: u32,
+    /// Day the invoice was written, as `YYYY-MM-DD`.
+    pub date: String,
+    pub client: String,
+    /// First and last days invoiced, as `YYYY-MM-DD`.
+    pub from: String,
+    pub to: String,
+    pub hours: f64,
+    /// Missing if no rate is configured for the client.
+    pub amount: Option<f64>,
+    pub currency: Option<String>,
+}
+
+/// Read the invoices recorded for a tracking file, oldest first.
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+    let ledger = paths::invoices(path);
+    if !ledger.exists() {
+        return Ok(vec![]);
+    }
+    ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(&ledger)
+        .context("Could not open invoice ledger")?
+        .into_deserialize()
+        .collect::<Result<_, _>>()
+        .context("Could not read invoice ledger")
+}
+
+/// Number of the next invoice.
+pub fn next_number(path: &Path) -> Result<u32> {
+    let last = read(path)?.iter().map(|record| record.number).max();
+    Ok(last.map_or(1, |last| last + 1))
+}
+
+/// Add an invoice to the ledger.
+pub fn record(path: &Path, record: &Record) -> Result<()> {
+    let ledger = paths::invoices(path);
+    let new = !ledger.exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&ledger)
+        .context("Could not open invoice ledger")?;
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(new)
+        .from_writer(file);
+    writer
+        .serialize(record)
+        .context("Could not write to invoice ledger")?;
+    writer
+        .flush()
+        .context("Could not write to invoice ledger")?;
+    Ok(())
+}
+
+/// Number of the invoice that covers an entry, if any.
+pub fn invoiced(entry: &Entry) -> Option<u32> {
+    entry
+        .tags
+        .iter()
+        .find_map(|tag| tag.strip_prefix(TAG_PREFIX)?.parse().ok())
+}
+
+/// Tag an entry with the invoice that covers it, instead of any earlier one.
+pub fn mark(entry: &mut Entry, number: u32) {
+    entry.tags.retain(|tag| !tag.starts_with(TAG_PREFIX));
+    entry.add_tag(&format!("{}{}", TAG_PREFIX, number));
+}
@@ -0,0 +1,344 @@
+//! How dates, times and numbers are displayed.
+//!
+//! By default, this follows the locale of the environment (`LC_ALL`, `LC_TIME`
+//! or `LANG`), e.g. 12-hour times and month/day dates with `en_US`, or
+//! day/month dates with `en_GB`, and numbers like `1.234,50` with `de_DE`.
+//! Without a locale, or with the `C` one, dates are `YYYY-MM-DD` and times are
+//! on 24 hours.  The `[display]` section of the
+//! config overrides what's detected.
+//!
+//! Only what's meant for people changes: the tracking file, timestamps, CSV
+//! and JSON keep their formats.  Names of days and months stay in English.
+
+use std::env;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, Time};
+
+use crate::config::DisplayConfig;
+
+/// How times are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Clock {
+    /// `14:05`
+    #[serde(rename = "24h")]
+    H24,
+    /// `2:05 pm`
+    #[serde(rename = "12h")]
+    H12,
+}
+
+/// Order of the year, month and day in dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateOrder {
+    /// `2021-09-17`
+    Ymd,
+    /// `17/09/2021`
+    Dmy,
+    /// `09/17/2021`
+    Mdy,
+}
+
+impl Clock {
+    /// As written in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Clock::H24 => "24h",
+            Clock::H12 => "12h",
+        }
+    }
+}
+
+impl DateOrder {
+    /// As written in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            DateOrder::Ymd => "ymd",
+            DateOrder::Dmy => "dmy",
+            DateOrder::Mdy => "mdy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub clock: Clock,
+    pub date_order: DateOrder,
+    pub date_separator: String,
+    pub decimal_separator: String,
+    pub thousands_separator: String,
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Regions where times are usually on 12 hours.
+const H12_REGIONS: &[&str] = &[
+    "US", "CA", "AU", "NZ", "PH", "IN", "PK", "BD", "EG", "SA", "MY", "KR", "TW",
+];
+/// Languages and regions where dates start with the year.
+const YMD_LANGUAGES: &[&str] = &["zh", "ja", "ko", "hu", "lt", "mn", "sv"];
+const YMD_REGIONS: &[&str] = &["CN", "TW", "JP", "KR", "HU", "LT", "SE"];
+/// Regions where dates start with the month.
+const MDY_REGIONS: &[&str] = &["US", "PH", "FM", "MH", "PW"];
+/// Languages whose dates are written with dots, e.g. `17.09.2021`.
+const DOT_LANGUAGES: &[&str] = &[
+    "de", "ru", "pl", "cs", "sk", "fi", "nb", "nn", "no", "da", "tr", "uk", "ro", "hr", "sl", "sr",
+    "bg", "et", "lv", "is",
+];
+
+/// Languages whose decimal mark is a comma, e.g. `3,50`.
+const DECIMAL_COMMA_LANGUAGES: &[&str] = &[
+    "de", "fr", "es", "it", "pt", "nl", "ru", "pl", "cs", "sk", "fi", "nb", "nn", "no", "da", "sv",
+    "tr", "uk", "ro", "hr", "sl", "sr", "bg", "et", "lv", "lt", "is", "hu", "el", "id", "ca", "be",
+    "vi",
+];
+/// Languages whose thousands are separated by a space, e.g. `1 234,50`.
+const SPACE_GROUPING_LANGUAGES: &[&str] = &[
+    "fr", "ru", "pl", "cs", "sk", "fi", "nb", "nn", "no", "sv", "uk", "bg", "et", "lv", "lt", "hu",
+    "be",
+];
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            clock: Clock::H24,
+            date_order: DateOrder::Ymd,
+            date_separator: "-".to_owned(),
+            decimal_separator: ".".to_owned(),
+            thousands_separator: ",".to_owned(),
+        }
+    }
+}
+
+impl Locale {
+    /// The locale of the environment.
+    pub fn detect() -> Self {
+        Self::from_name(&variable().map(|(_, name)| name).unwrap_or_default())
+    }
+
+    /// A locale from its name, e.g. `en_US.UTF-8`.
+    fn from_name(name: &str) -> Self {
+        // Leave out the encoding and modifier
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = name.split_once('_').unwrap_or((name, ""));
+        if language.is_empty() || language == "C" || language == "POSIX" {
+            return Locale::default();
+        }
+
+        let clock = if H12_REGIONS.contains(&region) && !(language == "fr" && region == "CA") {
+            Clock::H12
+        } else {
+            Clock::H24
+        };
+        let date_order = if MDY_REGIONS.contains(&region) {
+            DateOrder::Mdy
+        } else if YMD_LANGUAGES.contains(&language) || YMD_REGIONS.contains(&region) {
+            DateOrder::Ymd
+        } else {
+            DateOrder::Dmy
+        };
+        let date_separator = match date_order {
+            DateOrder::Ymd => "-",
+            DateOrder::Dmy if DOT_LANGUAGES.contains(&language) => ".",
+            DateOrder::Dmy | DateOrder::Mdy => "/",
+        };
+        let (decimal_separator, thousands_separator) = if region == "CH" {
+            (".", "'")
+        } else if SPACE_GROUPING_LANGUAGES.contains(&language) {
+            // Not broken across lines
+            (",", "\u{a0}")
+        } else if DECIMAL_COMMA_LANGUAGES.contains(&language) {
+            (",", ".")
+        } else {
+            (".", ",")
+        };
+        Locale {
+            clock,
+            date_order,
+            date_separator: date_separator.to_owned(),
+            decimal_separator: decimal_separator.to_owned(),
+            thousands_separator: thousands_separator.to_owned(),
+        }
+    }
+
+    /// The detected locale, with what the config overrides.
+    fn with_config(mut self, config: &DisplayConfig) -> Self {
+        if let Some(clock) = config.clock {
+            self.clock = clock;
+        }
+        if let Some(order) = config.date_order {
+            if order != self.date_order {
+                self.date_separator = if order == DateOrder::Ymd { "-" } else { "/" }.to_owned();
+            }
+            self.date_order = order;
+        }
+        if let Some(separator) = &config.date_separator {
+            self.date_separator = separator.clone();
+        }
+        if let Some(separator) = &config.decimal_separator {
+            self.decimal_separator = separator.clone();
+        }
+        if let Some(separator) = &config.thousands_separator {
+            self.thousands_separator = separator.clone();
+        }
+        self
+    }
+}
+
+/// Display dates and times as configured, from now on.
+pub fn init(config: &DisplayConfig) {
+    let _ = LOCALE.set(Locale::detect().with_config(config));
+}
+
+/// The locale dates and times are displayed in.
+pub fn current() -> &'static Locale {
+    LOCALE.get_or_init(Locale::detect)
+}
+
+/// The environment variable the locale is detected from, and its value.
+pub fn variable() -> Option<(&'static str, String)> {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .into_iter()
+        .filter_map(|var| Some((var, env::var(var).ok()?)))
+        .find(|(_, value)| !value.is_empty())
+}
+
+/// A time of day, e.g. `14:05` or `2:05 pm`.
+pub fn time(time: Time) -> String {
+    let (hour, minute) = (time.hour(), time.minute());
+    match current().clock {
+        Clock::H24 => format!("{:02}:{:02}", hour, minute),
+        Clock::H12 => format!("{}:{:02} {}", hour12(hour), minute, period(hour)),
+    }
+}
+
+/// The end of a day, shown as `24:00` on 24 hours.
+pub fn end_of_day() -> String {
+    match current().clock {
+        Clock::H24 => "24:00".to_owned(),
+        Clock::H12 => time(Time::MIDNIGHT),
+    }
+}
+
+/// Width of the times written by [`time`], to align them.
+pub fn time_width() -> usize {
+    match current().clock {
+        Clock::H24 => 5,
+        Clock::H12 => 8,
+    }
+}
+
+/// A round hour, e.g. in the margin of a chart: `14:00` or `2 pm`.
+pub fn hour(time: Time) -> String {
+    match current().clock {
+        Clock::H24 => format!("{:02}:{:02}", time.hour(), time.minute()),
+        Clock::H12 if time.minute() == 0 => {
+            format!("{} {}", hour12(time.hour()), period(time.hour()))
+        }
+        Clock::H12 => format!("{}:{:02}", hour12(time.hour()), time.minute()),
+    }
+}
+
+/// A round hour in at most three characters, e.g. above a timeline: `14` or
+/// `2p`.
+pub fn short_hour(time: Time) -> String {
+    match current().clock {
+        Clock::H24 => format!("{:02}", time.hour()),
+        Clock::H12 => format!("{}{}", hour12(time.hour()), &period(time.hour())[..1]),
+    }
+}
+
+fn hour12(hour: u8) -> u8 {
+    match hour % 12 {
+        0 => 12,
+        hour => hour,
+    }
+}
+
+fn period(hour: u8) -> &'static str {
+    if hour < 12 {
+        "am"
+    } else {
+        "pm"
+    }
+}
+
+/// A date, e.g. `2021-09-17`, `17/09/2021` or `09/17/2021`.
+pub fn date(date: Date) -> String {
+    let locale = current();
+    let (year, month, day) = (date.year(), date.month() as u8, date.day());
+    let separator = &locale.date_separator;
+    match locale.date_order {
+        DateOrder::Ymd => format!("{}{sep}{:02}{sep}{:02}", year, month, day, sep = separator),
+        DateOrder::Dmy => format!("{:02}{sep}{:02}{sep}{}", day, month, year, sep = separator),
+        DateOrder::Mdy => format!("{:02}{sep}{:02}{sep}{}", month, day, year, sep = separator),
+    }
+}
+
+/// A date and time, e.g. `2021-09-17 14:05`.
+pub fn datetime(datetime: OffsetDateTime) -> String {
+    format!("{} {}", date(datetime.date()), time(datetime.time()))
+}
+
+/// A date with its day of the week, e.g. `Friday, 2021-09-17`.
+pub fn weekday_date(day: Date) -> String {
+    format!("{}, {}", day.weekday(), date(day))
+}
+
+/// A day relative to today: `today`, `yesterday`, the day of the week within
+/// the past week (e.g. `Monday`), or else the date.
+pub fn relative_day(day: Date, today: Date) -> String {
+    match (today - day).whole_days() {
+        -1 => "tomorrow".to_owned(),
+        0 => "today".to_owned(),
+        1 => "yesterday".to_owned(),
+        2..=6 => day.weekday().to_string(),
+        _ => date(day),
+    }
+}
+
+/// A day relative to today as a heading: `Today`, `Yesterday`, the day of the
+/// week within the past week, or else the date with its day of the week.
+pub fn relative_weekday_date(day: Date, today: Date) -> String {
+    match (today - day).whole_days() {
+        -1 => "Tomorrow".to_owned(),
+        0 => "Today".to_owned(),
+        1 => "Yesterday".to_owned(),
+        2..=6 => day.weekday().to_string(),
+        _ => weekday_date(day),
+    }
+}
+
+/// A day of the month, e.g. `Sep 17`, or `17 Sep` where the day comes first.
+pub fn month_day(date: Date) -> String {
+    let format = match current().date_order {
+        DateOrder::Dmy => format_description!("[day] [month repr:short]"),
+        DateOrder::Ymd | DateOrder::Mdy => format_description!("[month repr:short] [day]"),
+    };
+    date.format(format).unwrap_or_default()
+}
+
+/// A number with some decimals, e.g. `1,234.50` or `1.234,50`.
+pub fn number(value: f64, decimals: usize) -> String {
+    let locale = current();
+    let digits = format!("{:.*}", decimals, value.abs());
+    let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+    let mut number = String::new();
+    if value < 0. && digits.bytes().any(|digit| matches!(digit, b'1'..=b'9')) {
+        number.push('-');
+    }
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            number.push_str(&locale.thousands_separator);
+        }
+        number.push(digit);
+    }
+    if !fraction.is_empty() {
+        number.push_str(&locale.decimal_separator);
+        number.push_str(fraction);
+    }
+    number
+}
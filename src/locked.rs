@@ -0,0 +1,66 @@
+//! Locked entries, which were reported (submitted, approved, invoiced...) and
+//! so shouldn't change anymore.
+//!
+//! `temps lock` sets their `locked` column, and commands that rewrite existing
+//! entries (`edit`, `retag`, `start --from`, and `invoice`, which tags them)
+//! refuse to change or remove them, unless told to with `--force`.  New
+//! entries can still be added among them, and `temps prune` still removes
+//! them, since it keeps a backup.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::{locale, Entry};
+
+/// Lock (or unlock) the finished entries from before a day.  Returns how many
+/// were changed.
+pub fn set(entries: &mut [Entry], before: Date, midnight_offset: Duration, locked: bool) -> usize {
+    let mut changed = 0;
+    for entry in entries {
+        if !entry.is_ongoing()
+            && (entry.start - midnight_offset).date() < before
+            && entry.locked != locked
+        {
+            entry.locked = locked;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// The entries of `old` that were changed or removed in `new`.
+pub fn changed<'a>(old: &'a [Entry], new: &[Entry]) -> Vec<&'a Entry> {
+    let mut by_start = BTreeMap::<OffsetDateTime, Vec<&Entry>>::new();
+    for entry in new {
+        by_start.entry(entry.start).or_default().push(entry);
+    }
+    old.iter()
+        .filter(|entry| {
+            by_start
+                .get(&entry.start)
+                .is_none_or(|same_start| !same_start.contains(entry))
+        })
+        .collect()
+}
+
+/// Refuse to change or remove entries if some are locked, unless `force`.
+pub fn check<'a>(changed: impl IntoIterator<Item = &'a Entry>, force: bool) -> Result<()> {
+    if force {
+        return Ok(());
+    }
+    let locked = changed
+        .into_iter()
+        .filter(|entry| entry.locked)
+        .collect::<Vec<_>>();
+    if let (Some(first), Some(last)) = (locked.first(), locked.last()) {
+        bail!(
+            "Cannot change {} locked entries (from {} to {}), pass --force to change them anyway",
+            locked.len(),
+            locale::date(first.start.date()),
+            locale::date(last.start.date())
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Reports sent by email.
+//!
+//! Messages are handed over to a sendmail-compatible command (see
+//! [`EmailConfig`]), which takes care of actually delivering them: `temps`
+//! has no SMTP settings of its own, and never sees passwords.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use time::OffsetDateTime;
+
+use crate::config::EmailConfig;
+use crate::summary::Report;
+use crate::table;
+
+/// Send a report, as both plain text and HTML.
+pub fn send(config: &EmailConfig, to: &[String], report: &Report) -> Result<()> {
+    let message = message(config, to, report);
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&config.command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not run email command '{}'", config.command))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(message.as_bytes())
+        .context("Could not pass the message to the email command")?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("Email command '{}' failed ({})", config.command, status);
+    }
+    Ok(())
+}
+
+/// Write a MIME message with a plain text and an HTML version of a report.
+fn message(config: &EmailConfig, to: &[String], report: &Report) -> String {
+    // Nothing in the report starts with this
+    let boundary = format!("temps-{}", OffsetDateTime::now_utc().unix_timestamp_nanos());
+    let text = report.to_string();
+    let activity = report
+        .activity
+        .as_ref()
+        .map_or_else(String::new, |activity| {
+            format!("<pre>{}</pre>\n", table::escape(activity))
+        });
+    let html = format!(
+        "<html>\n<body>\n<h2>{}</h2>\n{}<p>{}</p>\n{}</body>\n</html>\n",
+        table::escape(&report.title),
+        report.table.html(),
+        table::escape(&report.footer),
+        activity
+    );
+
+    let mut message = String::new();
+    if let Some(from) = &config.from {
+        message.push_str(&format!("From: {}\r\n", from));
+    }
+    message.push_str(&format!("To: {}\r\n", to.join(", ")));
+    message.push_str(&format!("Subject: {}\r\n", encode_header(&report.title)));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!(
+        "Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n",
+        boundary
+    ));
+    for (subtype, body) in [("plain", text), ("html", html)] {
+        message.push_str(&format!("--{}\r\n", boundary));
+        message.push_str(&format!(
+            "Content-Type: text/{}; charset=utf-8\r\n",
+            subtype
+        ));
+        message.push_str("Content-Transfer-Encoding: 8bit\r\n\r\n");
+        message.push_str(&body.replace('\n', "\r\n"));
+        message.push_str("\r\n");
+    }
+    message.push_str(&format!("--{}--\r\n", boundary));
+    message
+}
+
+/// Encode a header with non-ASCII characters (e.g. dashes), as per RFC 2047.
+///
+/// Control characters are encoded too, so that a line break (e.g. in a
+/// project name) can't start another header.
+fn encode_header(header: &str) -> String {
+    if header.is_ascii() && !header.contains(|c: char| c.is_ascii_control()) {
+        return header.to_owned();
+    }
+    let mut encoded = "=?utf-8?Q?".to_owned();
+    for byte in header.bytes() {
+        match byte {
+            b' ' => encoded.push('_'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'(' | b')' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("={:02X}", byte)),
+        }
+    }
+    encoded.push_str("?=");
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_is_escaped() {
+        let mut table = table::Table::new(["Project", "Time"]);
+        table.row(["<b>", "1h 00m"]);
+        let report = Report {
+            title: "Report on <b>".to_owned(),
+            table,
+            footer: "Total on <b> & co".to_owned(),
+            activity: None,
+        };
+        let message = message(
+            &EmailConfig::default(),
+            &["me@example.com".to_owned()],
+            &report,
+        );
+        let html = &message[message.find("text/html").unwrap()..];
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("<h2>Report on &lt;b&gt;</h2>"));
+        assert!(html.contains("<td style=\"text-align: left\">&lt;b&gt;</td>"));
+        assert!(html.contains("<p>Total on &lt;b&gt; &amp; co</p>"));
+    }
+
+    #[test]
+    fn encode_ascii_header() {
+        assert_eq!(encode_header("Weekly report"), "Weekly report");
+    }
+
+    #[test]
+    fn encode_header_with_dashes_and_line_breaks() {
+        assert_eq!(
+            encode_header("Sep 13 – 19"),
+            "=?utf-8?Q?Sep_13_=E2=80=93_19?="
+        );
+        assert_eq!(encode_header("a\r\nBcc: b"), "=?utf-8?Q?a=0D=0ABcc=3A_b?=");
+    }
+}
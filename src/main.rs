@@ -1,27 +1,80 @@
-use std::convert::TryInto;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::iter;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
-use std::process::Command;
-use std::{collections::BTreeMap, fmt::Write, path::Path};
+use std::process::{Command, ExitCode};
+use std::{fmt::Write, path::Path};
 
 use anyhow::{bail, Context, Result};
 use clap::{CommandFactory, Parser};
-use clap_complete::{generate, Shell};
-use csv::{ReaderBuilder, WriterBuilder};
+use clap_complete::Shell;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use time::ext::NumericalDuration;
 use time::format_description::well_known::Rfc3339;
-use time::macros::format_description;
 use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
+mod autoswitch;
+mod cache;
+mod completions;
+mod config;
+mod diff;
+mod doctor;
+mod edit;
+mod error;
+mod export;
+mod fill;
+mod goals;
+mod index;
+mod init;
+mod invoice;
+mod locale;
+mod locked;
+mod mail;
+mod man;
+mod merge;
+mod mqtt;
+mod ongoing;
+mod overlap;
+mod parse;
+mod paths;
+mod prompt;
+mod prune;
+mod range;
+mod rounding;
+mod settings;
+mod shell;
+mod show;
+mod snapshot;
+mod standup;
+mod storage;
+mod summary;
 mod table;
-
-use table::{Alignment, Table};
-
-const FULL_BLOCK: char = '█';
-const UPPER_HALF_BLOCK: char = '▀';
-const LOWER_HALF_BLOCK: char = '▄';
-const LOWER_BORDER: char = '▁';
+mod template;
+mod timesheet;
+mod viz;
+mod watch;
+
+use config::{Config, Goal};
+use edit::EditFormat;
+use error::Failure;
+use export::Output;
+use mqtt::Event;
+use overlap::OnOverlap;
+use parse::{
+    parse_date, parse_datetime, parse_days, parse_duration, parse_iso_week, parse_midnight_offset,
+    parse_shift, parse_window,
+};
+use range::{Days, Range, Rounding};
+use shell::InitShell;
+use summary::{Breakdown, DailyTarget, DailyTotals, GroupBy};
+use table::{Alignment, Color, Options, Style, Table, NAME_WIDTH};
+use template::Starter;
+use viz::{Glyphs, Resolution, Theme};
 
 trait TruncateSubseconds {
     fn truncate_subseconds(self) -> Self;
@@ -46,74 +99,6 @@ impl TruncateSubseconds for OffsetDateTime {
     }
 }
 
-/// Parse a date and time, possibly inferring the date.
-///
-/// Expects either an RFC3339-formatted date/time, or a time with format
-/// `HH:MM:SS` or `HH:MM` (in which case the date is set to the current date).
-fn parse_datetime(src: &str) -> Result<OffsetDateTime> {
-    PrimitiveDateTime::parse(src, &Rfc3339)
-        .map_err(anyhow::Error::from)
-        .and_then(|dt| Ok(dt.assume_offset(UtcOffset::current_local_offset()?)))
-        .or_else(|_| {
-            // Try to parse either HH:MM:SS or HH:MM:SS
-            let time = Time::parse(src, &format_description!("[hour]:[minute]:[second]"))
-                .or_else(|_| Time::parse(src, &format_description!("[hour]:[minute]")))?;
-            // Extend time with current date
-            OffsetDateTime::now_local()
-                .map_err(anyhow::Error::from)
-                .map(|dt| dt.replace_time(time))
-        })
-        .context("Could not parse start date")
-}
-
-/// Parse a duration.
-///
-/// Expects a duration with format `HH:MM:SS` or `HH:MM`.
-fn parse_duration(src: &str) -> Result<Duration> {
-    // Try to parse a time
-    Time::parse(src, &format_description!("[hour]:[minute]:[second]"))
-        .or_else(|_| Time::parse(src, &format_description!("[hour]:[minute]")))
-        .context("Could not parse duration")
-        // Convert it to a duration by subtracting midnight
-        .map(|time| time - Time::MIDNIGHT)
-}
-
-/// Parse a (possibly relative) date.
-///
-/// Expects either `YYYY-mm-dd`, `today`, `yesterday`, or `N days ago` where `N`
-/// is a positive integer.
-fn parse_date(src: &str) -> Result<Date> {
-    // Try to parse a YYYY-mm-dd date
-    Date::parse(src, &format_description!("[year]-[month]-[day]"))
-        .map_err(anyhow::Error::from)
-        // Try to parse a literal 'today', 'yesterday' or 'N days ago'
-        .or_else(|err| {
-            if src == "today" {
-                Ok(OffsetDateTime::now_local()?.date())
-            } else if src == "yesterday" {
-                Ok(OffsetDateTime::now_local()?.date() - 1.days())
-            } else if let Some((days, s)) = src.split_once(" ") {
-                if s.trim() == "days ago" {
-                    if let Ok(days) = days.parse::<i64>() {
-                        return Ok(OffsetDateTime::now_local()?.date() - days.days());
-                    }
-                }
-                Err(err)
-            } else {
-                Err(err)
-            }
-        })
-        .context("Could not parse date")
-}
-
-fn default_temps_file() -> PathBuf {
-    if let Some(dirs) = directories::ProjectDirs::from("", "", "temps") {
-        dirs.data_dir().join("temps.tsv")
-    } else {
-        panic!("could not determine project dir")
-    }
-}
-
 #[derive(Parser, Debug)]
 #[clap(about = "Simple time tracker.", version, author)]
 struct Args {
@@ -122,19 +107,44 @@ struct Args {
     #[clap(
         long,
         env,
-        default_value_os_t = default_temps_file(),
-        help = "Path for the tracking data"
+        help = "Path for the tracking data (defaults to the configured one, or a platform-specific location)"
+    )]
+    temps_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "TEMPS_CONFIG",
+        default_value_os_t = paths::config_file(),
+        help = "Path for the configuration file"
     )]
-    temps_file: PathBuf,
+    config: PathBuf,
     #[clap(
         long,
         env = "TEMPS_MIDNIGHT_OFFSET",
-        value_parser = parse_duration,
+        global = true,
+        value_parser = parse_midnight_offset,
         default_value = "00:00",
         help = "Time at which we consider the current day to have ended"
         // It's not necessarily midnight because sometimes we make poor choices
     )]
     midnight_offset: Duration,
+    #[clap(
+        long,
+        env = "TEMPS_TABLE_STYLE",
+        value_enum,
+        help = "How tables are drawn (defaults to the configured style)"
+    )]
+    table_style: Option<Style>,
+    #[clap(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Whether to color tables (auto: if printing to a terminal, unless NO_COLOR is set)"
+    )]
+    color: When,
+    #[clap(long, help = "Never cut off long project names or tags in tables")]
+    full_names: bool,
+    #[clap(long, help = "Fail instead of changing the tracking file")]
+    read_only: bool,
     #[clap(
         long,
         value_name = "SHELL",
@@ -154,8 +164,125 @@ enum Subcommand {
         full: bool,
         #[clap(short, long, conflicts_with_all = &["full", "daily"], display_order=1, help = "Time tracked in the past week")]
         weekly: bool,
-        #[clap(short, long, conflicts_with_all = &["full", "weekly"], display_order=2, help = "Time tracked today (default)")]
-        daily: bool,
+        #[clap(
+            short,
+            long,
+            value_name = "DATE",
+            value_parser = parse_date,
+            num_args = 0..=1,
+            conflicts_with_all = &["full", "weekly"],
+            display_order = 2,
+            help = "Time tracked during a day (defaults to today, the default summary)"
+        )]
+        daily: Option<Option<Date>>,
+        #[clap(
+            short,
+            long,
+            value_name = "YEAR",
+            num_args = 0..=1,
+            conflicts_with_all = &["full", "weekly", "daily", "week", "week_of"],
+            display_order = 3,
+            help = "Time tracked per month during a year (defaults to this year)"
+        )]
+        yearly: Option<Option<i32>>,
+        #[clap(
+            long,
+            value_name = "DAYS",
+            value_parser = parse_days,
+            conflicts_with_all = &["full", "weekly", "daily", "yearly", "week", "week_of"],
+            help = "Time tracked over the past few days, with daily averages (e.g. 30d or 4w)"
+        )]
+        last: Option<u16>,
+        #[clap(
+            long,
+            value_name = "YYYY-Www",
+            value_parser = parse_iso_week,
+            conflicts_with_all = &["full", "daily", "week_of"],
+            help = "Time tracked during a calendar week (e.g. 2024-W07)"
+        )]
+        week: Option<Date>,
+        #[clap(
+            long,
+            value_name = "DATE",
+            value_parser = parse_date,
+            conflicts_with_all = &["full", "daily"],
+            help = "Time tracked during the calendar week containing a date"
+        )]
+        week_of: Option<Date>,
+        #[clap(
+            long,
+            value_name = "PROJECT",
+            help = "Leave a project out of the summary (in addition to the configured ones)"
+        )]
+        exclude: Vec<String>,
+        #[clap(
+            long,
+            help = "Only count entries with this context, e.g. 'office' or 'home'"
+        )]
+        context: Option<String>,
+        #[clap(long, help = "Only count entries with this tag")]
+        tag: Option<String>,
+        #[clap(
+            long,
+            value_enum,
+            default_value_t,
+            help = "Split the time of weekly summaries by project or by tag"
+        )]
+        by: GroupBy,
+        #[clap(long, value_enum, default_value_t, help = "Output format")]
+        format: Format,
+        #[clap(flatten)]
+        output: Output,
+    },
+    #[clap(
+        about = "Display the balance of time tracked against the weekly target",
+        display_order = 0
+    )]
+    Balance {
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "First day of the balance (defaults to the configured one)"
+        )]
+        since: Option<Date>,
+        #[clap(long, value_enum, default_value_t, help = "Output format")]
+        format: Format,
+        #[clap(flatten)]
+        output: Output,
+    },
+    #[clap(about = "Display today's summary and timeline", display_order = 0)]
+    Today,
+    #[clap(about = "Display the past week's summary", display_order = 0)]
+    Week,
+    #[clap(
+        about = "Chart the time tracked each week, to see trends over months",
+        display_order = 0
+    )]
+    Trend {
+        #[clap(long, help = "Only count this project (defaults to all of them)")]
+        project: Option<String>,
+        #[clap(
+            long,
+            default_value_t = 12,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Number of weeks to show, up to this one"
+        )]
+        weeks: u16,
+        #[clap(long, help = "Only use ASCII characters")]
+        ascii: bool,
+    },
+    #[clap(about = "Display the ongoing timer", display_order = 1)]
+    Status {
+        #[clap(
+            long,
+            help = "Set the terminal title to the ongoing project instead (or clear it if there is none)"
+        )]
+        osc_title: bool,
+        #[clap(
+            long,
+            help = "Only read the ongoing entry kept aside, not the tracking file (e.g. for prompts)"
+        )]
+        cached: bool,
     },
     #[clap(about = "Start new timer", display_order = 1)]
     Start {
@@ -163,18 +290,284 @@ enum Subcommand {
         project: Option<String>,
         #[clap(long, short, value_parser = parse_datetime, help = "Start date (defaults to now)")]
         from: Option<OffsetDateTime>,
+        #[clap(long, help = "Allow a start date in the future")]
+        force: bool,
+        #[clap(
+            long,
+            help = "Where the work is done, e.g. 'office' or 'home' (defaults to the detected one)"
+        )]
+        context: Option<String>,
+        #[clap(
+            long = "tag",
+            value_name = "TAG",
+            help = "Tag the new entry, e.g. 'billing' (can be repeated)"
+        )]
+        tags: Vec<String>,
+        #[clap(
+            long,
+            value_enum,
+            value_name = "WHAT",
+            help = "What to do if the start date is before the end of existing entries (asks by default)"
+        )]
+        on_overlap: Option<OnOverlap>,
     },
     #[clap(about = "Stop ongoing timer", display_order = 2)]
     Stop {
         #[clap(long, short, value_parser = parse_datetime, help = "Stop date (defaults to now)")]
         at: Option<OffsetDateTime>,
+        #[clap(long, help = "Allow a stop date in the future")]
+        force: bool,
+    },
+    #[clap(about = "Add a note to the ongoing timer", display_order = 2)]
+    Note {
+        #[clap(help = "Text of the note, e.g. what was just finished")]
+        text: String,
     },
     #[clap(about = "Cancel ongoing timer", display_order = 3)]
-    Cancel,
+    Cancel {
+        #[clap(
+            long,
+            visible_alias = "restore",
+            help = "Restore the last cancelled timer instead"
+        )]
+        undo: bool,
+    },
     #[clap(about = "List raw data", display_order = 4)]
-    List,
+    List {
+        #[clap(long, value_enum, default_value_t, help = "Output format")]
+        format: Format,
+        #[clap(flatten)]
+        output: Output,
+        #[clap(flatten)]
+        page: Page,
+        #[clap(long, help = "Show dates relative to today, e.g. 'yesterday 14:00'")]
+        relative: bool,
+        #[clap(long, help = "Only show entries with this tag")]
+        tag: Option<String>,
+    },
+    #[clap(
+        about = "Display everything about an entry, and what looks wrong with it",
+        display_order = 4
+    )]
+    Show {
+        #[clap(
+            required_unless_present = "last",
+            help = "Number of the entry, as shown by `temps list`"
+        )]
+        id: Option<usize>,
+        #[clap(long, conflicts_with = "id", help = "Show the last entry")]
+        last: bool,
+    },
+    #[clap(about = "Display entries grouped by day", display_order = 4)]
+    Log {
+        #[clap(long, value_parser = parse_date, help = "First day to display")]
+        from: Option<Date>,
+        #[clap(long, value_parser = parse_date, help = "Last day to display")]
+        to: Option<Date>,
+        #[clap(
+            long,
+            help = "Display the time spent on each project each day, with its notes in a list"
+        )]
+        by_project: bool,
+        #[clap(flatten)]
+        page: Page,
+        #[clap(long, help = "Name days relative to today, e.g. 'Yesterday'")]
+        relative: bool,
+    },
+    #[clap(
+        about = "Display what was done on the last working day, to paste into a standup",
+        display_order = 4
+    )]
+    Standup {
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "Day to display (defaults to the last day before today with something tracked)"
+        )]
+        date: Option<Date>,
+    },
+    #[clap(about = "Search entries by project, tag or note", display_order = 4)]
+    Search {
+        #[clap(help = "Text to search for (case-insensitive)")]
+        pattern: String,
+        #[clap(
+            long,
+            short,
+            help = "Interpret the pattern as a regular expression (case-insensitive unless it starts with (?-i))"
+        )]
+        regex: bool,
+    },
+    #[clap(
+        about = "List projects, with when they were last used and the time spent on them",
+        display_order = 4
+    )]
+    Projects {
+        #[clap(
+            long,
+            value_enum,
+            default_value_t,
+            help = "Order in which to list projects"
+        )]
+        sort: ProjectOrder,
+    },
+    #[clap(
+        about = "List tags, with how many entries have them and the time spent on them",
+        display_order = 4
+    )]
+    Tags,
+    #[clap(
+        about = "Record time spent on a project some day, without start or end times",
+        display_order = 3
+    )]
+    Add {
+        #[clap(help = "Project name")]
+        project: String,
+        #[clap(value_parser = parse_duration, help = "Time spent, as HH:MM")]
+        duration: Duration,
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "Day the time was spent (defaults to today)"
+        )]
+        date: Option<Date>,
+    },
+    #[clap(
+        about = "Record a day off, e.g. vacation or sick leave",
+        display_order = 3
+    )]
+    Off {
+        #[clap(value_parser = parse_date, help = "Day off (defaults to today)")]
+        date: Option<Date>,
+        #[clap(
+            long,
+            default_value = "vacation",
+            help = "Kind of day off, e.g. 'vacation' or 'sick'"
+        )]
+        kind: String,
+    },
+    #[clap(
+        about = "Record the recurring blocks of the config, e.g. daily meetings",
+        display_order = 3
+    )]
+    Fill {
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "Day to fill (defaults to today)"
+        )]
+        date: Option<Date>,
+        #[clap(long, help = "Preview the changes without writing them")]
+        dry_run: bool,
+        #[clap(
+            long,
+            short,
+            help = "Write the changes without asking for confirmation"
+        )]
+        yes: bool,
+    },
     #[clap(about = "Edit raw data with default editor", display_order = 5)]
-    Edit,
+    Edit {
+        #[clap(
+            long,
+            value_enum,
+            default_value_t,
+            help = "Format in which to edit entries"
+        )]
+        format: EditFormat,
+        #[clap(
+            long,
+            short,
+            help = "Write the changes without asking for confirmation (with --format toml or yaml)"
+        )]
+        yes: bool,
+        #[clap(long, help = "Allow changes to locked entries")]
+        force: bool,
+    },
+    #[clap(
+        about = "Display the weekly report, or send it by email",
+        display_order = 5
+    )]
+    Report {
+        #[clap(flatten)]
+        days: Days,
+        #[clap(
+            long,
+            value_enum,
+            value_name = "COLUMNS",
+            requires = "from",
+            help = "Split the range into a column per day, week or month, or only show the total of each project (the default)"
+        )]
+        group_by: Option<Breakdown>,
+        #[clap(
+            long,
+            value_name = "ADDRESS",
+            help = "Send the report to this address instead (see the `[email]` section of the config)"
+        )]
+        email: Vec<String>,
+        #[clap(
+            long,
+            value_name = "NAME",
+            conflicts_with = "email",
+            help = "Write the report from a template of the config directory"
+        )]
+        template: Option<String>,
+        #[clap(
+            long,
+            value_enum,
+            value_name = "KIND",
+            exclusive = true,
+            help = "Write a starter template to the config directory, to be edited"
+        )]
+        init_template: Option<Starter>,
+        #[clap(
+            long,
+            conflicts_with = "template",
+            help = "Add what was done each day, with the notes of each project in a list"
+        )]
+        notes: bool,
+        #[clap(flatten)]
+        rounding: Rounding,
+    },
+    #[clap(
+        about = "Write a numbered invoice to a client, and record it in the ledger",
+        display_order = 5
+    )]
+    Invoice {
+        #[clap(help = "Client to invoice: a project, along with those under it")]
+        client: String,
+        #[clap(flatten)]
+        days: Days,
+        #[clap(
+            long,
+            value_name = "NAME",
+            default_value = "invoice",
+            help = "Write the invoice from this template of the config directory"
+        )]
+        template: String,
+        #[clap(flatten)]
+        rounding: Rounding,
+        #[clap(
+            long,
+            help = "Invoice entries again, even if they're already on an invoice or locked"
+        )]
+        force: bool,
+        #[clap(long, help = "Show the invoice without recording it")]
+        dry_run: bool,
+    },
+    #[clap(
+        about = "Export a timesheet for an HR system, with the columns of the config",
+        display_order = 5
+    )]
+    Export {
+        #[clap(long, value_enum, default_value_t, help = "Format of the timesheet")]
+        format: timesheet::Format,
+        #[clap(flatten)]
+        days: Days,
+        #[clap(flatten)]
+        rounding: Rounding,
+        #[clap(flatten)]
+        output: Output,
+    },
     #[clap(
         about = "Visualize time spent on a given day",
         display_order = 5,
@@ -183,7 +576,270 @@ enum Subcommand {
     Visualize {
         #[clap(value_parser = parse_date, help = "Date (defaults to today)")]
         date: Option<Date>,
+        #[clap(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            conflicts_with = "from",
+            help = "Number of days to show side by side, up to the date"
+        )]
+        days: u16,
+        #[clap(
+            long,
+            value_parser = parse_date,
+            conflicts_with = "date",
+            help = "First day to show side by side"
+        )]
+        from: Option<Date>,
+        #[clap(
+            long,
+            value_parser = parse_date,
+            requires = "from",
+            help = "Last day to show side by side (defaults to today)"
+        )]
+        to: Option<Date>,
+        #[clap(long, help = "Only show entries of this project")]
+        project: Option<String>,
+        #[clap(long, help = "Only show entries with this tag")]
+        tag: Option<String>,
+        #[clap(long, help = "Only use ASCII characters")]
+        ascii: bool,
+        #[clap(
+            long,
+            conflicts_with = "ascii",
+            help = "Show time in finer detail, with Braille patterns"
+        )]
+        fine: bool,
+        #[clap(
+            long,
+            help = "List the tracked intervals as text instead (e.g. for screen readers)"
+        )]
+        text: bool,
+        #[clap(
+            long,
+            value_name = "HH:MM-HH:MM",
+            value_parser = parse_window,
+            conflicts_with = "text",
+            help = "Only show some hours of the day, e.g. 06:00-22:00"
+        )]
+        window: Option<(Duration, Duration)>,
+        #[clap(long, help = "Print the time tracked on each project under the chart")]
+        with_summary: bool,
+        #[clap(
+            long,
+            value_enum,
+            default_value_t,
+            conflicts_with_all = ["text", "window", "with_summary"],
+            help = "Output format"
+        )]
+        format: VizFormat,
+        #[clap(flatten)]
+        output: Output,
+    },
+    #[clap(about = "Rename or retag many entries at once", display_order = 6)]
+    Retag {
+        #[clap(
+            long = "match",
+            value_name = "GLOB",
+            required_unless_present = "regex",
+            conflicts_with = "regex",
+            help = "Select projects matching a glob pattern (e.g. 'client-a-*')"
+        )]
+        glob: Option<String>,
+        #[clap(
+            long,
+            value_name = "REGEX",
+            help = "Select projects matching a regular expression"
+        )]
+        regex: Option<String>,
+        #[clap(
+            long = "set-tag",
+            value_name = "TAG",
+            help = "Add a tag to the selected entries"
+        )]
+        set_tags: Vec<String>,
+        #[clap(
+            long = "unset-tag",
+            value_name = "TAG",
+            help = "Remove a tag from the selected entries"
+        )]
+        unset_tags: Vec<String>,
+        #[clap(
+            long,
+            value_name = "REPLACEMENT",
+            help = "Rename the selected projects (supports capture groups like '$1')"
+        )]
+        rename: Option<String>,
+        #[clap(long, help = "Allow changes to locked entries")]
+        force: bool,
+        #[clap(long, help = "Preview the changes without writing them")]
+        dry_run: bool,
+        #[clap(
+            long,
+            short,
+            help = "Write the changes without asking for confirmation"
+        )]
+        yes: bool,
+    },
+    #[clap(
+        about = "Merge entries from tracking files of other devices, fixing clock skew",
+        display_order = 6
+    )]
+    Merge {
+        #[clap(value_name = "FILE", required = true, help = "Tracking files to merge")]
+        files: Vec<PathBuf>,
+        #[clap(
+            long = "shift",
+            value_name = "DEVICE=SHIFT",
+            value_parser = parse_shift,
+            help = "Move the entries recorded on a device in time (e.g. 'laptop=-00:05')"
+        )]
+        shifts: Vec<(String, Duration)>,
+        #[clap(long, help = "Preview the changes without writing them")]
+        dry_run: bool,
+        #[clap(
+            long,
+            short,
+            help = "Write the changes without asking for confirmation"
+        )]
+        yes: bool,
+    },
+    #[clap(
+        about = "Remove old entries, e.g. once they're exported, after backing up the file",
+        display_order = 6
+    )]
+    Prune {
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "Remove the entries from before this day (e.g. 2020-01-01)"
+        )]
+        before: Date,
+        #[clap(long, help = "Only remove the entries of this project")]
+        project: Option<String>,
+        #[clap(long, help = "Preview the changes without writing them")]
+        dry_run: bool,
+        #[clap(
+            long,
+            short,
+            help = "Remove the entries without asking for confirmation"
+        )]
+        yes: bool,
+    },
+    #[clap(
+        about = "Lock past entries once they're reported, so that they aren't changed by mistake",
+        display_order = 6
+    )]
+    Lock {
+        #[clap(
+            long,
+            value_parser = parse_date,
+            help = "Lock the entries from before this day (e.g. 2021-10-01)"
+        )]
+        before: Date,
+        #[clap(long, help = "Unlock them instead, so that they can be changed again")]
+        unlock: bool,
+    },
+    #[clap(
+        about = "Print where the tracking file, configuration, cache and state are kept",
+        display_order = 7
+    )]
+    Paths,
+    #[clap(
+        about = "Print the tracking file and settings in effect, and where each comes from",
+        display_order = 7
+    )]
+    Where,
+    #[clap(
+        about = "Set things up: the configuration file, where entries are kept, and completions",
+        display_order = 7
+    )]
+    Init,
+    #[clap(
+        about = "Check the tracking file and configuration for problems",
+        display_order = 7
+    )]
+    Doctor,
+    #[clap(
+        about = "Start the project of a directory, from its .temps file (for shell hooks)",
+        display_order = 7
+    )]
+    AutoSwitch {
+        #[clap(long, value_name = "PATH", help = "Directory that was entered")]
+        dir: PathBuf,
+    },
+    #[clap(
+        about = "Track time from the focused window, following the rules in the config",
+        display_order = 7
+    )]
+    Watch {
+        #[clap(
+            long,
+            help = "Print the project of each focused window instead of tracking it"
+        )]
+        dry_run: bool,
+    },
+    #[clap(
+        about = "Print shell functions to load with eval, e.g. `eval \"$(temps shell-init bash)\"`",
+        display_order = 7
+    )]
+    ShellInit {
+        #[clap(value_enum)]
+        shell: InitShell,
+        #[clap(
+            long,
+            help = "Start the project named in a directory's .temps file when entering it"
+        )]
+        auto_switch: bool,
+    },
+    #[clap(
+        about = "Install shell completions, or list what they complete",
+        display_order = 7
+    )]
+    Completions {
+        #[clap(subcommand)]
+        action: CompletionsAction,
+    },
+    #[clap(
+        about = "Print the man page, with every command and the configuration file",
+        display_order = 7
+    )]
+    GenMan,
+}
+
+#[derive(Parser, Debug)]
+enum CompletionsAction {
+    #[clap(about = "Write completions where the shell looks for them, for the current user")]
+    Install {
+        #[clap(value_enum)]
+        shell: InitShell,
     },
+    #[clap(about = "List project names, one per line (called by the completions)")]
+    Projects,
+}
+
+impl Subcommand {
+    /// Whether the command may change the tracking file.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Subcommand::Start { .. }
+            | Subcommand::Stop { .. }
+            | Subcommand::Note { .. }
+            | Subcommand::Cancel { .. }
+            | Subcommand::Off { .. }
+            | Subcommand::Add { .. }
+            | Subcommand::Edit { .. }
+            | Subcommand::Lock { .. }
+            | Subcommand::AutoSwitch { .. } => true,
+            Subcommand::Retag { dry_run, .. }
+            | Subcommand::Merge { dry_run, .. }
+            | Subcommand::Prune { dry_run, .. }
+            | Subcommand::Fill { dry_run, .. }
+            | Subcommand::Invoice { dry_run, .. }
+            | Subcommand::Watch { dry_run } => !dry_run,
+            _ => false,
+        }
+    }
 }
 
 impl Default for Subcommand {
@@ -191,12 +847,152 @@ impl Default for Subcommand {
         Subcommand::Summary {
             full: false,
             weekly: false,
-            daily: true,
+            daily: None,
+            yearly: None,
+            last: None,
+            week: None,
+            week_of: None,
+            exclude: vec![],
+            context: None,
+            tag: None,
+            by: GroupBy::Project,
+            format: Format::Text,
+            output: Output::default(),
+        }
+    }
+}
+
+/// Output format of reports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// Human-readable text, with tables drawn in the configured style
+    #[default]
+    Text,
+    /// GitHub-flavoured Markdown tables, e.g. to paste into issues or wikis
+    Markdown,
+    /// Comma-separated values, with durations in seconds
+    Csv,
+    /// A JSON array, with durations in seconds
+    Json,
+}
+
+/// Output format of `viz`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum VizFormat {
+    /// A chart of each day, drawn in the terminal
+    #[default]
+    Chart,
+    /// The tracked intervals, clipped to each day, e.g. for dashboards
+    Json,
+}
+
+impl Format {
+    /// Whether the output is meant for other programs rather than people.
+    fn is_machine_readable(self) -> bool {
+        matches!(self, Format::Csv | Format::Json)
+    }
+
+    /// How tables should be displayed, given the configured options.
+    fn table_options(self, options: Options) -> Options {
+        match self {
+            Format::Text | Format::Csv | Format::Json => options,
+            Format::Markdown => Options {
+                style: Style::Markdown,
+                colored: false,
+                ..options
+            },
+        }
+    }
+}
+
+/// Order in which projects are listed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ProjectOrder {
+    /// Most recently used first
+    #[default]
+    Recent,
+    /// Most time spent first
+    Time,
+    /// Alphabetically
+    Name,
+}
+
+/// Which part of a long list to show.
+#[derive(Debug, Default, clap::Args)]
+struct Page {
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Show at most this many entries (days, with `log`)"
+    )]
+    limit: Option<usize>,
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t,
+        conflicts_with = "page",
+        help = "Skip this many first (or last, with --reverse)"
+    )]
+    offset: usize,
+    #[clap(
+        long,
+        value_name = "N",
+        requires = "limit",
+        help = "Show the Nth page of --limit each, from 1"
+    )]
+    page: Option<NonZeroUsize>,
+    #[clap(long, help = "Show the newest first")]
+    reverse: bool,
+}
+
+impl Page {
+    /// The items on the page, in the order they're shown, with their index
+    /// among all items.
+    fn select<'a, T>(&self, items: &'a [T]) -> Vec<(usize, &'a T)> {
+        self.select_where(items, |_| true)
+    }
+
+    /// The items on the page among those to `keep`, keeping their index
+    /// among all items.
+    fn select_where<'a, T>(
+        &self,
+        items: &'a [T],
+        keep: impl Fn(&T) -> bool,
+    ) -> Vec<(usize, &'a T)> {
+        let offset = match (self.page, self.limit) {
+            (Some(page), Some(limit)) => (page.get() - 1) * limit,
+            _ => self.offset,
+        };
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let items = items.iter().enumerate().filter(|(_, item)| keep(item));
+        if self.reverse {
+            items.rev().skip(offset).take(limit).collect()
+        } else {
+            items.skip(offset).take(limit).collect()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum When {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl When {
+    /// Whether colors should be used when printing to stdout.
+    fn colored(self) -> bool {
+        match self {
+            When::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            When::Always => true,
+            When::Never => false,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// A time-tracking entry associated with a project.
 struct Entry {
     project: String,
@@ -204,173 +1000,615 @@ struct Entry {
     start: OffsetDateTime,
     #[serde(with = "time::serde::rfc3339::option")]
     end: Option<OffsetDateTime>,
+    #[serde(default, with = "tags")]
+    tags: Vec<String>,
+    #[serde(default, with = "notes")]
+    notes: Vec<Note>,
+    #[serde(default)]
+    kind: Kind,
+    /// Where the work was done, e.g. `office` or `home`.
+    #[serde(default)]
+    context: Option<String>,
+    /// Machine the entry was recorded on, e.g. `laptop`.
+    #[serde(default)]
+    source: Option<String>,
+    /// Set once the entry is reported, so that it isn't changed by mistake.
+    #[serde(default, with = "flag")]
+    locked: bool,
+}
+
+/// What an entry records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Kind {
+    /// Time spent on a project
+    #[default]
+    #[serde(rename = "")]
+    Work,
+    /// A full day off, e.g. vacation or sick leave (the project says which)
+    #[serde(rename = "day-off")]
+    DayOff,
+    /// Time spent on a project some day, at no particular time
+    ///
+    /// The entry starts at the beginning of the day and lasts as long as the
+    /// time spent, so that it counts like any other; charts keep it apart.
+    #[serde(rename = "unscheduled")]
+    Unscheduled,
+}
+
+/// A timestamped annotation of an entry.
+#[derive(Debug, Clone, PartialEq)]
+struct Note {
+    at: OffsetDateTime,
+    text: String,
+}
+
+/// (De)serialize a list of tags as a single comma-separated column.
+mod tags {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(tags: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&tags.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        let tags = String::deserialize(deserializer)?;
+        Ok(tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
+/// (De)serialize a flag as `true`, or an empty column when it's not set.
+mod flag {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(flag: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(if *flag { "true" } else { "" })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "" | "false" => Ok(false),
+            "true" => Ok(true),
+            other => Err(D::Error::custom(format!(
+                "expected 'true' or nothing, got '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+/// (De)serialize a list of notes as a single column.
+///
+/// Notes are separated by ` | `, and each starts with its RFC3339 timestamp.
+/// Pipes, backslashes and line breaks in the text are escaped with a backslash.
+mod notes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use time::format_description::well_known::Rfc3339;
+    use time::OffsetDateTime;
+
+    use super::Note;
+
+    pub fn serialize<S: Serializer>(notes: &[Note], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut column = vec![];
+        for note in notes {
+            let at = note
+                .at
+                .format(&Rfc3339)
+                .map_err(serde::ser::Error::custom)?;
+            let text = note
+                .text
+                .replace('\\', "\\\\")
+                .replace('|', "\\|")
+                .replace('\n', "\\n");
+            column.push(format!("{} {}", at, text));
+        }
+        serializer.serialize_str(&column.join(" | "))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Note>, D::Error> {
+        let column = String::deserialize(deserializer)?;
+
+        // Split on unescaped pipes, unescaping the rest
+        let mut notes = vec![String::new()];
+        let mut chars = column.chars();
+        while let Some(c) = chars.next() {
+            let note = notes.last_mut().unwrap();
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => note.push('\n'),
+                    Some(c) => note.push(c),
+                    None => {}
+                },
+                '|' => notes.push(String::new()),
+                c => note.push(c),
+            }
+        }
+
+        notes
+            .iter()
+            .map(|note| note.trim())
+            .filter(|note| !note.is_empty())
+            .map(|note| {
+                let (at, text) = note.split_once(' ').unwrap_or((note, ""));
+                Ok(Note {
+                    at: OffsetDateTime::parse(at, &Rfc3339).map_err(D::Error::custom)?,
+                    text: text.to_owned(),
+                })
+            })
+            .collect()
+    }
 }
 
 impl Entry {
     /// Start a new entry from the current date/time.
-    fn start(project: String) -> Self {
-        Self::start_from(
-            project,
-            OffsetDateTime::now_local().expect("Could not determine local datetime"),
-        )
+    fn start(project: String) -> Result<Self> {
+        Self::start_from(project, OffsetDateTime::now_local()?, false)
     }
 
     /// Start a new entry from a specific date/time.
     ///
-    /// Panics if the start time is in the future.
-    fn start_from(project: String, start: OffsetDateTime) -> Self {
-        if start > OffsetDateTime::now_local().expect("Could not determine local datetime") {
-            panic!("Start date is in the future");
+    /// Fails if the start time is in the future, unless `force` is set.
+    fn start_from(project: String, start: OffsetDateTime, force: bool) -> Result<Self> {
+        if !force && start > OffsetDateTime::now_local()? {
+            bail!("Start date is in the future (use --force to start anyway)");
         }
-        Self {
+        Ok(Self {
             project,
             start: start.truncate_subseconds(),
             end: None,
+            tags: vec![],
+            notes: vec![],
+            kind: Kind::Work,
+            context: None,
+            source: None,
+            locked: false,
+        })
+    }
+
+    /// Record a full day off, e.g. vacation.
+    ///
+    /// The entry starts and ends at the beginning of the day, so that no time
+    /// is tracked.
+    fn day_off(kind: String, day: Date, midnight_offset: Duration) -> Result<Self> {
+        let start = assume_local(day.with_time(Time::MIDNIGHT) + midnight_offset)?;
+        Ok(Self {
+            project: kind,
+            start,
+            end: Some(start),
+            tags: vec![],
+            notes: vec![],
+            kind: Kind::DayOff,
+            context: None,
+            source: None,
+            locked: false,
+        })
+    }
+
+    /// Record time spent on a project some day, without start or end times.
+    fn unscheduled(
+        project: String,
+        day: Date,
+        duration: Duration,
+        midnight_offset: Duration,
+    ) -> Result<Self> {
+        if !duration.is_positive() || duration >= Duration::DAY {
+            bail!("The time spent must be more than nothing, and less than a day");
         }
+        let start = assume_local(day.with_time(Time::MIDNIGHT) + midnight_offset)?;
+        Ok(Self {
+            project,
+            start,
+            end: Some(start + duration),
+            tags: vec![],
+            notes: vec![],
+            kind: Kind::Unscheduled,
+            context: None,
+            source: None,
+            locked: false,
+        })
     }
 
     /// Stop the entry at the current date/time.
-    fn stop(&mut self) {
-        self.stop_at(OffsetDateTime::now_local().expect("Could not determine local datetime"))
+    fn stop(&mut self) -> Result<()> {
+        self.stop_at(OffsetDateTime::now_local()?, false)
     }
 
     /// Stop the entry at a specific date/time.
     ///
-    /// Panics if the end time is in the future, or is before the start time.
-    fn stop_at(&mut self, end: OffsetDateTime) {
-        if end > OffsetDateTime::now_local().expect("Could not determine local datetime") {
-            panic!("End date is in the future");
+    /// Fails if the end time is before the start time, or is in the future
+    /// (unless `force` is set).
+    fn stop_at(&mut self, end: OffsetDateTime, force: bool) -> Result<()> {
+        if !force && end > OffsetDateTime::now_local()? {
+            bail!("End date is in the future (use --force to stop anyway)");
         }
         if end < self.start {
-            panic!("End date is before start date");
+            bail!(
+                "Cannot stop '{}' before it started (at {})",
+                self.project,
+                datetime_to_human_string(self.start)?
+            );
         }
         self.end = Some(end.truncate_subseconds());
+        Ok(())
     }
 
     /// Check whether the entry is still tracking time.
     fn is_ongoing(&self) -> bool {
         self.end.is_none()
     }
+
+    /// Time spent on the entry, counting an ongoing one up to `now`.
+    ///
+    /// An entry that ends before it starts (after a clock change or a bad edit)
+    /// counts for nothing, rather than taking time off totals.
+    fn duration(&self, now: OffsetDateTime) -> Duration {
+        (self.end.unwrap_or(now) - self.start).max(Duration::ZERO)
+    }
+
+    /// Check whether the project name, one of the tags or one of the notes
+    /// matches a pattern.
+    fn matches(&self, pattern: &Regex) -> bool {
+        pattern.is_match(&self.project)
+            || self.tags.iter().any(|tag| pattern.is_match(tag))
+            || self.notes.iter().any(|note| pattern.is_match(&note.text))
+    }
+
+    /// Add a tag to the entry, unless it's already there.
+    fn add_tag(&mut self, tag: &str) {
+        if !self.tags.iter().any(|t| t == tag) {
+            self.tags.push(tag.to_owned());
+        }
+    }
+
+    /// Remove a tag from the entry, if it's there.
+    fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| t != tag);
+    }
 }
 
-/// Write entries back to a time tracking file
-fn write_back<P: AsRef<Path>>(path: P, entries: &[Entry]) -> Result<()> {
-    let mut writer = WriterBuilder::new()
-        .delimiter(b'\t')
-        .from_path(path)
-        .context("Could not open tracking file")?;
-    for entry in entries {
-        writer
-            .serialize(entry)
-            .context("Could not write entry to file")?;
+/// Convert a glob pattern (`*` and `?` wildcards) to an anchored regex.
+///
+/// Each wildcard becomes a capture group, so that it can be referred to in
+/// replacements.
+fn glob_to_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str("(.*)"),
+            '?' => pattern.push_str("(.)"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
     }
-    Ok(())
+    pattern.push('$');
+    Regex::new(&pattern).context("Could not parse glob pattern")
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Filter out entries whose project is in the `exclude` list.
+fn excluding(entries: &[Entry], exclude: &[String]) -> Vec<Entry> {
+    entries
+        .iter()
+        .filter(|entry| !exclude.contains(&entry.project))
+        .cloned()
+        .collect()
+}
 
-    if let Some(shell) = args.generate_completions {
-        // Generate completions then exit
-        let mut app = Args::command();
-        let bin_name = app.get_name().to_string();
+/// Directory of the report templates, next to the config file.
+fn templates_dir(config: &Path) -> PathBuf {
+    config
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("templates")
+}
+
+/// Daily totals and ongoing entry of some entries, leaving out some projects.
+fn totals_of(
+    entries: &[Entry],
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+    exclude: &[String],
+) -> (DailyTotals, Option<Entry>) {
+    let entries = excluding(entries, exclude);
+    let ongoing = entries.last().filter(|entry| entry.is_ongoing()).cloned();
+    (
+        summary::daily_totals(&entries, midnight_offset, now),
+        ongoing,
+    )
+}
+
+/// Days without a target: the holidays of the configuration, and the days
+/// taken off among `entries`.
+fn days_off(
+    holidays: &config::HolidaysConfig,
+    entries: &[Entry],
+    midnight_offset: Duration,
+) -> Result<BTreeSet<Date>> {
+    let mut days = holidays.days()?;
+    days.extend(
+        entries
+            .iter()
+            .filter(|entry| entry.kind == Kind::DayOff)
+            .map(|entry| (entry.start - midnight_offset).date()),
+    );
+    Ok(days)
+}
+
+/// Moment a date and time happen in the local timezone, with the offset it has
+/// then, which isn't the current one if daylight saving time changes between.
+fn assume_local(datetime: PrimitiveDateTime) -> Result<OffsetDateTime> {
+    // The same time in UTC is only hours away, so its offset is the right
+    // one unless it changes in between, and then the guess's offset is
+    let guess = datetime.assume_offset(UtcOffset::local_offset_at(datetime.assume_utc())?);
+    Ok(datetime.assume_offset(UtcOffset::local_offset_at(guess)?))
+}
+
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            // Help and version are also reported as "errors" by clap
+            let _ = err.print();
+            return if err.use_stderr() {
+                ExitCode::from(Failure::USER_ERROR)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
 
-        if shell == Shell::Fish {
-            // For fish shell, never complete on file names
-            println!("complete -c {} -f", bin_name);
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            err.downcast_ref::<Failure>()
+                .map_or(ExitCode::from(Failure::USER_ERROR), Failure::exit_code)
         }
+    }
+}
 
-        generate(shell, &mut app, bin_name, &mut std::io::stdout());
+fn run(args: Args) -> Result<()> {
+    if let Some(shell) = args.generate_completions {
+        // Generate completions then exit
+        return completions::write(shell, Args::command(), &mut io::stdout());
+    }
 
+    if let Some(Subcommand::Init) = args.subcommand {
+        // The configuration may not exist yet, or be the reason to start over
+        let path = args.temps_file.unwrap_or_else(paths::data_file);
+        return init::init(&args.config, &path, Args::command());
+    }
+    if let Some(Subcommand::GenMan) = args.subcommand {
+        return man::write(Args::command(), &mut io::stdout());
+    }
+    if let Some(Subcommand::Completions {
+        action: CompletionsAction::Install { shell },
+    }) = args.subcommand
+    {
+        // Completions don't depend on the configuration
+        let path = completions::install(shell.into(), Args::command())?;
+        completions::print_installed(&path);
         return Ok(());
     }
 
-    let path = Path::new(&args.temps_file);
+    let config = Config::load(&args.config)?;
+    locale::init(&config.display);
+    let path = &args
+        .temps_file
+        .clone()
+        .or_else(|| config.storage.file.clone())
+        .unwrap_or_else(paths::data_file);
+    let path = path.as_path();
+    if config.storage.snapshot {
+        snapshot::enable(path);
+    }
+    let options = Options {
+        style: args.table_style.unwrap_or(config.table.style),
+        colored: args.color.colored(),
+        full_width: args.full_names,
+    };
 
-    // Read entry file if it exists
-    let mut entries = if path.exists() {
-        ReaderBuilder::new()
-            .delimiter(b'\t')
-            .from_path(path)
-            .context("Could not open tracking file")?
-            .into_deserialize()
-            .collect::<Result<Vec<Entry>, csv::Error>>()
-            .context("Could not read entries")?
-    } else {
-        vec![]
+    let subcommand = args.subcommand.unwrap_or_default();
+    // Goals can only be reached by changing the tracking file (which read-only
+    // mode refuses)
+    let check_goals = subcommand.is_mutating();
+    if subcommand.is_mutating() {
+        if args.read_only {
+            bail!(Failure::ReadOnly(
+                "Not changing the tracking file in read-only mode (--read-only)".to_owned()
+            ));
+        }
+        storage::check_writable(path)?;
+    }
+    // Held until the command is done; the watcher runs all the time, so it
+    // only locks the file while it changes it
+    let _lock = match subcommand {
+        Subcommand::Watch { .. } => None,
+        _ if subcommand.is_mutating() => Some(storage::lock(path)?),
+        _ => None,
     };
 
-    match args.subcommand.unwrap_or_default() {
-        Subcommand::Start { project, from } => {
-            // Stop previous entry if it's still ongoing
-            if let Some(last) = entries.last_mut() {
-                if last.is_ongoing() {
-                    if let Some(from) = from {
-                        last.stop_at(from);
-                        eprintln!(
-                            "Stopped '{}' at {}.",
-                            last.project,
-                            datetime_to_human_string(from).context("Could not format datetime")?
-                        );
-                    } else {
-                        last.stop();
-                        eprintln!("Stopped '{}'.", last.project);
-                    }
-                }
-            }
+    match subcommand {
+        Subcommand::Start {
+            project,
+            mut from,
+            force,
+            context,
+            tags,
+            on_overlap,
+        } => {
+            let mut last = storage::read_last(path)?;
 
             // Use previous project as default
             let project = project
-                .or_else(|| entries.last().map(|e| e.project.clone()))
+                .or_else(|| {
+                    last.clone()
+                        .filter(|e| e.kind == Kind::Work)
+                        .map(|e| e.project)
+                })
                 .context("Cannot infer project name, please specify")?;
 
-            let entry = if let Some(from) = from {
-                Entry::start_from(project, from)
-            } else {
-                Entry::start(project)
-            };
+            // Starting before existing entries end would overlap them
+            let mut truncate_at = None;
+            if let Some(start) = from {
+                if !force && start > OffsetDateTime::now_local()? {
+                    bail!("Start date is in the future (use --force to start anyway)");
+                }
+                let overlapping = overlap::find(path, start)?;
+                if !overlapping.is_empty() {
+                    match overlap::choose(&project, start, &overlapping, on_overlap)? {
+                        OnOverlap::TruncateOld if overlapping.iter().any(|entry| entry.locked) => {
+                            bail!(
+                                "Cannot end or remove locked entries, pass --on-overlap truncate-new to start '{}' when they end instead",
+                                project
+                            )
+                        }
+                        OnOverlap::TruncateOld => truncate_at = Some(start),
+                        OnOverlap::TruncateNew => from = overlap::truncated_start(&overlapping),
+                        OnOverlap::Abort => bail!("Not starting '{}'", project),
+                    }
+                }
+            }
 
-            if let Some(from) = from {
-                eprintln!(
-                    "Started '{}' from {}.",
-                    entry.project,
+            // Stop previous entry if it's still ongoing, or end all those that
+            // overlap
+            let mut stopped = None;
+            let mut truncated = None;
+            if let Some(start) = truncate_at {
+                let mut entries = storage::read_all(path)?;
+                for change in overlap::truncate(&mut entries, start)? {
+                    eprintln!("{}", change);
+                }
+                truncated = Some(entries);
+                stopped = last
+                    .filter(|last| last.is_ongoing() && last.start < start)
+                    .map(|last| Entry {
+                        end: Some(start),
+                        ..last
+                    });
+            } else if let Some(last) = last.as_mut().filter(|last| last.is_ongoing()) {
+                if let Some(from) = from {
+                    last.stop_at(from, force)?;
+                    eprintln!(
+                        "Stopped '{}' at {}.",
+                        last.project,
+                        datetime_to_human_string(from).context("Could not format datetime")?
+                    );
+                } else {
+                    last.stop()?;
+                    eprintln!("Stopped '{}'.", last.project);
+                }
+                stopped = Some(last.clone());
+            }
+
+            let mut entry = if let Some(from) = from {
+                Entry::start_from(project, from, force)?
+            } else {
+                Entry::start(project)?
+            };
+            entry.context = context.or_else(|| detect_context(&config));
+            entry.source = config.storage.source();
+            for tag in &tags {
+                entry.add_tag(tag);
+            }
+
+            if let Some(from) = from {
+                eprintln!(
+                    "Started '{}' from {}.",
+                    entry.project,
                     datetime_to_human_string(from).context("Could not format datetime")?
                 );
             } else {
                 eprintln!("Started '{}'.", entry.project);
             }
-            entries.push(entry);
 
-            write_back(path, &entries)?;
+            if let Some(mut entries) = truncated {
+                entries.push(entry.clone());
+                storage::write_back(path, &entries)?;
+                if let Some(stopped) = stopped {
+                    mqtt::publish(&config.mqtt, Event::Stop, &stopped);
+                }
+            } else if let Some(stopped) = stopped {
+                storage::replace_last(path, &[stopped.clone(), entry.clone()])?;
+                mqtt::publish(&config.mqtt, Event::Stop, &stopped);
+            } else {
+                storage::append(path, &entry)?;
+            }
+            mqtt::publish(&config.mqtt, Event::Start, &entry);
         }
 
-        Subcommand::Stop { at } => {
-            let last = entries.last_mut().context("No previous entry exists")?;
-
-            if !last.is_ongoing() {
-                bail!("No ongoing entry");
+        Subcommand::Status { osc_title, cached } => {
+            let ongoing = if cached {
+                ongoing::read(path)?
+            } else {
+                storage::read_last(path)?
+                    .filter(|entry| entry.is_ongoing())
+                    .map(|entry| (entry.project, entry.start))
+            };
+            let now = OffsetDateTime::now_local()?;
+            if osc_title {
+                let title = match ongoing {
+                    Some((project, start)) => {
+                        format!("{} ({})", project, duration_to_string(now - start)?)
+                    }
+                    // An empty title gives the terminal's own back
+                    None => String::new(),
+                };
+                // OSC 0 sets the window and tab title; control characters in the
+                // project name would end the sequence early, so they're dropped
+                let title = title
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .collect::<String>();
+                print!("\x1b]0;{}\x07", title);
+            } else {
+                let (project, start) = ongoing.context(Failure::NoOngoingEntry)?;
+                println!(
+                    "{} ({}, since {})",
+                    project,
+                    duration_to_string(now - start)?,
+                    datetime_to_human_string(start)?
+                );
             }
+        }
+
+        Subcommand::Stop { at, force } => {
+            let mut last = storage::read_last(path)?
+                .filter(|entry| entry.is_ongoing())
+                .context(Failure::NoOngoingEntry)?;
 
             if let Some(at) = at {
-                last.stop_at(at);
+                last.stop_at(at, force)?;
             } else {
-                last.stop();
+                last.stop()?;
             }
             eprintln!("Stopped '{}'.", last.project);
 
-            write_back(path, &entries)?;
+            storage::replace_last(path, &[last.clone()])?;
+            mqtt::publish(&config.mqtt, Event::Stop, &last);
         }
 
-        Subcommand::Cancel => {
-            if !entries
-                .last()
-                .context("No previous entry exists")?
-                .is_ongoing()
-            {
-                bail!("No ongoing entry");
-            }
+        Subcommand::Note { text } => {
+            let mut last = storage::read_last(path)?
+                .filter(|entry| entry.is_ongoing())
+                .context(Failure::NoOngoingEntry)?;
+            last.notes.push(Note {
+                at: OffsetDateTime::now_local()?.truncate_subseconds(),
+                text,
+            });
+            storage::replace_last(path, &[last])?;
+        }
 
-            let entry = entries.pop().unwrap(); // Unwrap ok because we know there's at least one entry
+        Subcommand::Cancel { undo: false } => {
+            let entry = storage::read_last(path)?
+                .filter(|entry| entry.is_ongoing())
+                .context(Failure::NoOngoingEntry)?;
 
             eprintln!(
                 "Cancelled '{}' (started at {}).",
@@ -378,349 +1616,1472 @@ fn main() -> Result<()> {
                 entry.start.format(&Rfc3339)?
             );
 
-            write_back(path, &entries)?;
+            // Keep it around, in case it was cancelled by mistake
+            storage::save_cancelled(path, &entry)?;
+            storage::replace_last(path, &[])?;
+            mqtt::publish(&config.mqtt, Event::Cancel, &entry);
         }
 
-        Subcommand::List => {
-            let mut table = Table::new(["Project", "Start", "End"]);
-            for entry in &entries {
+        Subcommand::Cancel { undo: true } => {
+            let entry = storage::read_cancelled(path)?.context("No cancelled timer to restore")?;
+            if let Some(last) = storage::read_last(path)? {
+                if last.is_ongoing() {
+                    bail!(
+                        "Cannot restore '{}' while '{}' is ongoing (stop or cancel it first)",
+                        entry.project,
+                        last.project
+                    );
+                }
+                if last.end.is_some_and(|end| end > entry.start) {
+                    bail!(
+                        "Cannot restore '{}': '{}' was tracked since it started",
+                        entry.project,
+                        last.project
+                    );
+                }
+            }
+
+            storage::append(path, &entry)?;
+            storage::clear_cancelled(path)?;
+            mqtt::publish(&config.mqtt, Event::Restore, &entry);
+            eprintln!(
+                "Restored '{}' (started at {}).",
+                entry.project,
+                entry.start.format(&Rfc3339)?
+            );
+        }
+
+        Subcommand::Add {
+            project,
+            duration,
+            date,
+        } => {
+            let today = (OffsetDateTime::now_local()? - args.midnight_offset).date();
+            let date = date.unwrap_or(today);
+            if date > today {
+                bail!("Cannot record time spent in the future");
+            }
+            let mut entry = Entry::unscheduled(project, date, duration, args.midnight_offset)?;
+            entry.source = config.storage.source();
+            storage::insert(path, entry.clone(), |_| Ok(()))?;
+            eprintln!(
+                "Recorded {} on '{}' on {}.",
+                duration_to_string(duration)?,
+                entry.project,
+                locale::date(date)
+            );
+        }
+
+        Subcommand::Off { date, kind } => {
+            let today = (OffsetDateTime::now_local()? - args.midnight_offset).date();
+            let date = date.unwrap_or(today);
+            if date > today {
+                bail!("Cannot record a day off in the future (declare it in the `[holidays]` section of the config file instead)");
+            }
+            let mut entry = Entry::day_off(kind, date, args.midnight_offset)?;
+            entry.source = config.storage.source();
+            let day = locale::date(date);
+            storage::insert(path, entry.clone(), |existing| {
+                if existing.kind == Kind::DayOff && existing.start == entry.start {
+                    bail!(
+                        "{} is already recorded as a day off ({})",
+                        day,
+                        existing.project
+                    );
+                }
+                Ok(())
+            })?;
+            eprintln!("Recorded {} as a day off ({}).", day, entry.project);
+        }
+
+        Subcommand::Fill { date, dry_run, yes } => {
+            let today = (OffsetDateTime::now_local()? - args.midnight_offset).date();
+            fill::fill(
+                path,
+                &config,
+                date.unwrap_or(today),
+                args.midnight_offset,
+                dry_run,
+                yes,
+                options.colored,
+            )?;
+        }
+
+        Subcommand::List {
+            format,
+            output,
+            page,
+            tag,
+            ..
+        } if format.is_machine_readable() => {
+            // Same columns as the tracking file
+            let entries = storage::read_all(path)?;
+            let entries = page
+                .select_where(&entries, |entry| has_tag(entry, tag.as_deref()))
+                .into_iter()
+                .map(|(_, entry)| entry)
+                .collect::<Vec<_>>();
+            export::write(&entries, format, &output)?;
+        }
+
+        Subcommand::List {
+            format,
+            output,
+            page,
+            relative,
+            tag,
+        } => {
+            output.check(false)?;
+            let entries = storage::read_all(path)?;
+            let now = OffsetDateTime::now_local()?;
+            // Relative to the day the entries belong to
+            let today = (now - args.midnight_offset).date();
+            let relative_day = |datetime: OffsetDateTime| {
+                locale::relative_day((datetime - args.midnight_offset).date(), today)
+            };
+            let mut table = Table::new(["#", "Project", "Start", "End", "Time", "Tags", "Context"]);
+            table
+                .align([
+                    Alignment::Right,
+                    Alignment::Left,
+                    Alignment::Left,
+                    Alignment::Left,
+                    Alignment::Right,
+                    Alignment::Left,
+                    Alignment::Left,
+                ])
+                .color([None, Some(Color::Hashed), None, None, None, None, None])
+                .max_width(1, NAME_WIDTH)
+                .max_width(5, NAME_WIDTH)
+                .options(format.table_options(options));
+            let mut total = Duration::ZERO;
+            // Entries keep their number, for `temps show`
+            for (i, entry) in page.select_where(&entries, |entry| has_tag(entry, tag.as_deref())) {
+                // Days off last no time
+                let time = if entry.kind == Kind::DayOff {
+                    String::new()
+                } else {
+                    total += entry.duration(now);
+                    duration_to_string(entry.end.unwrap_or(now) - entry.start)?
+                };
+                let (start, end) = if relative {
+                    let start = relative_day(entry.start);
+                    let end = entry.end.map(|end| match relative_day(end) {
+                        // Only the time, when it's the same day
+                        day if day == start => locale::time(end.time()),
+                        day => format!("{} {}", day, locale::time(end.time())),
+                    });
+                    (
+                        format!("{} {}", start, locale::time(entry.start.time())),
+                        end,
+                    )
+                } else {
+                    (
+                        entry.start.format(&Rfc3339)?,
+                        entry.end.map(|end| end.format(&Rfc3339)).transpose()?,
+                    )
+                };
                 table.row([
+                    (i + 1).to_string(),
                     entry.project.clone(),
-                    entry.start.format(&Rfc3339)?,
-                    entry
-                        .end
-                        .as_ref()
-                        .map(|dt| dt.format(&Rfc3339))
-                        .transpose()?
-                        .unwrap_or_else(String::new),
+                    start,
+                    end.unwrap_or_else(|| if relative { "…" } else { "" }.to_owned()),
+                    time,
+                    entry.tags.join(", "),
+                    entry.context.clone().unwrap_or_default(),
                 ]);
             }
+            table.separator();
+            table.total([
+                String::new(),
+                "TOTAL".to_owned(),
+                String::new(),
+                String::new(),
+                duration_to_string(total)?,
+                String::new(),
+                String::new(),
+            ]);
             print!("{}", table);
         }
 
-        Subcommand::Summary { full: true, .. } => {
-            // BTreeMap instead of HashMap so the keys are sorted :>
-            let mut summary = BTreeMap::new();
+        Subcommand::Show { id, last } => {
+            let entries = storage::read_numbered(path)?;
+            if entries.is_empty() {
+                bail!("No entries yet");
+            }
+            let index = match (id, last) {
+                (_, true) => entries.len() - 1,
+                (Some(id), false) if (1..=entries.len()).contains(&id) => id - 1,
+                (Some(id), false) => bail!(
+                    "There's no entry {}, entries are numbered from 1 to {}",
+                    id,
+                    entries.len()
+                ),
+                (None, false) => unreachable!("clap requires an id or --last"),
+            };
+            show::card(path, &entries, index, OffsetDateTime::now_local()?)?;
+        }
 
-            let now = OffsetDateTime::now_local()?;
+        Subcommand::Standup { date } => {
+            standup::standup(path, &config, date, args.midnight_offset)?;
+        }
 
-            // Collect total time on each project
-            for entry in &entries {
-                let total = summary
-                    .entry(entry.project.clone())
-                    .or_insert(Duration::ZERO);
-                *total += entry.end.unwrap_or(now) - entry.start;
+        Subcommand::Log {
+            from,
+            to,
+            by_project,
+            page,
+            relative,
+        } => {
+            let entries = if from.is_some() || to.is_some() {
+                let offset = UtcOffset::current_local_offset()?;
+                let bound = |date: Date| {
+                    date.with_time(Time::MIDNIGHT).assume_offset(offset) + args.midnight_offset
+                };
+                storage::read_range(
+                    path,
+                    from.map_or(OffsetDateTime::UNIX_EPOCH, bound),
+                    to.map_or(OffsetDateTime::now_utc() + 1.days(), |to| {
+                        bound(to + 1.days())
+                    }),
+                    config.storage.index,
+                )?
+            } else {
+                storage::read_all(path)?
+            };
+            let now = OffsetDateTime::now_local()?;
+            if by_project {
+                let activities = summary::activities(&entries, args.midnight_offset, now)
+                    .into_iter()
+                    .filter(|(day, _)| {
+                        from.is_none_or(|from| *day >= from) && to.is_none_or(|to| *day <= to)
+                    })
+                    .collect::<Vec<_>>();
+                let activities = page
+                    .select(&activities)
+                    .into_iter()
+                    .map(|(_, (day, activities))| (day, activities));
+                let today = relative.then(|| (now - args.midnight_offset).date());
+                print!("{}", summary::activity_log(activities, today)?);
+                return Ok(());
             }
-
-            // Display summary as a table
-            let mut table = Table::new(["Project", "Time"]);
-            table.align([Alignment::Left, Alignment::Right]);
-            for (project, duration) in summary {
-                table.row([project, duration_to_string(duration)?]);
+            // Entries belong to the day they started on
+            let mut days = Vec::<(Date, Vec<&Entry>)>::new();
+            for entry in &entries {
+                let day = (entry.start - args.midnight_offset).date();
+                if from.is_some_and(|from| day < from) || to.is_some_and(|to| day > to) {
+                    continue;
+                }
+                match days.last_mut() {
+                    Some((last, entries)) if *last == day => entries.push(entry),
+                    _ => days.push((day, vec![entry])),
+                }
             }
-            print!("{}", table);
 
-            if let Some(last) = &entries.last() {
-                if last.is_ongoing() {
+            for (i, (_, (day, entries))) in page.select(&days).into_iter().enumerate() {
+                if i > 0 {
                     println!();
+                }
+                if relative {
+                    let today = (now - args.midnight_offset).date();
+                    println!("{}", locale::relative_weekday_date(*day, today));
+                } else {
+                    println!("{}", locale::weekday_date(*day));
+                }
+                for entry in entries {
+                    // Start and end times, e.g. `09:00–10:30`
+                    let times_width = 2 * locale::time_width() + 1;
+                    if entry.kind == Kind::DayOff {
+                        println!(
+                            "  {:width$}  {:>7}  {}",
+                            "day off",
+                            "",
+                            entry.project,
+                            width = times_width
+                        );
+                        continue;
+                    }
+                    if entry.kind == Kind::Unscheduled {
+                        println!(
+                            "  {:width$}  {:>7}  {}",
+                            "unscheduled",
+                            duration_to_string(entry.end.unwrap_or(now) - entry.start)?,
+                            entry.project,
+                            width = times_width
+                        );
+                        continue;
+                    }
+
+                    let end = entry
+                        .end
+                        .map_or_else(|| "…".to_owned(), |end| locale::time(end.time()));
+                    let tags = if entry.tags.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" [{}]", entry.tags.join(", "))
+                    };
+                    let context = entry
+                        .context
+                        .as_ref()
+                        .map_or_else(String::new, |context| format!(" @{}", context));
                     println!(
-                        "Ongoing: {} ({})",
-                        last.project,
-                        duration_to_string(now - last.start)?
+                        "  {}–{:width$}  {:>7}  {}{}{}",
+                        locale::time(entry.start.time()),
+                        end,
+                        duration_to_string(entry.end.unwrap_or(now) - entry.start)?,
+                        entry.project,
+                        tags,
+                        context,
+                        width = locale::time_width()
                     );
+                    // Notes go under the project name
+                    for note in &entry.notes {
+                        println!(
+                            "{:indent$}{}  {}",
+                            "",
+                            locale::time(note.at.time()),
+                            note.text,
+                            indent = times_width + 13
+                        );
+                    }
                 }
             }
         }
 
-        // Weekly
-        Subcommand::Summary { weekly: true, .. } => {
-            // BTreeMap instead of HashMap so the keys are sorted :>
-            let mut summary = BTreeMap::<String, [Duration; 7]>::new();
-            let mut daily_total = [Duration::ZERO; 7];
-
+        Subcommand::Projects { sort } => {
+            let entries = storage::read_all(path)?;
             let now = OffsetDateTime::now_local()?;
-            let today = now.date();
-
-            // Collect daily total time on each project
-            for entry in &entries {
-                let start = entry.start - args.midnight_offset;
-                let end = entry.end.unwrap_or(now) - args.midnight_offset;
 
-                // Iterate over every day between `start` and `end`.
-                // `min(6)` ensures that we don't consider start dates beyond one week
-                for delta in (today - end.date()).whole_days() as usize
-                    ..=(today - start.date()).whole_days().min(6) as usize
-                {
-                    let totals = summary.entry(entry.project.clone()).or_default();
+            // Last use and total time of each project
+            let mut projects = BTreeMap::<&str, (Option<OffsetDateTime>, Duration)>::new();
+            for entry in entries.iter().filter(|entry| entry.kind != Kind::DayOff) {
+                let end = entry.end.unwrap_or(now);
+                let (last_used, total) = projects
+                    .entry(&entry.project)
+                    .or_insert((Some(end), Duration::ZERO));
+                *last_used = (*last_used).max(Some(end));
+                *total += entry.duration(now);
+            }
+            // Projects from the config, even if they were never used
+            for project in config.projects.iter().chain(config.budgets.keys()) {
+                projects.entry(project).or_insert((None, Duration::ZERO));
+            }
 
-                    // Duration is min(end, today - delta + 1 day) - max(start, today - delta)
-                    let duration = end
-                        .min(now.replace_time(Time::MIDNIGHT) - (delta as i64 - 1).days())
-                        - start.max(now.replace_time(Time::MIDNIGHT) - (delta as i64).days());
-                    totals[delta] += duration;
-                    daily_total[delta] += duration;
+            let mut projects = projects.into_iter().collect::<Vec<_>>();
+            match sort {
+                ProjectOrder::Recent => {
+                    projects.sort_by_key(|(_, (last_used, _))| Reverse(*last_used))
                 }
+                ProjectOrder::Time => projects.sort_by_key(|(_, (_, total))| Reverse(*total)),
+                ProjectOrder::Name => {}
             }
 
-            println!("Summary for the past week");
-            println!();
-
-            fn week_row<T: std::fmt::Debug>(
-                first: impl Into<T>,
-                rest: impl IntoIterator<Item = T>,
-            ) -> [T; 8] {
-                let mut row = vec![first.into()];
-                row.extend(rest.into_iter());
-                row.try_into().unwrap()
-            }
-
-            // Display summary as a table
-            let headers = week_row(
-                "Project".to_owned(),
-                (0..7)
-                    .rev()
-                    .map(|i| today - Duration::days(i))
-                    .map(|d| d.format(&format_description!("[weekday]")))
-                    .collect::<Result<Vec<_>, _>>()?,
+            // Budgets get a column of their own, if there are any
+            let budgets = !config.budgets.is_empty();
+            let columns = if budgets { 4 } else { 3 };
+            let mut table = Table::new(
+                ["Project", "Last used", "Time", "Budget"]
+                    .into_iter()
+                    .take(columns),
             );
-            let alignments = week_row(Alignment::Left, vec![Alignment::Right; 7]);
-
-            let mut table = Table::<8>::new(headers);
-            table.align(alignments);
-            for (project, durations) in summary {
-                let row = week_row(
-                    project,
-                    durations
+            table
+                .align(
+                    [
+                        Alignment::Left,
+                        Alignment::Left,
+                        Alignment::Right,
+                        Alignment::Right,
+                    ]
+                    .into_iter()
+                    .take(columns),
+                )
+                .color(
+                    [Some(Color::Hashed), None, None, None]
                         .into_iter()
-                        .rev()
-                        .map(|d| duration_to_string(d).expect("could not format duration")),
-                );
-                table.row(row);
+                        .take(columns),
+                )
+                .max_width(0, NAME_WIDTH)
+                .options(options);
+            for (project, (last_used, total)) in projects {
+                let budget = config.budgets.get(project).map(|Goal(budget)| *budget);
+                let mut row = vec![
+                    project.to_owned(),
+                    match last_used {
+                        Some(last_used) => locale::date(last_used.to_offset(now.offset()).date()),
+                        None => "never".to_owned(),
+                    },
+                    duration_to_string(total)?,
+                ];
+                if budgets {
+                    row.push(
+                        budget
+                            .map(duration_to_string)
+                            .transpose()?
+                            .unwrap_or_default(),
+                    );
+                }
+                if budget.is_some_and(|budget| total > budget) {
+                    table.over_budget(row);
+                } else {
+                    table.row(row);
+                }
             }
+            print!("{}", table);
+        }
 
-            table.row(vec![String::new(); 8].try_into().unwrap());
+        Subcommand::Tags => {
+            let entries = storage::read_all(path)?;
+            let now = OffsetDateTime::now_local()?;
 
-            let row = week_row(
-                "TOTAL".to_owned(),
-                daily_total
-                    .into_iter()
-                    .rev()
-                    .map(|d| duration_to_string(d).expect("could not format duration")),
-            );
-            table.row(row);
+            // Number of entries and total time of each tag
+            let mut tags = BTreeMap::<&str, (usize, Duration)>::new();
+            for entry in &entries {
+                for tag in &entry.tags {
+                    let (count, total) = tags.entry(tag).or_insert((0, Duration::ZERO));
+                    *count += 1;
+                    *total += entry.duration(now);
+                }
+            }
 
+            let mut table = Table::new(["Tag", "Entries", "Time"]);
+            table
+                .align([Alignment::Left, Alignment::Right, Alignment::Right])
+                .color([Some(Color::Hashed), None, None])
+                .max_width(0, NAME_WIDTH)
+                .options(options);
+            for (tag, (count, total)) in tags {
+                table.row([
+                    tag.to_owned(),
+                    count.to_string(),
+                    duration_to_string(total)?,
+                ]);
+            }
             print!("{}", table);
+        }
 
+        Subcommand::Search { pattern, regex } => {
+            let entries = storage::read_all(path)?;
+            let pattern = if regex {
+                pattern
+            } else {
+                regex::escape(&pattern)
+            };
+            let pattern = RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .context("Could not parse regular expression")?;
+
+            let now = OffsetDateTime::now_local()?;
+            let mut table = Table::new(["Date", "Start", "End", "Time", "Project", "Tags"]);
+            table
+                .color([None, None, None, None, Some(Color::Hashed), None])
+                .max_width(4, NAME_WIDTH)
+                .max_width(5, NAME_WIDTH)
+                .options(options);
+            table.align([
+                Alignment::Left,
+                Alignment::Left,
+                Alignment::Left,
+                Alignment::Right,
+                Alignment::Left,
+                Alignment::Left,
+            ]);
+            let mut total = Duration::ZERO;
+            let mut matches = 0;
+            for entry in entries.iter().filter(|entry| entry.matches(&pattern)) {
+                let duration = entry.end.unwrap_or(now) - entry.start;
+                total += entry.duration(now);
+                matches += 1;
+                table.row([
+                    locale::date(entry.start.date()),
+                    locale::time(entry.start.time()),
+                    entry
+                        .end
+                        .map_or_else(|| "…".to_owned(), |end| locale::time(end.time())),
+                    duration_to_string(duration)?,
+                    entry.project.clone(),
+                    entry.tags.join(", "),
+                ]);
+            }
+
+            if matches == 0 {
+                bail!("No matching entries");
+            }
+
+            print!("{}", table);
             println!();
             println!(
-                "Weekly total: {}",
-                duration_to_string(daily_total.into_iter().sum())?
+                "{} matching entries, {} in total",
+                matches,
+                duration_to_string(total)?
             );
+        }
 
-            if let Some(last) = &entries.last() {
-                if last.is_ongoing() {
-                    println!();
-                    println!(
-                        "Ongoing: {} ({})",
-                        last.project,
-                        duration_to_string(now - last.start)?
+        Subcommand::Today => {
+            let now = OffsetDateTime::now_local()?;
+            let today = (now - args.midnight_offset).date();
+            let since =
+                today.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + args.midnight_offset;
+            let entries = storage::read_since(path, since)?;
+            let entries = excluding(&entries, &config.summary.exclude);
+            let (totals, ongoing) = totals_of(&entries, args.midnight_offset, now, &[]);
+            let switches = summary::switches(&entries, args.midnight_offset);
+            summary::daily(
+                &totals,
+                ongoing.as_ref(),
+                today,
+                today,
+                switches.get(&today).copied().unwrap_or(0),
+                options,
+            )?;
+            println!();
+            viz::timeline(
+                &entries,
+                today,
+                args.midnight_offset,
+                config.viz.theme(Glyphs::UNICODE).glyphs,
+            )?;
+        }
+
+        Subcommand::Week => {
+            let now = OffsetDateTime::now_local()?;
+            let today = (now - args.midnight_offset).date();
+            let since = today.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                + args.midnight_offset
+                - 6.days();
+            let entries = storage::read_since(path, since)?;
+            let (totals, ongoing) =
+                totals_of(&entries, args.midnight_offset, now, &config.summary.exclude);
+            let days_off = days_off(&config.holidays, &entries, args.midnight_offset)?;
+            summary::weekly(
+                &totals,
+                ongoing.as_ref(),
+                None,
+                today,
+                config
+                    .summary
+                    .daily_target
+                    .as_ref()
+                    .map(|target| DailyTarget {
+                        target: target.0,
+                        days_off: &days_off,
+                    }),
+                GroupBy::Project,
+                options,
+            )?
+        }
+
+        Subcommand::Summary {
+            full,
+            weekly,
+            daily,
+            yearly,
+            last,
+            week,
+            week_of,
+            mut exclude,
+            context,
+            tag,
+            by,
+            format,
+            output,
+        } => {
+            output.check(format.is_machine_readable())?;
+            if by == GroupBy::Tag {
+                if !weekly && week.is_none() && week_of.is_none() {
+                    bail!(
+                        "--by tag only works with weekly summaries (--weekly, --week or --week-of)"
                     );
                 }
+                if format.is_machine_readable() {
+                    bail!("--by tag only works with text and Markdown summaries");
+                }
+            }
+            let options = format.table_options(options);
+            // Calendar weeks start on Monday
+            let week = week.or_else(|| {
+                week_of.map(|date| date - (date.weekday().number_days_from_monday() as i64).days())
+            });
+            exclude.extend(config.summary.exclude);
+            let now = OffsetDateTime::now_local()?;
+            let today = (now - args.midnight_offset).date();
+            let midnight = summary::start_of_day(today, now, args.midnight_offset);
+
+            let (totals, ongoing, switches) = if by == GroupBy::Tag {
+                // Spans don't have tags, so whole entries are read
+                let entries = match week {
+                    Some(week) => {
+                        let start = week.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                            + args.midnight_offset;
+                        storage::read_range(path, start, start + 7.days(), config.storage.index)?
+                    }
+                    None => storage::read_since(path, midnight - 6.days())?,
+                };
+                let entries = entries
+                    .into_iter()
+                    .filter(|entry| context.is_none() || entry.context == context)
+                    .filter(|entry| has_tag(entry, tag.as_deref()))
+                    .filter(|entry| !exclude.contains(&entry.project))
+                    .collect::<Vec<_>>();
+                // Entries with several tags count for each of them
+                let mut totals = DailyTotals::new();
+                for entry in &entries {
+                    for tag in &entry.tags {
+                        let span = storage::Span {
+                            project: tag,
+                            ..entry.into()
+                        };
+                        summary::add_to_daily_totals(&mut totals, &span, args.midnight_offset, now);
+                    }
+                }
+                let ongoing = entries.into_iter().last().filter(Entry::is_ongoing);
+                (totals, ongoing, BTreeMap::new())
+            } else if config.storage.cache
+                && context.is_none()
+                && tag.is_none()
+                && (full || yearly.is_some() || week.is_some())
+            {
+                // Long ranges are computed from cached daily totals, which
+                // don't know about contexts or tags
+                let (mut totals, ongoing) = cache::daily_totals(path, args.midnight_offset, now)?;
+                for projects in totals.values_mut() {
+                    projects.retain(|project, _| !exclude.contains(project));
+                }
+                let ongoing = ongoing.filter(|entry| !exclude.contains(&entry.project));
+                (totals, ongoing, BTreeMap::new())
+            } else {
+                // Entries are aggregated as they're read, without keeping them
+                let mut totals = DailyTotals::new();
+                let mut switches = summary::Switches::default();
+                let mut ongoing = false;
+                let mut add = |span: storage::Span| {
+                    if context.is_some() && span.context != context.as_deref()
+                        || tag.as_ref().is_some_and(|tag| !span.has_tag(tag))
+                        || exclude.iter().any(|project| project == span.project)
+                    {
+                        return;
+                    }
+                    ongoing = span.end.is_none();
+                    switches.add(&span, args.midnight_offset);
+                    summary::add_to_daily_totals(&mut totals, &span, args.midnight_offset, now);
+                };
+                // Summaries of the past few days only need the end of the file
+                let index = config.storage.index;
+                if full {
+                    storage::for_each_span(path, &mut add)?;
+                } else if let Some(year) = yearly {
+                    let year = year.unwrap_or(now.year());
+                    let start = |year| {
+                        Date::from_ordinal_date(year, 1).map(|date| {
+                            date.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                                + args.midnight_offset
+                        })
+                    };
+                    storage::for_each_span_in(
+                        path,
+                        start(year)?,
+                        start(year + 1)?,
+                        index,
+                        &mut add,
+                    )?;
+                } else if let Some(week) = week {
+                    let start = week.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                        + args.midnight_offset;
+                    storage::for_each_span_in(path, start, start + 7.days(), index, &mut add)?;
+                } else if weekly {
+                    storage::for_each_span_since(path, midnight - 6.days(), &mut add)?;
+                } else if let Some(days) = last {
+                    storage::for_each_span_since(
+                        path,
+                        midnight - (days as i64 - 1).days(),
+                        &mut add,
+                    )?;
+                } else if let Some(Some(day)) = daily {
+                    let start = day.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                        + args.midnight_offset;
+                    storage::for_each_span_in(path, start, start + 1.days(), index, &mut add)?;
+                } else {
+                    storage::for_each_span_since(path, midnight, &mut add)?;
+                }
+                // The ongoing entry is the last one of the file
+                let ongoing = if ongoing {
+                    storage::read_last(path)?.filter(|entry| entry.is_ongoing())
+                } else {
+                    None
+                };
+                (totals, ongoing, switches.counts)
+            };
+
+            if format.is_machine_readable() {
+                // Periods are days (`YYYY-MM-DD`), months (`YYYY-MM`) or
+                // ranges of days (`YYYY-MM-DD/YYYY-MM-DD`)
+                let records = if full {
+                    let (first, last) = (totals.keys().next(), totals.keys().next_back());
+                    let period = first
+                        .zip(last)
+                        .map(|(first, last)| format!("{}/{}", first, last))
+                        .unwrap_or_default();
+                    export::totals(&totals, |_| Some(period.clone()))
+                } else if let Some(year) = yearly {
+                    let year = year.unwrap_or(now.year());
+                    export::totals(&totals, |date| {
+                        (date.year() == year).then(|| format!("{}-{:02}", year, date.month() as u8))
+                    })
+                } else if let Some(days) = last {
+                    let first = today - (days as i64 - 1).days();
+                    let period = format!("{}/{}", first, today);
+                    export::totals(&totals, |date| {
+                        (first..=today).contains(&date).then(|| period.clone())
+                    })
+                } else if weekly || week.is_some() {
+                    let last = week.map_or(today, |monday| monday + 6.days());
+                    export::totals(&totals, |date| {
+                        (last - 6.days()..=last)
+                            .contains(&date)
+                            .then(|| date.to_string())
+                    })
+                } else {
+                    let day = daily.flatten().unwrap_or(today);
+                    export::totals(&totals, |date| (date == day).then(|| date.to_string()))
+                };
+                export::write(&records, format, &output)?;
+            } else if full {
+                summary::full(&totals, ongoing.as_ref(), options)?;
+            } else if let Some(year) = yearly {
+                let year = year.unwrap_or(now.year());
+                summary::yearly(&totals, ongoing.as_ref(), year, options)?;
+            } else if let Some(days) = last {
+                summary::last(&totals, ongoing.as_ref(), days, today, options)?;
+            } else if weekly || week.is_some() {
+                // Tags overlap, so their totals aren't the time worked each day
+                let target = config
+                    .summary
+                    .daily_target
+                    .as_ref()
+                    .map(|target| target.0)
+                    .filter(|_| by == GroupBy::Project);
+                // Days off are only needed to mark the days short of the target
+                let days_off = match target {
+                    Some(_) => {
+                        let last = week.map_or(today, |monday| monday + 6.days());
+                        let start =
+                            summary::start_of_day(last - 6.days(), now, args.midnight_offset);
+                        let entries = storage::read_range(
+                            path,
+                            start,
+                            start + 7.days(),
+                            config.storage.index,
+                        )?;
+                        days_off(&config.holidays, &entries, args.midnight_offset)?
+                    }
+                    None => BTreeSet::new(),
+                };
+                summary::weekly(
+                    &totals,
+                    ongoing.as_ref(),
+                    week,
+                    today,
+                    target.map(|target| DailyTarget {
+                        target,
+                        days_off: &days_off,
+                    }),
+                    by,
+                    options,
+                )?;
+            } else {
+                let day = daily.flatten().unwrap_or(today);
+                let switches = switches.get(&day).copied().unwrap_or(0);
+                summary::daily(&totals, ongoing.as_ref(), day, today, switches, options)?;
             }
         }
 
-        // Daily summary
-        Subcommand::Summary { .. } => {
-            // BTreeMap instead of HashMap so the keys are sorted :>
-            let mut summary = BTreeMap::new();
-            let mut daily_total = Duration::ZERO;
+        Subcommand::Trend {
+            project,
+            weeks,
+            ascii,
+        } => {
+            let now = OffsetDateTime::now_local()?;
+            let today = (now - args.midnight_offset).date();
+            // Calendar weeks start on Monday
+            let first = today
+                - (today.weekday().number_days_from_monday() as i64).days()
+                - (weeks as i64 - 1).weeks();
+            let (mut totals, _) = if config.storage.cache {
+                cache::daily_totals(path, args.midnight_offset, now)?
+            } else {
+                let start = first.with_time(Time::MIDNIGHT).assume_offset(now.offset())
+                    + args.midnight_offset;
+                let mut totals = DailyTotals::new();
+                storage::for_each_span_since(path, start, |span| {
+                    summary::add_to_daily_totals(&mut totals, &span, args.midnight_offset, now)
+                })?;
+                (totals, None::<Entry>)
+            };
+            for projects in totals.values_mut() {
+                projects.retain(|name, _| match &project {
+                    Some(project) => name == project,
+                    None => !config.summary.exclude.contains(name),
+                });
+            }
+            summary::trend(&totals, project.as_deref(), first, weeks, ascii, options)?;
+        }
 
+        Subcommand::Balance {
+            since,
+            format,
+            output,
+        } => {
+            output.check(format.is_machine_readable())?;
+            let options = format.table_options(options);
+            let weekly_target = config
+                .balance
+                .weekly_target
+                .context("No weekly target, please set `weekly-target` in the `[balance]` section of the config file")?
+                .0;
+            let since = since.or(config.balance.since).context(
+                "Cannot infer the first day of the balance, please specify it with `--since`",
+            )?;
             let now = OffsetDateTime::now_local()?;
-            let today = now.date();
+            let exclude = config.summary.exclude;
+
+            // Days off are recorded in the tracking file, so it's read anyway
+            let start =
+                since.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + args.midnight_offset;
+            let entries = storage::read_since(path, start)?;
+            let (totals, ongoing) = totals_of(&entries, args.midnight_offset, now, &exclude);
+
+            let days_off = days_off(&config.holidays, &entries, args.midnight_offset)?;
+            if format.is_machine_readable() {
+                let weeks =
+                    summary::balance_weeks(&totals, since, now.date(), weekly_target, &days_off);
+                export::write(&export::balance(&weeks), format, &output)?;
+            } else {
+                summary::balance(
+                    &totals,
+                    ongoing.as_ref(),
+                    since,
+                    weekly_target,
+                    &days_off,
+                    options,
+                )?;
+            }
+        }
 
-            // Collect total time on each project
-            for entry in &entries {
-                // Actual start time is max(today at midnight, start),
-                // in case the entry started the day before
-                let start =
-                    (entry.start - args.midnight_offset).max(now.replace_time(Time::MIDNIGHT));
-                let end = entry.end.unwrap_or(now) - args.midnight_offset;
+        Subcommand::Report {
+            init_template: Some(starter),
+            ..
+        } => {
+            let path = template::init(&templates_dir(&args.config), starter)?;
+            println!("Wrote {}, edit it to your liking.", path.display());
+        }
 
-                if end.date() == today {
-                    let total = summary.entry(entry.project.clone()).or_default();
+        Subcommand::Report {
+            days,
+            group_by,
+            email,
+            template,
+            init_template: None,
+            notes,
+            rounding: Rounding { round },
+        } => {
+            let now = OffsetDateTime::now_local()?;
+            let range = days.range(now, args.midnight_offset)?;
+            let Range { first, last, .. } = range;
+            let entries = range.read(path, config.storage.index)?;
+            let (mut totals, _) =
+                totals_of(&entries, args.midnight_offset, now, &config.summary.exclude);
+            totals.retain(|day, _| range.contains(*day));
+            // Ranges round the weeks or months they show, the rest shows days
+            let mut rounded = totals.clone();
+            rounding::round_daily_totals(&mut rounded, round);
+            let days_off = days_off(&config.holidays, &entries, args.midnight_offset)?;
+            let daily_target = config
+                .summary
+                .daily_target
+                .as_ref()
+                .map(|target| DailyTarget {
+                    target: target.0,
+                    days_off: &days_off,
+                });
+            let report = |options| match days.from {
+                Some(_) => summary::range_report(
+                    &totals,
+                    first,
+                    last,
+                    group_by.unwrap_or_default(),
+                    round,
+                    options,
+                ),
+                None => summary::weekly_report(
+                    &rounded,
+                    range.week,
+                    (now - args.midnight_offset).date(),
+                    daily_target,
+                    GroupBy::Project,
+                    options,
+                ),
+            };
+            let activity = if notes {
+                let activities = summary::activities(
+                    &excluding(&entries, &config.summary.exclude),
+                    args.midnight_offset,
+                    now,
+                )
+                .into_iter()
+                .filter(|(day, _)| range.contains(*day))
+                .collect::<BTreeMap<_, _>>();
+                Some(summary::activity_log(&activities, None)?)
+            } else {
+                None
+            };
 
-                    let duration = end - start;
-                    *total += duration;
-                    daily_total += duration;
-                }
+            if let Some(name) = template {
+                let entries = excluding(&entries, &config.summary.exclude);
+                let context = template::Context::new(
+                    &entries,
+                    &rounded,
+                    first,
+                    last,
+                    args.midnight_offset,
+                    now,
+                    &config.invoice,
+                )?;
+                print!(
+                    "{}",
+                    template::render(
+                        &templates_dir(&args.config),
+                        &name,
+                        &context,
+                        &config.invoice
+                    )?
+                );
+            } else if email.is_empty() {
+                let mut report = report(options)?;
+                report.activity = activity;
+                print!("{}", report);
+            } else {
+                // Emails are read elsewhere, so colors make no sense
+                let options = Options {
+                    colored: false,
+                    ..options
+                };
+                let mut report = report(options)?;
+                report.activity = activity;
+                mail::send(&config.email, &email, &report)?;
+                println!("Sent the report to {}.", email.join(", "));
             }
+        }
 
-            println!(
-                "Summary for today ({})",
-                today.format(&format_description!(
-                    "[month repr:short] [day padding:zero]"
-                ))?
-            );
-            println!();
-
-            // Display summary as a table
-            let mut table = Table::new(["Project", "Time"]);
-            table.align([Alignment::Left, Alignment::Right]);
-            for (project, duration) in summary {
-                table.row([project, duration_to_string(duration)?]);
+        Subcommand::Invoice {
+            client,
+            days,
+            template,
+            rounding: Rounding { round },
+            force,
+            dry_run,
+        } => {
+            let now = OffsetDateTime::now_local()?;
+            let today = (now - args.midnight_offset).date();
+            let range = days.range(now, args.midnight_offset)?;
+            let Range { first, last, .. } = range;
+
+            let sub_projects = format!("{}/", client);
+            let entries = range
+                .read(path, config.storage.index)?
+                .into_iter()
+                .filter(|entry| entry.project == client || entry.project.starts_with(&sub_projects))
+                // Ongoing entries aren't over yet, and days off aren't billed
+                .filter(|entry| !entry.is_ongoing() && entry.kind != Kind::DayOff)
+                // Entries from the day before that end during the first day were
+                // invoiced with it
+                .filter(|entry| range.contains((entry.start - args.midnight_offset).date()))
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                bail!(
+                    "Nothing to invoice for '{}' from {} to {}",
+                    client,
+                    locale::date(first),
+                    locale::date(last)
+                );
+            }
+            let invoiced = entries
+                .iter()
+                .filter_map(invoice::invoiced)
+                .collect::<Vec<_>>();
+            if !invoiced.is_empty() && !force {
+                let numbers = invoiced
+                    .iter()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(|number| number.to_string())
+                    .collect::<Vec<_>>();
+                bail!(
+                    "{} of these entries are already invoiced (invoice {}), pass --force to invoice them again",
+                    invoiced.len(),
+                    numbers.join(", ")
+                );
             }
-            table.row(["", ""]);
-            table.row(["TOTAL".to_owned(), duration_to_string(daily_total)?]);
-            print!("{}", table);
 
-            if let Some(last) = &entries.last() {
-                if last.is_ongoing() {
-                    println!();
-                    println!(
-                        "Ongoing: {} ({})",
-                        last.project,
-                        duration_to_string(now - last.start)?
-                    );
+            let mut totals = summary::daily_totals(&entries, args.midnight_offset, now);
+            totals.retain(|day, _| range.contains(*day));
+            rounding::round_daily_totals(&mut totals, round);
+            let number = invoice::next_number(path)?;
+            // Same entries, down to their notes, in case two start at once
+            let mut all = storage::read_all(path)?;
+            let old = all.clone();
+            for entry in &mut all {
+                if entries.contains(entry) {
+                    invoice::mark(entry, number);
                 }
             }
+            locked::check(locked::changed(&old, &all), force)?;
+            let mut context = template::Context::new(
+                &entries,
+                &totals,
+                first,
+                last,
+                args.midnight_offset,
+                now,
+                &config.invoice,
+            )?;
+            context.invoice(number, today, &client, &config.invoice);
+            print!(
+                "{}",
+                template::render(
+                    &templates_dir(&args.config),
+                    &template,
+                    &context,
+                    &config.invoice
+                )?
+            );
+
+            if dry_run {
+                eprintln!("Would record invoice {} (dry run).", number);
+            } else {
+                // Recorded first, so that its number is never used twice
+                invoice::record(
+                    path,
+                    &invoice::Record {
+                        number,
+                        date: today.to_string(),
+                        client: client.clone(),
+                        from: first.to_string(),
+                        to: last.to_string(),
+                        hours: context.hours(),
+                        amount: context.amount(),
+                        currency: context.currency().map(str::to_owned),
+                    },
+                )?;
+                storage::write_back(path, &all)?;
+                eprintln!(
+                    "Recorded invoice {} for '{}', covering {} entries.",
+                    number,
+                    client,
+                    entries.len()
+                );
+            }
         }
 
-        Subcommand::Edit => {
+        Subcommand::Export {
+            format: timesheet::Format::TimesheetCsv,
+            days,
+            rounding: Rounding { round },
+            output,
+        } => {
+            let now = OffsetDateTime::now_local()?;
+            let range = days.range(now, args.midnight_offset)?;
+            let entries = range
+                .read(path, config.storage.index)?
+                .into_iter()
+                // Days off aren't worked time
+                .filter(|entry| entry.kind != Kind::DayOff)
+                .collect::<Vec<_>>();
+            let (mut totals, _) =
+                totals_of(&entries, args.midnight_offset, now, &config.summary.exclude);
+            totals.retain(|day, _| range.contains(*day));
+            rounding::round_daily_totals(&mut totals, round);
+            timesheet::write(&totals, &config.timesheet, &output)?;
+        }
+
+        Subcommand::Edit { format, yes, force } => {
             let editor = env::var("EDITOR")
                 .expect("no default editor, set the $EDITOR environment variable");
+            if format != EditFormat::Tsv {
+                return edit::edit(path, format, &editor, yes, force, options.colored);
+            }
+            let old = storage::read_all(path)?;
             Command::new(&editor)
-                .arg(&args.temps_file)
+                .arg(path)
                 .status()
                 .unwrap_or_else(|_| panic!("could not run editor '{}'", editor));
+            // Offsets may have changed anywhere in the file
+            index::invalidate(path)?;
+            let new = storage::read_all(path)?;
+            if let Err(err) = locked::check(locked::changed(&old, &new), force) {
+                // The file was changed in place, so it's put back as it was
+                let edited = paths::backup(path, OffsetDateTime::now_local()?);
+                paths::create_parent(&edited).context("Could not create state directory")?;
+                fs::copy(path, &edited).context("Could not keep the edited tracking file")?;
+                storage::write_back(path, &old)?;
+                bail!(
+                    "{}; the tracking file was restored, and your version copied to {}",
+                    err,
+                    edited.display()
+                );
+            }
         }
 
-        Subcommand::Visualize { date } => {
-            // TODO a possibly more elegant way of doing all this is to use a sort of
-            //   hash map or something, which can be queried for each slot.  Then, we
-            //   iterate from the first slot we care about (i.e., slightly before the
-            //   first project slot), and query two slots at a time, displaying them
-            //   if there's a project.  This would also make it easier to scale this to
-            //   multiple projects.
+        Subcommand::Lock { before, unlock } => {
+            let mut entries = storage::read_all(path)?;
+            let changed = locked::set(&mut entries, before, args.midnight_offset, !unlock);
+            let verb = if unlock { "unlock" } else { "lock" };
+            if changed == 0 {
+                eprintln!(
+                    "No entries from before {} to {}.",
+                    locale::date(before),
+                    verb
+                );
+            } else {
+                storage::write_back(path, &entries)?;
+                eprintln!(
+                    "{}ed {} entries from before {}.",
+                    if unlock { "Unlock" } else { "Lock" },
+                    changed,
+                    locale::date(before)
+                );
+            }
+        }
 
+        Subcommand::Visualize {
+            date,
+            days,
+            from,
+            to,
+            project,
+            tag,
+            ascii,
+            fine,
+            text,
+            window,
+            with_summary,
+            format,
+            output,
+        } => {
+            output.check(format == VizFormat::Json)?;
             let now = OffsetDateTime::now_local()?;
-            let today = now.date();
+            let today = (now - args.midnight_offset).date();
+            let (first, last) = match from {
+                Some(from) => (from, to.unwrap_or(today)),
+                None => {
+                    let last = date.unwrap_or(today);
+                    (last - (days as i64 - 1).days(), last)
+                }
+            };
+            if first > last {
+                bail!("The first day to show is after the last one");
+            }
+            let dates = iter::successors(Some(first), |date| date.next_day())
+                .take_while(|date| *date <= last)
+                .collect::<Vec<_>>();
 
-            let date = date
-                .unwrap_or(today)
-                .with_time(Time::MIDNIGHT)
-                .assume_offset(now.offset());
-            let next_date = date + Duration::days(1);
+            let bound = |date: Date| {
+                date.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + args.midnight_offset
+            };
+            let mut entries = storage::read_range(
+                path,
+                bound(first),
+                bound(last) + 1.days(),
+                config.storage.index,
+            )?;
+            // Other entries are left blank
+            entries.retain(|entry| {
+                project
+                    .as_ref()
+                    .is_none_or(|project| entry.project == *project)
+                    && tag.as_ref().is_none_or(|tag| entry.tags.contains(tag))
+            });
+            // ASCII wins over the configured glyphs
+            let theme = if ascii {
+                Theme {
+                    glyphs: Glyphs::ASCII,
+                    ..config.viz.theme(Glyphs::ASCII)
+                }
+            } else {
+                config.viz.theme(Glyphs::UNICODE)
+            };
+            let theme = Theme {
+                colored: options.colored,
+                ..theme
+            };
+            if format == VizFormat::Json {
+                viz::json(&entries, &dates, args.midnight_offset, &output)?
+            } else if text {
+                viz::intervals(&entries, &dates, args.midnight_offset, theme.glyphs)?
+            } else {
+                let resolution = if fine {
+                    Resolution::Fine
+                } else {
+                    Resolution::Quarter
+                };
+                viz::visualize(
+                    &entries,
+                    &dates,
+                    args.midnight_offset,
+                    window,
+                    theme,
+                    resolution,
+                )?
+            }
 
-            let mut slots = vec![];
-            let mut previous_end = None;
+            if with_summary {
+                let (totals, _) =
+                    totals_of(&entries, args.midnight_offset, now, &config.summary.exclude);
+                println!();
+                summary::days(&totals, first, last, options)?;
+            }
+        }
 
-            for entry in &entries {
-                let start = entry.start;
-                let end = entry.end.unwrap_or(now);
+        Subcommand::Init => unreachable!("handled before reading the config"),
 
-                // Does the entry overlap with today?
-                if start < next_date && end >= date {
-                    // Convert start/end to quarter-hours
-                    let s = ((start.max(date).time() - Time::MIDNIGHT).whole_minutes() as f32 / 15.)
-                        .round() as i64;
-                    let e = ((end.min(next_date).time() - Time::MIDNIGHT).whole_minutes() as f32
-                        / 15.)
-                        .round() as i64;
-                    if s == e {
-                        // Skip very short slots
-                        continue;
+        Subcommand::AutoSwitch { dir } => {
+            if let Some(project) = autoswitch::project(&dir)? {
+                let now = OffsetDateTime::now_local()?;
+                let ongoing = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+                let cooldown = config.auto_switch.cooldown.0;
+                match ongoing {
+                    Some(ongoing) if ongoing.project == project => {}
+                    Some(ongoing) if now - ongoing.start < cooldown => {
+                        eprintln!(
+                            "Not switching to '{}', '{}' started less than {} ago.",
+                            project,
+                            ongoing.project,
+                            duration_to_string(cooldown)?
+                        );
                     }
+                    _ => switch_to(path, &config, project, now)?,
+                }
+            }
+        }
 
-                    // Prepend empty slots before the first project slot
-                    // We round at a half hour, that way the time is displayed properly
-                    if previous_end.is_none() {
-                        previous_end = Some((s / 8) * 8 - 2);
-                    }
+        Subcommand::Watch { dry_run } => watch::run(path, &config, dry_run)?,
 
-                    // Fill with empty slots since last entry
-                    if let Some(previous_end) = previous_end {
-                        slots.extend((previous_end..s).into_iter().map(|i| (i, None)));
-                    }
-                    previous_end = Some(e);
+        Subcommand::ShellInit { shell, auto_switch } => {
+            print!("{}", shell::script(shell, auto_switch));
+        }
+
+        Subcommand::Doctor => doctor::run(path, &args.config)?,
+
+        Subcommand::GenMan => unreachable!("handled before reading the config"),
 
-                    // Fill with project slots for the duration of the entry
-                    slots.extend((s..e).into_iter().map(|i| (i, Some(&entry.project))));
+        Subcommand::Completions { action } => match action {
+            CompletionsAction::Install { .. } => {
+                unreachable!("handled before reading the config")
+            }
+            CompletionsAction::Projects => {
+                let entries = storage::read_all(path)?;
+                let projects = entries
+                    .iter()
+                    .filter(|entry| entry.kind != Kind::DayOff)
+                    .map(|entry| &entry.project)
+                    .chain(&config.projects)
+                    .collect::<BTreeSet<_>>();
+                for project in projects {
+                    println!("{}", project);
                 }
             }
+        },
+
+        Subcommand::Merge {
+            files,
+            shifts,
+            dry_run,
+            yes,
+        } => {
+            merge::merge(path, &files, &shifts, dry_run, yes, options.colored)?;
+        }
 
-            // Add one or two empty slots at the end if we're close to a two-hour mark
-            // This makes the display slightly prettier :>
-            if let Some((last, _)) = slots.last() {
-                let last = *last; // Otherwise rustc says we can't mutate `slots` :<
-                if last % 8 >= 6 {
-                    slots.extend(
-                        ((last + 1)..=(last / 8 + 1) * 8)
-                            .into_iter()
-                            .map(|i| (i, None)),
-                    );
-                }
+        Subcommand::Prune {
+            before,
+            project,
+            dry_run,
+            yes,
+        } => {
+            prune::prune(
+                path,
+                before,
+                project.as_deref(),
+                args.midnight_offset,
+                dry_run,
+                yes,
+                options,
+            )?;
+        }
+
+        Subcommand::Paths => {
+            let paths = [
+                ("Tracking file", path.to_owned()),
+                ("Configuration", args.config.clone()),
+                ("Templates", templates_dir(&args.config)),
+                ("Index", paths::index(path)),
+                ("Daily totals", paths::daily_totals(path)),
+                ("Snapshot", paths::snapshot(path)),
+                ("Invoice ledger", paths::invoices(path)),
+                ("Ongoing entry", paths::ongoing(path)),
+                ("Cancelled entry", paths::cancelled(path)),
+                ("Reported goals", paths::reported_goals(path)),
+                ("Lock", paths::lock(path)),
+            ];
+            for (name, path) in paths {
+                println!("{:17}{}", name, path.display());
+            }
+        }
+
+        Subcommand::Where => settings::print(
+            path,
+            args.temps_file.as_deref(),
+            &args.config,
+            &config,
+            args.midnight_offset,
+            args.table_style,
+        )?,
+
+        Subcommand::Retag {
+            glob,
+            regex,
+            set_tags,
+            unset_tags,
+            rename,
+            force,
+            dry_run,
+            yes,
+        } => {
+            let old = storage::read_all(path)?;
+            let mut entries = old.clone();
+            if set_tags.is_empty() && unset_tags.is_empty() && rename.is_none() {
+                bail!("Nothing to do, pass --set-tag, --unset-tag or --rename");
             }
 
-            let mut previous_project = None;
-            let times_width = 6;
-            let width = 8;
-            for chunks in slots.chunks(2) {
-                let i = chunks[0].0;
-                // Display the time every two hours
-                if i % 8 == 0 {
-                    print!(
-                        "{:width$} ",
-                        (Time::MIDNIGHT + (i * 15).minutes())
-                            .format(&format_description!("[hour]:[minute]"))?,
-                        width = times_width - 1
-                    );
-                } else if i % 8 == 6 {
-                    print!("{}", LOWER_BORDER.to_string().repeat(times_width));
-                } else {
-                    print!("{}", " ".repeat(times_width));
+            let pattern = match (glob, regex) {
+                (Some(glob), _) => glob_to_regex(&glob)?,
+                (None, Some(regex)) => {
+                    Regex::new(&regex).context("Could not parse regular expression")?
                 }
+                (None, None) => unreachable!("clap requires either --match or --regex"),
+            };
 
-                // Display the current two slots with half-blocks
-                match chunks {
-                    &[(_, None), (_, None)] | &[(_, None)] => {
-                        previous_project = None;
-                    }
-                    &[(_, None), (_, Some(p1))] => {
-                        print!("{}", LOWER_HALF_BLOCK.to_string().repeat(width));
-                        print!(" {}", p1);
-                        previous_project = Some(p1);
-                    }
-                    &[(_, Some(p0)), (_, None)] | &[(_, Some(p0))] => {
-                        print!("{}", UPPER_HALF_BLOCK.to_string().repeat(width));
-                        if previous_project != Some(p0) {
-                            print!(" {}", p0);
-                        }
-                        previous_project = None;
-                    }
-                    &[(_, Some(p0)), (_, Some(p1))] => {
-                        print!("{}", FULL_BLOCK.to_string().repeat(width));
-                        if previous_project != Some(p0) {
-                            print!(" {}", p0);
-                            if p0 != p1 {
-                                print!(" / {}", p1);
-                            }
-                        } else if p0 != p1 {
-                            print!(" {}", p1);
-                        }
-                        previous_project = Some(p1);
-                    }
-                    _ => unreachable!(),
+            let mut table = Table::new(["Start", "Project", "Tags"]);
+            table.options(options);
+            let mut updated = 0;
+            for entry in entries.iter_mut() {
+                if !pattern.is_match(&entry.project) {
+                    continue;
                 }
-                println!();
+
+                let old_project = entry.project.clone();
+                let old_tags = entry.tags.join(", ");
+                if let Some(rename) = &rename {
+                    entry.project = pattern.replace(&old_project, rename.as_str()).into_owned();
+                }
+                for tag in &set_tags {
+                    entry.add_tag(tag);
+                }
+                for tag in &unset_tags {
+                    entry.remove_tag(tag);
+                }
+                let new_tags = entry.tags.join(", ");
+
+                if entry.project == old_project && new_tags == old_tags {
+                    continue;
+                }
+                updated += 1;
+
+                // Display "old -> new" for every column that changed
+                let change = |old: String, new: String| {
+                    let or_none = |s: String| if s.is_empty() { "(none)".to_owned() } else { s };
+                    if old == new {
+                        new
+                    } else {
+                        format!("{} -> {}", or_none(old), or_none(new))
+                    }
+                };
+                table.row([
+                    entry.start.format(&Rfc3339)?,
+                    change(old_project, entry.project.clone()),
+                    change(old_tags, new_tags),
+                ]);
+            }
+
+            if updated == 0 {
+                eprintln!("No entries to update.");
+                return Ok(());
+            }
+
+            locked::check(locked::changed(&old, &entries), force)?;
+            print!("{}", table);
+            println!();
+            if dry_run {
+                print!("{}", diff::unified(path, &old, &entries, options.colored)?);
+                eprintln!("Would update {} entries (dry run).", updated);
+            } else if diff::confirm(path, &old, &entries, yes, options.colored)? {
+                eprintln!("Updated {} entries.", updated);
             }
         }
     }
 
+    if check_goals {
+        goals::check(path, &config.goals, args.midnight_offset);
+    }
+    Ok(())
+}
+
+/// Stop the ongoing entry, if any, and start tracking a project from some time.
+///
+/// The new entry never starts before the ongoing one, so they can't overlap.
+fn switch_to(path: &Path, config: &Config, project: String, from: OffsetDateTime) -> Result<()> {
+    let ongoing = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+    let from = ongoing
+        .as_ref()
+        .map_or(from, |ongoing| from.max(ongoing.start));
+    let mut entry = Entry::start_from(project, from, false)?;
+    entry.context = detect_context(config);
+    entry.source = config.storage.source();
+    if let Some(mut ongoing) = ongoing {
+        ongoing.stop_at(from, false)?;
+        eprintln!("Stopped '{}'.", ongoing.project);
+        eprintln!("Started '{}'.", entry.project);
+        storage::replace_last(path, &[ongoing.clone(), entry.clone()])?;
+        mqtt::publish(&config.mqtt, Event::Stop, &ongoing);
+    } else {
+        eprintln!("Started '{}'.", entry.project);
+        storage::append(path, &entry)?;
+    }
+    mqtt::publish(&config.mqtt, Event::Start, &entry);
     Ok(())
 }
 
+/// Check whether an entry has a tag, if one is asked for.
+fn has_tag(entry: &Entry, tag: Option<&str>) -> bool {
+    tag.is_none_or(|tag| entry.tags.iter().any(|t| t == tag))
+}
+
+/// Detect the context of new entries with the configured command, if any.
+fn detect_context(config: &Config) -> Option<String> {
+    // Don't get in the way of tracking time if detection fails
+    config.context.detect().unwrap_or_else(|err| {
+        eprintln!("Warning: {:#}", err);
+        None
+    })
+}
+
+/// Longest duration worth printing in full.  Anything longer comes from a
+/// broken entry (a typo in a year, say), and would only widen columns.
+const MAX_DURATION: Duration = Duration::hours(99_999);
+
 /// Print a duration as a human-readable string.
 ///
+/// Negative durations, which only come from entries ending before they start,
+/// are printed as `?`, and those over [`MAX_DURATION`] as `>99999h`.
+///
 /// # Examples
 ///
 /// ```
@@ -730,14 +3091,24 @@ fn main() -> Result<()> {
 /// );
 /// assert_eq!(
 ///     duration_to_string(Duration::minutes(64)).unwrap(),
-///     "1h 4m".to_owned()
+///     "1h 04m".to_owned()
 /// );
 /// assert_eq!(
 ///     duration_to_string(Duration::minutes(4000)).unwrap(),
 ///     "66h 40m".to_owned()
 /// );
+/// assert_eq!(
+///     duration_to_string(Duration::minutes(-119)).unwrap(),
+///     "?".to_owned()
+/// );
 /// ```
 fn duration_to_string(duration: Duration) -> Result<String, std::fmt::Error> {
+    if duration.is_negative() {
+        return Ok("?".to_owned());
+    }
+    if duration > MAX_DURATION {
+        return Ok(format!(">{}h", MAX_DURATION.whole_hours()));
+    }
     let minutes = duration.whole_minutes();
     let hours = minutes / 60;
     let minutes = minutes % 60;
@@ -752,12 +3123,202 @@ fn duration_to_string(duration: Duration) -> Result<String, std::fmt::Error> {
     Ok(result)
 }
 
+/// Print a duration that may be negative, always with its sign.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(
+///     signed_duration_to_string(Duration::minutes(64)).unwrap(),
+///     "+1h 04m".to_owned()
+/// );
+/// assert_eq!(
+///     signed_duration_to_string(Duration::minutes(-16)).unwrap(),
+///     "-16m".to_owned()
+/// );
+/// ```
+fn signed_duration_to_string(duration: Duration) -> Result<String, std::fmt::Error> {
+    let sign = if duration.is_negative() { '-' } else { '+' };
+    Ok(format!("{}{}", sign, duration_to_string(duration.abs())?))
+}
+
 /// Converts an [`OffsetDateTime`] to a string, possibly omitting the date.
 fn datetime_to_human_string(dt: OffsetDateTime) -> Result<String, time::error::Format> {
     let now = OffsetDateTime::now_local().unwrap();
     if now.date() != dt.date() {
-        dt.format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        Ok(locale::datetime(dt))
     } else {
-        dt.format(format_description!("[hour]:[minute]"))
+        Ok(locale::time(dt.time()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn page(limit: Option<usize>, offset: usize, page: Option<usize>, reverse: bool) -> Page {
+        Page {
+            limit,
+            offset,
+            page: page.and_then(NonZeroUsize::new),
+            reverse,
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let items = (0..10).collect::<Vec<_>>();
+        let indices = |page: Page| {
+            page.select(&items)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(indices(page(None, 0, None, false)), items);
+        assert_eq!(indices(page(Some(3), 0, Some(2), false)), [3, 4, 5]);
+        assert_eq!(indices(page(Some(3), 0, Some(4), false)), [9]);
+        assert_eq!(indices(page(Some(2), 1, None, true)), [8, 7]);
+        assert_eq!(indices(page(None, 20, None, false)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn pages_of_selected_items() {
+        let items = (0..10).collect::<Vec<_>>();
+        let even = |item: &i32| item % 2 == 0;
+        let selected = |page: Page| {
+            page.select_where(&items, even)
+                .into_iter()
+                .map(|(i, item)| (i, *item))
+                .collect::<Vec<_>>()
+        };
+        // Indices are among all items, not only the selected ones
+        assert_eq!(selected(page(Some(2), 0, Some(2), false)), [(4, 4), (6, 6)]);
+        assert_eq!(selected(page(Some(2), 1, None, true)), [(6, 6), (4, 4)]);
+    }
+
+    /// Columns of a tracking file that are (de)serialized by hand.
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Columns {
+        #[serde(with = "tags")]
+        tags: Vec<String>,
+        #[serde(with = "notes")]
+        notes: Vec<Note>,
+        #[serde(with = "flag")]
+        locked: bool,
+    }
+
+    fn columns(tags: &str, notes: &str, locked: &str) -> serde_json::Result<Columns> {
+        serde_json::from_value(serde_json::json!({
+            "tags": tags,
+            "notes": notes,
+            "locked": locked,
+        }))
+    }
+
+    #[test]
+    fn tags_column() {
+        let parsed = columns(" a, ,b c,", "", "").unwrap();
+        assert_eq!(parsed.tags, ["a", "b c"]);
+        assert_eq!(serde_json::to_value(&parsed).unwrap()["tags"], "a,b c");
+        assert!(columns("", "", "").unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn notes_column() {
+        let parsed = Columns {
+            tags: vec![],
+            notes: vec![
+                Note {
+                    at: datetime!(2021-09-14 09:00 +2),
+                    text: "a | b\\c\nd".to_owned(),
+                },
+                Note {
+                    at: datetime!(2021-09-14 09:30 +2),
+                    text: "second".to_owned(),
+                },
+            ],
+            locked: false,
+        };
+        let column = serde_json::to_value(&parsed).unwrap()["notes"].clone();
+        assert_eq!(
+            column,
+            "2021-09-14T09:00:00+02:00 a \\| b\\\\c\\nd | 2021-09-14T09:30:00+02:00 second"
+        );
+        assert_eq!(columns("", column.as_str().unwrap(), "").unwrap(), parsed);
+        assert!(columns("", "", "").unwrap().notes.is_empty());
+        assert!(columns("", "yesterday did stuff", "").is_err());
+    }
+
+    #[test]
+    fn flag_column() {
+        assert!(!columns("", "", "").unwrap().locked);
+        assert!(!columns("", "", "false").unwrap().locked);
+        assert!(columns("", "", "true").unwrap().locked);
+        assert!(columns("", "", "yes").is_err());
+        let locked = columns("", "", "true").unwrap();
+        assert_eq!(serde_json::to_value(&locked).unwrap()["locked"], "true");
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let pattern = glob_to_regex("client-a-*").unwrap();
+        assert!(pattern.is_match("client-a-web"));
+        assert!(pattern.is_match("client-a-"));
+        assert!(!pattern.is_match("client-a"));
+        assert!(!pattern.is_match("old/client-a-web"));
+
+        let pattern = glob_to_regex("v?.(beta)").unwrap();
+        assert!(pattern.is_match("v2.(beta)"));
+        assert!(!pattern.is_match("v2x(beta)"));
+        assert!(!pattern.is_match("v10.(beta)"));
+    }
+
+    #[test]
+    fn glob_renames() {
+        // Each wildcard is a group of the replacement
+        let pattern = glob_to_regex("client-?-*").unwrap();
+        assert_eq!(pattern.replace("client-a-web", "$1/$2"), "a/web");
+        assert_eq!(pattern.replace("client-b-", "acme/${2}misc"), "acme/misc");
+    }
+
+    #[test]
+    fn retagging() {
+        let mut entry = Entry {
+            project: "a".to_owned(),
+            start: datetime!(2021-09-14 09:00 +2),
+            end: None,
+            tags: vec!["billable".to_owned()],
+            notes: vec![],
+            kind: Kind::Work,
+            context: None,
+            source: None,
+            locked: false,
+        };
+        entry.add_tag("billable");
+        entry.add_tag("meeting");
+        assert_eq!(entry.tags, ["billable", "meeting"]);
+        entry.remove_tag("billable");
+        entry.remove_tag("missing");
+        assert_eq!(entry.tags, ["meeting"]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration_to_string(16.minutes()).unwrap(), "16m");
+        assert_eq!(duration_to_string(64.minutes()).unwrap(), "1h 04m");
+        assert_eq!(duration_to_string(4000.minutes()).unwrap(), "66h 40m");
+        assert_eq!(duration_to_string((-119).minutes()).unwrap(), "?");
+        assert_eq!(
+            duration_to_string(MAX_DURATION + 1.minutes()).unwrap(),
+            ">99999h"
+        );
+    }
+
+    #[test]
+    fn signed_durations() {
+        assert_eq!(signed_duration_to_string(64.minutes()).unwrap(), "+1h 04m");
+        assert_eq!(signed_duration_to_string((-16).minutes()).unwrap(), "-16m");
     }
 }
@@ -0,0 +1,112 @@
+//! The man page, generated from the definitions of the command line and an
+//! example configuration, so that it can't drift from them.
+
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use clap_mangen::roff::{bold, roman, Roff};
+use clap_mangen::Man;
+
+use crate::config::Config;
+
+/// Every setting of the configuration file, with comments.
+const EXAMPLE_CONFIG: &str = include_str!("example-config.toml");
+
+/// Write the man page of `temps`, as roff.
+pub fn write(command: clap::Command, out: &mut dyn Write) -> Result<()> {
+    // The example would be misleading if it didn't parse anymore
+    toml::from_str::<Config>(EXAMPLE_CONFIG)
+        .context("The example configuration of the man page is invalid")?;
+
+    // The default path of the config is where it is on this machine
+    let command = command.mut_arg("config", |arg| arg.hide_default_value(true));
+    let man = Man::new(command.clone());
+    let mut page = vec![];
+    man.render_title(&mut page)?;
+    man.render_name_section(&mut page)?;
+    man.render_synopsis_section(&mut page)?;
+    man.render_description_section(&mut page)?;
+    man.render_options_section(&mut page)?;
+
+    page.extend(Roff::new().control("SH", ["COMMANDS"]).to_roff().bytes());
+    let name = command.get_name().to_owned();
+    for subcommand in command.get_subcommands() {
+        write_subcommand(subcommand, &name, &mut page)?;
+    }
+    page.extend(configuration().to_roff().bytes());
+
+    man.render_version_section(&mut page)?;
+    man.render_authors_section(&mut page)?;
+
+    // Each section is rendered with the same preamble, which is only needed
+    // once, at the top
+    let page = String::from_utf8(page)?;
+    let preamble = page
+        .lines()
+        .take_while(|line| line.starts_with(".ie ") || line.starts_with(".el "))
+        .collect::<Vec<_>>();
+    for line in &preamble {
+        writeln!(out, "{}", line)?;
+    }
+    for line in page.lines().filter(|line| !preamble.contains(line)) {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Write the synopsis, description and options of a subcommand, then those
+/// of its own subcommands.
+fn write_subcommand(subcommand: &clap::Command, parent: &str, page: &mut Vec<u8>) -> Result<()> {
+    if subcommand.is_hide_set() || subcommand.get_name() == "help" {
+        return Ok(());
+    }
+    let name = format!("{} {}", parent, subcommand.get_name());
+    let man = Man::new(
+        subcommand
+            .clone()
+            .bin_name(&name)
+            .disable_help_flag(true)
+            .disable_help_subcommand(true),
+    );
+
+    page.extend(Roff::new().control("SS", [name.as_str()]).to_roff().bytes());
+    // Sections of subcommands go without their heading
+    let mut sections = vec![];
+    man.render_synopsis_section(&mut sections)?;
+    Roff::new().control("PP", []).to_writer(&mut sections)?;
+    man.render_description_section(&mut sections)?;
+    man.render_options_section(&mut sections)?;
+    for line in String::from_utf8(sections)?.lines() {
+        if !line.starts_with(".SH ") {
+            page.extend(line.bytes());
+            page.push(b'\n');
+        }
+    }
+
+    for nested in subcommand.get_subcommands() {
+        write_subcommand(nested, &name, page)?;
+    }
+    Ok(())
+}
+
+/// Where the configuration file is, and what can be set in it.
+fn configuration() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["CONFIGURATION"])
+        .text([
+            roman("Settings are read from an optional TOML file, in a platform-specific location (on Linux, typically "),
+            bold("~/.config/temps/config.toml"),
+            roman("), which can be changed with "),
+            bold("--config"),
+            roman(" or "),
+            bold("TEMPS_CONFIG"),
+            roman(".  Every section and setting is optional:"),
+        ])
+        .control("PP", [])
+        .control("nf", []);
+    for line in EXAMPLE_CONFIG.lines() {
+        roff.text([roman(line)]);
+    }
+    roff.control("fi", []);
+    roff
+}
@@ -0,0 +1,176 @@
+//! Merging tracking files recorded on several devices.
+//!
+//! Entries recorded on different devices can't overlap: you can only be
+//! tracking one thing at a time.  When they do, one of the devices' clocks was
+//! most likely off, so overlaps are reported per pair of devices along with a
+//! shift that would undo them.  Shifts are applied to the merged entries of a
+//! device (as recorded in their `source`), never to those already in the
+//! tracking file, so that merging again with the same shifts changes nothing.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use time::{Duration, OffsetDateTime};
+
+use crate::{diff, duration_to_string, storage, Entry, Kind};
+
+/// Name given to entries that don't say which device they come from.
+const UNKNOWN: &str = "(unknown)";
+
+/// Merge entries from other tracking files into a tracking file.
+///
+/// Returns whether the tracking file was changed.
+pub fn merge(
+    path: &Path,
+    files: &[PathBuf],
+    shifts: &[(String, Duration)],
+    dry_run: bool,
+    yes: bool,
+    colored: bool,
+) -> Result<bool> {
+    let shifts = shifts.iter().cloned().collect::<HashMap<_, _>>();
+    let old = storage::read_all(path)?;
+    let mut merged = vec![];
+    for file in files {
+        if !file.exists() {
+            bail!("'{}' does not exist", file.display());
+        }
+        merged.extend(
+            storage::read_all(file)
+                .with_context(|| format!("Could not read '{}'", file.display()))?,
+        );
+    }
+
+    let mut unused = shifts.keys().collect::<HashSet<_>>();
+    for entry in merged.iter_mut() {
+        if let Some(&shift) = entry.source.as_ref().and_then(|source| shifts.get(source)) {
+            unused.remove(entry.source.as_ref().unwrap());
+            shift_entry(entry, shift);
+        }
+    }
+    for device in unused {
+        eprintln!("Warning: no entries to merge come from '{}'", device);
+    }
+    let mut entries = old.clone();
+    entries.extend(merged);
+
+    // The same entry may be in several files, e.g. if they were merged before
+    let mut seen = HashSet::new();
+    entries.retain(|entry| {
+        seen.insert((
+            entry.project.clone(),
+            entry.start,
+            entry.end,
+            entry.source.clone(),
+        ))
+    });
+    entries.sort_by_key(|entry| entry.start);
+    if let Some(ongoing) = entries.iter().rev().skip(1).find(|e| e.is_ongoing()) {
+        bail!(
+            "Entry '{}' from {} is ongoing but isn't the last one, stop it first",
+            ongoing.project,
+            device(ongoing)
+        );
+    }
+
+    report_skew(&entries)?;
+
+    let added = entries.len().saturating_sub(old.len());
+    if added == 0 {
+        eprintln!("Nothing to merge.");
+        return Ok(false);
+    }
+    if dry_run {
+        print!("{}", diff::unified(path, &old, &entries, colored)?);
+        eprintln!("Would add {} entries (dry run).", added);
+        return Ok(false);
+    }
+    let written = diff::confirm(path, &old, &entries, yes, colored)?;
+    if written {
+        eprintln!("Added {} entries.", added);
+    }
+    Ok(written)
+}
+
+/// Move an entry, and its notes, in time.
+fn shift_entry(entry: &mut Entry, shift: Duration) {
+    entry.start += shift;
+    entry.end = entry.end.map(|end| end + shift);
+    for note in entry.notes.iter_mut() {
+        note.at += shift;
+    }
+}
+
+fn device(entry: &Entry) -> &str {
+    entry.source.as_deref().unwrap_or(UNKNOWN)
+}
+
+/// Overlaps between entries of two devices.
+#[derive(Default)]
+struct Overlaps(Vec<Duration>);
+
+/// Report overlapping entries from different devices, grouped by pair of
+/// devices: the first one's entries end after the second one's start.
+fn report_skew(entries: &[Entry]) -> Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let mut pairs: BTreeMap<(&str, &str), Overlaps> = BTreeMap::new();
+    // Entry ending last among those seen so far
+    let mut latest: Option<&Entry> = None;
+    // Unscheduled entries were never at the time they're recorded at
+    for entry in entries.iter().filter(|e| e.kind != Kind::Unscheduled) {
+        if let Some(previous) = latest {
+            let end = previous.end.unwrap_or(now);
+            if end > entry.start && previous.source != entry.source {
+                pairs
+                    .entry((device(previous), device(entry)))
+                    .or_default()
+                    .0
+                    .push(end - entry.start);
+            }
+        }
+        if latest.is_none_or(|latest| latest.end.unwrap_or(now) < entry.end.unwrap_or(now)) {
+            latest = Some(entry);
+        }
+    }
+    if pairs.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("Suspected clock skew (entries from different devices overlap):");
+    for ((first, second), Overlaps(mut overlaps)) in pairs {
+        overlaps.sort();
+        let typical = overlaps[overlaps.len() / 2];
+        let longest = overlaps[overlaps.len() - 1];
+        eprintln!(
+            "  {} ends after {} starts {} time(s), by {} typically (up to {})",
+            first,
+            second,
+            overlaps.len(),
+            duration_to_string(typical)?,
+            duration_to_string(longest)?
+        );
+        let suggestions = [(first, -typical), (second, typical)]
+            .into_iter()
+            .filter(|(device, _)| *device != UNKNOWN)
+            .map(|(device, shift)| format!("--shift {}={}", device, shift_to_string(shift)))
+            .collect::<Vec<_>>();
+        if !suggestions.is_empty() {
+            eprintln!("    try {}", suggestions.join(" or "));
+        }
+    }
+    Ok(())
+}
+
+/// Write a shift as it's passed to `--shift`, e.g. `-00:05:30`.
+fn shift_to_string(shift: Duration) -> String {
+    let sign = if shift.is_negative() { '-' } else { '+' };
+    let seconds = shift.whole_seconds().abs();
+    format!(
+        "{}{:02}:{:02}:{:02}",
+        sign,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,106 @@
+//! Publishing what's tracked to an MQTT broker, e.g. for home automation.
+//!
+//! Each change (starting, stopping, cancelling or restoring an entry) is
+//! published as an event to `{topic}/events`, and the current status to
+//! `{topic}/status`, where it's retained for new subscribers.  Messages are
+//! JSON objects, sent with `mosquitto_pub`, which reads credentials from its
+//! own configuration file.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+
+use crate::config::MqttConfig;
+use crate::Entry;
+
+/// What happened to an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    Start,
+    Stop,
+    Cancel,
+    Restore,
+}
+
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    event: Event,
+    project: &'a str,
+    tags: &'a [String],
+    start: String,
+    end: Option<String>,
+    context: Option<&'a str>,
+    source: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct StatusMessage<'a> {
+    tracking: bool,
+    project: Option<&'a str>,
+    since: Option<String>,
+}
+
+/// Publish an event and the resulting status, if a broker is configured.
+///
+/// Failing to publish doesn't get in the way of tracking time, it's only
+/// reported.
+pub fn publish(config: &MqttConfig, event: Event, entry: &Entry) {
+    if config.host.is_none() {
+        return;
+    }
+    if let Err(err) = try_publish(config, event, entry) {
+        eprintln!("Warning: Could not publish to MQTT: {:#}", err);
+    }
+}
+
+fn try_publish(config: &MqttConfig, event: Event, entry: &Entry) -> Result<()> {
+    let message = EventMessage {
+        event,
+        project: &entry.project,
+        tags: &entry.tags,
+        start: entry.start.format(&Rfc3339)?,
+        end: entry.end.map(|end| end.format(&Rfc3339)).transpose()?,
+        context: entry.context.as_deref(),
+        source: entry.source.as_deref(),
+    };
+    send(config, "events", &serde_json::to_string(&message)?, false)?;
+
+    let tracking = matches!(event, Event::Start | Event::Restore) && entry.is_ongoing();
+    let status = StatusMessage {
+        tracking,
+        project: tracking.then_some(entry.project.as_str()),
+        since: tracking.then(|| entry.start.format(&Rfc3339)).transpose()?,
+    };
+    send(config, "status", &serde_json::to_string(&status)?, true)
+}
+
+/// Send a message to a subtopic of the configured one.
+fn send(config: &MqttConfig, subtopic: &str, message: &str, retain: bool) -> Result<()> {
+    let host = config.host.as_deref().unwrap_or_default();
+    let mut command = Command::new("mosquitto_pub");
+    command
+        .args(["-h", host, "-p", &config.port.to_string()])
+        .args(["-t", &format!("{}/{}", config.topic, subtopic)])
+        .arg("-s");
+    if retain {
+        command.arg("-r");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Could not run 'mosquitto_pub'")?;
+    child
+        .stdin
+        .take()
+        .context("Could not write to 'mosquitto_pub'")?
+        .write_all(message.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("'mosquitto_pub' failed ({})", status);
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! The ongoing entry of a tracking file, kept in a small JSON file of its own.
+//!
+//! Every change to the tracking file made by `temps` (starting, stopping,
+//! cancelling...) also writes its ongoing entry to the state directory, so
+//! that `temps status --cached`, widgets and status bars can tell what's being
+//! tracked without reading the tracking file at all, however large or remote
+//! it is.  Changes made by hand or by syncing the tracking file only show up
+//! after the next command that changes it.
+//!
+//! The file is a stable interface: fields may be added, but not removed or
+//! changed.  That of the default tracking file is always `current.json` in the
+//! state directory, so that widgets can find it (see [`paths::ongoing`]).
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{paths, storage, Entry};
+
+/// What's in the file: the ongoing entry, or only `null`s if there is none.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    project: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    start: Option<OffsetDateTime>,
+    tags: Vec<String>,
+    context: Option<String>,
+}
+
+impl From<Option<&Entry>> for State {
+    fn from(entry: Option<&Entry>) -> Self {
+        match entry {
+            Some(entry) => State {
+                project: Some(entry.project.clone()),
+                start: Some(entry.start),
+                tags: entry.tags.clone(),
+                context: entry.context.clone(),
+            },
+            None => State::default(),
+        }
+    }
+}
+
+/// Keep the ongoing entry of a tracking file, if any, after a change.
+pub fn save(path: &Path, last: Option<&Entry>) {
+    let ongoing = paths::ongoing(path);
+    let state = State::from(last.filter(|entry| entry.is_ongoing()));
+    if write(&ongoing, &state).is_err() {
+        // Better to read the tracking file next time than to show a stale entry
+        let _ = fs::remove_file(&ongoing);
+    }
+}
+
+/// The project and start of the ongoing entry of a tracking file.
+///
+/// Only reads the tracking file (its last entry) if nothing was kept yet,
+/// e.g. when it was last changed by an older version.
+pub fn read(path: &Path) -> Result<Option<(String, OffsetDateTime)>> {
+    let ongoing = paths::ongoing(path);
+    let Ok(contents) = fs::read(&ongoing) else {
+        let last = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+        save(path, last.as_ref());
+        return Ok(last.map(|entry| (entry.project, entry.start)));
+    };
+    let state: State = serde_json::from_slice(&contents).context("Could not read ongoing entry")?;
+    Ok(state.project.zip(state.start))
+}
+
+fn write(ongoing: &Path, state: &State) -> Result<()> {
+    let mut contents = serde_json::to_vec_pretty(state)?;
+    contents.push(b'\n');
+    paths::create_parent(ongoing)?;
+    // Written aside then moved, so that readers never see half of it
+    let partial = ongoing.with_extension("partial");
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, ongoing)?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Entries started in the past over existing ones, for `temps start --from`.
+//!
+//! Starting from an earlier date than the end of the last entries would make
+//! them overlap, so the existing entries are ended when the new one starts,
+//! the new one starts when they end, or nothing is written at all.
+
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use time::OffsetDateTime;
+
+use crate::{datetime_to_human_string, locale, prompt, storage, Entry, Kind};
+
+/// What to do when a new entry would overlap existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnOverlap {
+    /// End the existing entries when the new one starts, removing those that
+    /// start later
+    TruncateOld,
+    /// Start the new entry when the existing ones end
+    TruncateNew,
+    /// Write nothing
+    Abort,
+}
+
+/// The entries that an entry started at `from` would overlap.
+///
+/// Unscheduled entries and days off aren't at any particular time, so they
+/// never overlap, and neither does an ongoing entry started before `from`,
+/// which is simply stopped then.
+pub fn find(path: &Path, from: OffsetDateTime) -> Result<Vec<Entry>> {
+    Ok(storage::read_since(path, from)?
+        .into_iter()
+        .filter(|entry| entry.kind == Kind::Work)
+        .filter(|entry| !(entry.is_ongoing() && entry.start <= from))
+        .collect())
+}
+
+/// When the new entry starts if it's truncated: when the last of the
+/// overlapping entries ends, or `None` (now) if one of them is ongoing.
+pub fn truncated_start(overlapping: &[Entry]) -> Option<OffsetDateTime> {
+    overlapping
+        .iter()
+        .map(|entry| entry.end)
+        .collect::<Option<Vec<_>>>()
+        .and_then(|ends| ends.into_iter().max())
+}
+
+/// Ask what to do about the entries that `project`, started at `from`, would
+/// overlap, unless `choice` already says.
+pub fn choose(
+    project: &str,
+    from: OffsetDateTime,
+    overlapping: &[Entry],
+    choice: Option<OnOverlap>,
+) -> Result<OnOverlap> {
+    if let Some(choice) = choice {
+        return Ok(choice);
+    }
+    if !io::stdin().is_terminal() {
+        bail!(
+            "Starting '{}' from {} would overlap {} entries, pass --on-overlap to say what to do",
+            project,
+            datetime_to_human_string(from)?,
+            overlapping.len()
+        );
+    }
+    eprintln!(
+        "Starting '{}' from {} would overlap:",
+        project,
+        datetime_to_human_string(from)?
+    );
+    for entry in overlapping {
+        let end = entry
+            .end
+            .map_or_else(|| "…".to_owned(), |end| locale::time(end.time()));
+        eprintln!(
+            "  {} {}–{:width$}  {}",
+            locale::date(entry.start.date()),
+            locale::time(entry.start.time()),
+            end,
+            entry.project,
+            width = locale::time_width()
+        );
+    }
+    let new_start = match truncated_start(overlapping) {
+        Some(start) => format!("at {}", datetime_to_human_string(start)?),
+        None => "now".to_owned(),
+    };
+    let choices = [
+        format!(
+            "End them at {} (removing those that start later)",
+            datetime_to_human_string(from)?
+        ),
+        format!("Start '{}' {} instead", project, new_start),
+        "Abort".to_owned(),
+    ];
+    Ok(match prompt::choose("What to do?", &choices, 2)? {
+        0 => OnOverlap::TruncateOld,
+        1 => OnOverlap::TruncateNew,
+        _ => OnOverlap::Abort,
+    })
+}
+
+/// End the entries that are still ongoing at `from` (including the ongoing
+/// one), or remove them if they start later.  Returns what was done, to be
+/// reported.
+pub fn truncate(entries: &mut Vec<Entry>, from: OffsetDateTime) -> Result<Vec<String>> {
+    let mut changes = vec![];
+    let mut kept = Vec::with_capacity(entries.len());
+    for mut entry in entries.drain(..) {
+        if entry.kind != Kind::Work || entry.end.is_some_and(|end| end <= from) {
+            kept.push(entry);
+        } else if entry.start < from {
+            changes.push(format!(
+                "{} '{}' at {}.",
+                if entry.is_ongoing() {
+                    "Stopped"
+                } else {
+                    "Ended"
+                },
+                entry.project,
+                datetime_to_human_string(from)?
+            ));
+            entry.end = Some(from);
+            kept.push(entry);
+        } else {
+            changes.push(format!(
+                "Removed '{}' (from {}).",
+                entry.project,
+                datetime_to_human_string(entry.start)?
+            ));
+        }
+    }
+    *entries = kept;
+    Ok(changes)
+}
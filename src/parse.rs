@@ -0,0 +1,373 @@
+use anyhow::{anyhow, bail, Context, Result};
+use time::ext::NumericalDuration;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset, Weekday};
+
+const DATETIME_FORMATS: &str = "Accepted formats:
+  HH:MM or HH:MM:SS   a time today, e.g. 9:30 or 14:05:00
+  DATE HH:MM          e.g. 'yesterday 14:00', '3 days ago 9:30' or '2021-09-16 9:30'
+  RFC3339             e.g. 2021-09-16T16:41:05+02:00";
+
+/// Parse a date and time, possibly inferring the date.
+///
+/// Expects either an RFC3339-formatted date/time, a date (see [`parse_date`])
+/// followed by a time, or a time with format `HH:MM:SS` or `HH:MM` (in which
+/// case the date is set to the current date).
+pub fn parse_datetime(src: &str) -> Result<OffsetDateTime> {
+    if let Ok(dt) = OffsetDateTime::parse(src, &Rfc3339) {
+        return Ok(dt);
+    }
+    // RFC3339 without an offset is assumed to be in the local timezone
+    if let Ok(dt) = PrimitiveDateTime::parse(src, &Rfc3339) {
+        return Ok(dt.assume_offset(UtcOffset::current_local_offset()?));
+    }
+
+    // The time is the last word, possibly preceded by a date
+    let (date, time) =
+        match src.trim().rsplit_once(' ') {
+            Some((date, time)) => (
+                Some(parse_date(date.trim()).map_err(|_| {
+                    anyhow!("'{}' is not a valid date\n\n{}", date, DATETIME_FORMATS)
+                })?),
+                time,
+            ),
+            None => (None, src),
+        };
+    let time = parse_time(time).map_err(|err| {
+        let mut message = format!("{}", err);
+        if let Some(hint) = suggest_time(time) {
+            message.push_str(&format!(" ({})", hint));
+        }
+        anyhow!("{}\n\n{}", message, DATETIME_FORMATS)
+    })?;
+
+    let now = OffsetDateTime::now_local()?;
+    Ok(match date {
+        Some(date) => date.with_time(time).assume_offset(now.offset()),
+        // Extend time with current date
+        None => now.replace_time(time),
+    })
+}
+
+/// Parse a time of day.
+///
+/// Expects `HH:MM:SS` or `HH:MM`, where each component may be a single digit.
+fn parse_time(src: &str) -> Result<Time> {
+    let components = src
+        .split(':')
+        .map(|c| {
+            if (1..=2).contains(&c.len()) && c.bytes().all(|b| b.is_ascii_digit()) {
+                c.parse::<u8>().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>();
+    let (hour, minute, second) = match components.as_deref() {
+        Some(&[hour, minute]) => (hour, minute, 0),
+        Some(&[hour, minute, second]) => (hour, minute, second),
+        _ => bail!("'{}' is not a valid time", src),
+    };
+    Time::from_hms(hour, minute, second)
+        .map_err(|err| anyhow!("'{}' is not a valid time: {}", src, err))
+}
+
+/// Guess which time the user meant, when it's obvious, and hint at it.
+fn suggest_time(src: &str) -> Option<String> {
+    // `9.30` or `9h30` instead of `9:30`
+    let replaced = src.replace(['.', 'h', 'H'], ":");
+    if replaced != src && parse_time(&replaced).is_ok() {
+        return Some(format!("did you mean '{}'?", replaced));
+    }
+    // `930` or `1430` instead of `09:30` or `14:30`
+    if (3..=4).contains(&src.len()) && src.bytes().all(|b| b.is_ascii_digit()) {
+        let (hour, minute) = src.split_at(src.len() - 2);
+        let time = format!("{:0>2}:{}", hour, minute);
+        if parse_time(&time).is_ok() {
+            return Some(format!("did you mean '{}'?", time));
+        }
+    }
+    // `00:00` today is the start of the day, not its end
+    if src == "24:00" {
+        return Some("for the end of a day, pass the next day with 00:00".to_owned());
+    }
+    None
+}
+
+/// Parse a duration.
+///
+/// Expects a duration with format `HH:MM:SS` or `HH:MM`.  Hours may go past
+/// 23, e.g. `40:00` for a weekly target.
+pub fn parse_duration(src: &str) -> Result<Duration> {
+    let components = src
+        .split(':')
+        .enumerate()
+        .map(|(i, c)| {
+            // Minutes and seconds have at most two digits, and are below 60
+            let valid =
+                !c.is_empty() && (i == 0 || c.len() <= 2) && c.bytes().all(|b| b.is_ascii_digit());
+            c.parse::<i64>()
+                .ok()
+                .filter(|&n| valid && (i == 0 || n < 60))
+        })
+        .collect::<Option<Vec<_>>>();
+    let (hours, minutes, seconds) = match components.as_deref() {
+        Some(&[hours, minutes]) => (hours, minutes, 0),
+        Some(&[hours, minutes, seconds]) => (hours, minutes, seconds),
+        _ => bail!(
+            "'{}' is not a valid duration, expected HH:MM or HH:MM:SS",
+            src
+        ),
+    };
+    hours
+        .checked_mul(3600)
+        .and_then(|total| total.checked_add(minutes * 60 + seconds))
+        .map(Duration::seconds)
+        .with_context(|| format!("'{}' is too long a duration", src))
+}
+
+/// Parse a midnight offset, i.e. the time of day at which days end (see
+/// [`parse_duration`]).  It's within a day, or days would overlap.
+pub fn parse_midnight_offset(src: &str) -> Result<Duration> {
+    let offset = parse_duration(src)?;
+    if !(Duration::ZERO..1.days()).contains(&offset) {
+        bail!(
+            "'{}' is not a valid midnight offset, expected a time of day from 00:00 to 23:59",
+            src
+        );
+    }
+    Ok(offset)
+}
+
+/// Parse a window of time of day, e.g. `06:00-22:00`.
+///
+/// Both ends are durations since midnight (see [`parse_duration`]), so the
+/// window may go past midnight, e.g. `20:00-26:00`.
+pub fn parse_window(src: &str) -> Result<(Duration, Duration)> {
+    let (start, end) = src
+        .split_once(['-', '–'])
+        .context("Could not parse window, expected e.g. 06:00-22:00")?;
+    let (start, end) = (parse_duration(start.trim())?, parse_duration(end.trim())?);
+    if start >= end {
+        bail!("The window must end after it starts");
+    }
+    Ok((start, end))
+}
+
+/// Parse a shift of the clock of a device, e.g. `laptop=-00:05` or
+/// `desktop=+01:00:30`.
+///
+/// The shift is a duration (see [`parse_duration`]) with an optional sign.
+pub fn parse_shift(src: &str) -> Result<(String, Duration)> {
+    let (device, shift) = src
+        .split_once('=')
+        .filter(|(device, _)| !device.is_empty())
+        .context("Could not parse shift, expected e.g. laptop=-00:05")?;
+    let shift = match shift.strip_prefix('-') {
+        Some(shift) => -parse_duration(shift)?,
+        None => parse_duration(shift.strip_prefix('+').unwrap_or(shift))?,
+    };
+    Ok((device.to_owned(), shift))
+}
+
+/// Parse a number of days, e.g. `30d` or `4w`.
+///
+/// A bare number is a number of days.
+pub fn parse_days(src: &str) -> Result<u16> {
+    let (number, factor) = match src.strip_suffix('w') {
+        Some(weeks) => (weeks, 7),
+        None => (src.strip_suffix('d').unwrap_or(src), 1),
+    };
+    number
+        .parse::<u16>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .filter(|&days| days > 0)
+        .with_context(|| {
+            format!(
+                "'{}' is not a valid number of days, expected e.g. 30d or 4w",
+                src
+            )
+        })
+}
+
+/// Parse a (possibly relative) date.
+///
+/// Expects either `YYYY-mm-dd`, `today`, `yesterday`, or `N days ago` where `N`
+/// is a positive integer.
+pub fn parse_date(src: &str) -> Result<Date> {
+    // Try to parse a YYYY-mm-dd date
+    Date::parse(src, &format_description!("[year]-[month]-[day]"))
+        .map_err(anyhow::Error::from)
+        // Try to parse a literal 'today', 'yesterday' or 'N days ago'
+        .or_else(|err| {
+            if src == "today" {
+                Ok(OffsetDateTime::now_local()?.date())
+            } else if src == "yesterday" {
+                Ok(OffsetDateTime::now_local()?.date() - 1.days())
+            } else if let Some((days, s)) = src.split_once(" ") {
+                if s.trim() == "days ago" {
+                    if let Ok(days) = days.parse::<i64>() {
+                        let today = OffsetDateTime::now_local()?.date();
+                        return days
+                            .checked_mul(86_400)
+                            .and_then(|seconds| today.checked_sub(Duration::seconds(seconds)))
+                            .context("Too many days ago");
+                    }
+                }
+                Err(err)
+            } else {
+                Err(err)
+            }
+        })
+        .context("Could not parse date")
+}
+
+/// Parse an ISO week, returning its first day (Monday).
+///
+/// Expects `YYYY-Www`, e.g. `2024-W07`.
+pub fn parse_iso_week(src: &str) -> Result<Date> {
+    src.split_once(['W', 'w'])
+        .and_then(|(year, week)| {
+            let year = year.strip_suffix('-').unwrap_or(year).parse().ok()?;
+            let week = week.parse().ok()?;
+            Date::from_iso_week_date(year, week, Weekday::Monday).ok()
+        })
+        .context("Could not parse week, expected e.g. 2024-W07")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("1:30").unwrap(), 90.minutes());
+        assert_eq!(parse_duration("40:00").unwrap(), 40.hours());
+        assert_eq!(
+            parse_duration("01:02:03").unwrap(),
+            1.hours() + 2.minutes() + 3.seconds()
+        );
+        for invalid in ["", "1", "1:60", "1:2:3:4", "-1:00", "1:000", "a:00"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn durations_too_long() {
+        assert!(parse_duration("9999999999999999:00").is_err());
+        assert!(parse_duration("2562047788015215:30:08").is_err());
+    }
+
+    #[test]
+    fn midnight_offsets() {
+        assert_eq!(
+            parse_midnight_offset("04:30").unwrap(),
+            4.hours() + 30.minutes()
+        );
+        assert_eq!(
+            parse_midnight_offset("23:59:59").unwrap(),
+            1.days() - 1.seconds()
+        );
+        assert!(parse_midnight_offset("24:00").is_err());
+        assert!(parse_midnight_offset("30:00").is_err());
+        assert!(parse_midnight_offset("-01:00").is_err());
+    }
+
+    #[test]
+    fn datetimes_with_an_offset() {
+        assert_eq!(
+            parse_datetime("2021-09-16T16:41:05+02:00").unwrap(),
+            time::macros::datetime!(2021-09-16 16:41:05 +2)
+        );
+        assert!(parse_datetime("2021-09-16 25:00").is_err());
+        assert!(parse_datetime("someday 9:30").is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            parse_time("9:30").unwrap(),
+            Time::from_hms(9, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_time("14:05:09").unwrap(),
+            Time::from_hms(14, 5, 9).unwrap()
+        );
+        for invalid in ["24:00", "9", "9:300", "+9:30", "9:30:00:00"] {
+            assert!(parse_time(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn suggested_times() {
+        assert_eq!(suggest_time("9.30").unwrap(), "did you mean '9:30'?");
+        assert_eq!(suggest_time("14h05").unwrap(), "did you mean '14:05'?");
+        assert_eq!(suggest_time("930").unwrap(), "did you mean '09:30'?");
+        assert!(!suggest_time("24:00").unwrap().contains("'00:00'"));
+        assert_eq!(suggest_time("2500"), None);
+        assert_eq!(suggest_time("9:30"), None);
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(
+            parse_window("06:00-22:00").unwrap(),
+            (6.hours(), 22.hours())
+        );
+        assert_eq!(
+            parse_window("20:00 – 26:00").unwrap(),
+            (20.hours(), 26.hours())
+        );
+        assert!(parse_window("22:00-06:00").is_err());
+        assert!(parse_window("06:00").is_err());
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(
+            parse_shift("laptop=-00:05").unwrap(),
+            ("laptop".to_owned(), (-5).minutes())
+        );
+        assert_eq!(
+            parse_shift("desktop=+01:00:30").unwrap(),
+            ("desktop".to_owned(), 1.hours() + 30.seconds())
+        );
+        assert_eq!(
+            parse_shift("phone=00:01").unwrap(),
+            ("phone".to_owned(), 1.minutes())
+        );
+        assert!(parse_shift("=00:05").is_err());
+        assert!(parse_shift("laptop").is_err());
+    }
+
+    #[test]
+    fn numbers_of_days() {
+        assert_eq!(parse_days("30").unwrap(), 30);
+        assert_eq!(parse_days("30d").unwrap(), 30);
+        assert_eq!(parse_days("4w").unwrap(), 28);
+        for invalid in ["0d", "-1", "w", "10000w", "3m"] {
+            assert!(parse_days(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            parse_date("2021-09-16").unwrap(),
+            time::macros::date!(2021 - 09 - 16)
+        );
+        assert!(parse_date("2021-02-30").is_err());
+        assert!(parse_date("16/09/2021").is_err());
+    }
+
+    #[test]
+    fn iso_weeks() {
+        let monday = time::macros::date!(2024 - 02 - 12);
+        assert_eq!(parse_iso_week("2024-W07").unwrap(), monday);
+        assert_eq!(parse_iso_week("2024w7").unwrap(), monday);
+        assert!(parse_iso_week("2024-W54").is_err());
+        assert!(parse_iso_week("2024-07").is_err());
+    }
+}
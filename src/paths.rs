@@ -0,0 +1,228 @@
+//! Where files are kept.
+//!
+//! Besides the tracking file and the configuration, each tracking file has a
+//! few files of its own.  Those that can be rebuilt at any time (the index, the
+//! daily totals and the snapshot) live in the cache directory, e.g.
+//! `$XDG_CACHE_HOME/temps`, and the others (the ongoing entry, the last
+//! cancelled entry, the goals reported today, backups) in the state
+//! directory, e.g. `$XDG_STATE_HOME/temps`.  They're named after a hash of
+//! the tracking file's path, which is the same whichever build of `temps`
+//! computes it.  The invoice ledger is worth backing up and
+//! syncing along with the tracking file, so it's kept right next to it.
+//!
+//! Older versions kept them next to the tracking file (e.g. `temps.tsv.idx`),
+//! or named them after another hash; they're moved over the first time
+//! they're needed.
+
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use time::OffsetDateTime;
+
+fn project_dirs() -> ProjectDirs {
+    if let Some(dirs) = ProjectDirs::from("", "", "temps") {
+        dirs
+    } else {
+        panic!("could not determine project dir")
+    }
+}
+
+/// Default path of the tracking file.
+pub fn data_file() -> PathBuf {
+    project_dirs().data_dir().join("temps.tsv")
+}
+
+/// Default path of the configuration file.
+pub fn config_file() -> PathBuf {
+    project_dirs().config_dir().join("config.toml")
+}
+
+/// Directory of the files that can be rebuilt at any time.
+pub fn cache_dir() -> PathBuf {
+    project_dirs().cache_dir().to_owned()
+}
+
+/// Directory of the files that must be kept, but aren't worth backing up.
+///
+/// Only some platforms have one, the others use the local data directory.
+pub fn state_dir() -> PathBuf {
+    let dirs = project_dirs();
+    dirs.state_dir()
+        .unwrap_or_else(|| dirs.data_local_dir())
+        .to_owned()
+}
+
+/// Path of the daily totals of a tracking file.
+pub fn daily_totals(path: &Path) -> PathBuf {
+    // Never kept next to the tracking file
+    rehashed(&cache_dir(), "daily-totals", path, "tsv")
+}
+
+/// Path of the snapshot of the entries of a tracking file.
+pub fn snapshot(path: &Path) -> PathBuf {
+    rehashed(&cache_dir(), "snapshot", path, "bin")
+}
+
+/// Path of the file in which the ongoing entry of a tracking file is kept.
+///
+/// It's read by widgets, so it isn't cleaned up like the cache, and that of
+/// the default tracking file is always `current.json`.
+pub fn ongoing(path: &Path) -> PathBuf {
+    let keyed = rehashed(&state_dir(), "ongoing", path, "json");
+    if is_default(path) {
+        moved(keyed, state_dir().join("current.json"))
+    } else {
+        keyed
+    }
+}
+
+/// Whether a path is that of the default tracking file.
+fn is_default(path: &Path) -> bool {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    canonical(path) == canonical(&data_file())
+}
+
+/// Path of the ledger of the invoices written from a tracking file, e.g.
+/// `temps.invoices.tsv` for `temps.tsv`.
+pub fn invoices(path: &Path) -> PathBuf {
+    path.with_extension("invoices.tsv")
+}
+
+/// Path of the index of a tracking file.
+pub fn index(path: &Path) -> PathBuf {
+    moved(
+        beside(path, "idx"),
+        rehashed(&cache_dir(), "index", path, "idx"),
+    )
+}
+
+/// Path of the file in which the last cancelled entry of a tracking file is kept.
+pub fn cancelled(path: &Path) -> PathBuf {
+    moved(
+        beside(path, "cancelled"),
+        rehashed(&state_dir(), "cancelled", path, "tsv"),
+    )
+}
+
+/// Path of the file in which the goals reported today are kept.
+pub fn reported_goals(path: &Path) -> PathBuf {
+    moved(
+        beside(path, "goals"),
+        rehashed(&state_dir(), "goals", path, "tsv"),
+    )
+}
+
+/// Path of a new copy of a tracking file, made at some time before removing
+/// entries from it.  Earlier copies are never overwritten.
+pub fn backup(path: &Path, at: OffsetDateTime) -> PathBuf {
+    let time = format!(
+        "backup-{:04}{:02}{:02}-{:02}{:02}{:02}",
+        at.year(),
+        at.month() as u8,
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    );
+    let backup = state_dir().join(keyed(&time, path, "tsv"));
+    let numbered = (1..).map(|n| backup.with_extension(format!("{}.tsv", n)));
+    std::iter::once(backup.clone())
+        .chain(numbered)
+        .find(|backup| !backup.exists())
+        .unwrap_or(backup)
+}
+
+/// Path of the lock taken while a tracking file is changed.
+pub fn lock(path: &Path) -> PathBuf {
+    state_dir().join(keyed("lock", path, "lock"))
+}
+
+/// Name of a file belonging to a tracking file, with a hash of its path.
+///
+/// The hash must never change: builds that disagree on it wouldn't take the
+/// same lock, nor find each other's state.
+fn keyed(name: &str, path: &Path, extension: &str) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    let hash = fnv1a(path.as_os_str().as_encoded_bytes());
+    format!("{}-{:016x}.{}", name, hash, extension)
+}
+
+/// 64-bit FNV-1a hash of some bytes, which is the same on every build.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Path of a file belonging to a tracking file, in some directory.
+///
+/// Older versions hashed the tracking file's path with std's `DefaultHasher`,
+/// which may change from one Rust release to the next; a file named after
+/// that hash is moved over, if this build computes the same.
+fn rehashed(dir: &Path, name: &str, path: &Path, extension: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_owned())
+        .hash(&mut hasher);
+    let old = format!("{}-{:016x}.{}", name, hasher.finish(), extension);
+    moved(dir.join(old), dir.join(keyed(name, path, extension)))
+}
+
+/// Path of a file next to the tracking file, e.g. `temps.tsv.idx`.
+fn beside(path: &Path, suffix: &str) -> PathBuf {
+    let mut beside = OsString::from(path.as_os_str());
+    beside.push(".");
+    beside.push(suffix);
+    PathBuf::from(beside)
+}
+
+/// Move a file from where older versions kept it to its new location, unless
+/// there's one already.
+///
+/// The file is left where it is if it can't be moved, e.g. across devices.
+fn moved(old: PathBuf, new: PathBuf) -> PathBuf {
+    if old.exists() && !new.exists() {
+        let renamed = new
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::rename(&old, &new));
+        if renamed.is_err() {
+            return old;
+        }
+    }
+    new
+}
+
+/// Create the directory of a file, if it doesn't exist yet.
+pub fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_is_stable() {
+        // Reference values of 64-bit FNV-1a
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn keyed_names() {
+        let path = Path::new("/nonexistent/temps.tsv");
+        assert_eq!(
+            keyed("lock", path, "lock"),
+            format!("lock-{:016x}.lock", fnv1a(b"/nonexistent/temps.tsv"))
+        );
+    }
+}
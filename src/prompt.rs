@@ -0,0 +1,53 @@
+//! Questions asked on the terminal.
+//!
+//! Answers are read from stdin, and an empty answer (or none at all, e.g. if
+//! stdin is closed) picks the default.
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+
+/// Ask a yes/no question, with a default answer.
+pub fn ask(question: &str, default: bool) -> Result<bool> {
+    eprint!("{} {} ", question, if default { "[Y/n]" } else { "[y/N]" });
+    Ok(match read_line()?.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// Ask to pick one of a few choices, by number, with a default one.
+///
+/// Returns the index of the choice.
+pub fn choose(question: &str, choices: &[String], default: usize) -> Result<usize> {
+    for (i, choice) in choices.iter().enumerate() {
+        eprintln!("  {}) {}", i + 1, choice);
+    }
+    eprint!("{} [{}] ", question, default + 1);
+    Ok(read_line()?
+        .parse::<usize>()
+        .ok()
+        .filter(|&choice| (1..=choices.len()).contains(&choice))
+        .map_or(default, |choice| choice - 1))
+}
+
+/// Ask for some text, with a default answer if there's one.
+pub fn read(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => eprint!("{} [{}] ", question, default),
+        None => eprint!("{} ", question),
+    }
+    let answer = read_line()?;
+    Ok(match default {
+        Some(default) if answer.is_empty() => default.to_owned(),
+        _ => answer,
+    })
+}
+
+fn read_line() -> Result<String> {
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_owned())
+}
@@ -0,0 +1,104 @@
+//! Removing old entries, e.g. once they're archived or exported.
+//!
+//! The tracking file is copied to the state directory before anything is
+//! removed, so that a prune can be undone by putting the copy back.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::table::{Alignment, Color, Options, Table, NAME_WIDTH};
+use crate::{duration_to_string, locale, paths, prompt, storage, Entry, Kind};
+
+/// Remove the entries from before a day, optionally only those of a project,
+/// after backing up the tracking file and asking for confirmation.
+pub fn prune(
+    path: &Path,
+    before: Date,
+    project: Option<&str>,
+    midnight_offset: Duration,
+    dry_run: bool,
+    yes: bool,
+    options: Options,
+) -> Result<()> {
+    let entries = storage::read_all(path)?;
+    let (pruned, kept): (Vec<Entry>, Vec<Entry>) = entries.into_iter().partition(|entry| {
+        // The ongoing entry isn't over, so it can't be old
+        !entry.is_ongoing()
+            && (entry.start - midnight_offset).date() < before
+            && project.is_none_or(|project| entry.project == project)
+    });
+    if pruned.is_empty() {
+        eprintln!("No entries from before {} to remove.", locale::date(before));
+        return Ok(());
+    }
+
+    // Number of entries and total time of each project (days off count no time)
+    let now = OffsetDateTime::now_local()?;
+    let mut projects = BTreeMap::<&str, (usize, Duration)>::new();
+    for entry in &pruned {
+        let (count, total) = projects
+            .entry(&entry.project)
+            .or_insert((0, Duration::ZERO));
+        *count += 1;
+        if entry.kind != Kind::DayOff {
+            *total += entry.duration(now);
+        }
+    }
+    let mut table = Table::new(["Project", "Entries", "Time"]);
+    table
+        .align([Alignment::Left, Alignment::Right, Alignment::Right])
+        .color([Some(Color::Hashed), None, None])
+        .max_width(0, NAME_WIDTH)
+        .options(options);
+    let mut total = Duration::ZERO;
+    for (project, (count, time)) in projects {
+        total += time;
+        table.row([
+            project.to_owned(),
+            count.to_string(),
+            duration_to_string(time)?,
+        ]);
+    }
+    table.separator();
+    table.total([
+        "TOTAL".to_owned(),
+        pruned.len().to_string(),
+        duration_to_string(total)?,
+    ]);
+    print!("{}", table);
+
+    if dry_run {
+        eprintln!("Would remove {} entries (dry run).", pruned.len());
+        return Ok(());
+    }
+    if !yes {
+        if !io::stdin().is_terminal() {
+            bail!("Not removing entries without confirmation, pass --yes to remove them anyway");
+        }
+        let question = format!(
+            "Remove these {} entries from '{}'?",
+            pruned.len(),
+            path.display()
+        );
+        if !prompt::ask(&question, false)? {
+            eprintln!("Nothing removed.");
+            return Ok(());
+        }
+    }
+
+    let backup = paths::backup(path, now);
+    paths::create_parent(&backup).context("Could not create state directory")?;
+    fs::copy(path, &backup).context("Could not back up tracking file")?;
+    storage::write_back(path, &kept)?;
+    eprintln!(
+        "Removed {} entries, the previous tracking file was copied to {}.",
+        pruned.len(),
+        backup.display()
+    );
+    Ok(())
+}
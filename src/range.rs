@@ -0,0 +1,173 @@
+//! Ranges of days covered by reports, invoices and timesheets.
+//!
+//! `temps report`, `temps invoice` and `temps export` all cover a calendar
+//! week or a range of days, defaulting to the past week, and read the
+//! entries of these days in the same way.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use time::ext::NumericalDuration;
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::parse::{parse_date, parse_duration};
+use crate::storage;
+use crate::Entry;
+
+/// Days to cover, as given on the command line.
+#[derive(Debug, clap::Args)]
+pub struct Days {
+    #[clap(
+        long,
+        value_name = "DATE",
+        value_parser = parse_date,
+        conflicts_with = "from",
+        help = "Cover the calendar week containing a date (defaults to the past week)"
+    )]
+    pub week_of: Option<Date>,
+    #[clap(long, value_parser = parse_date, help = "Cover a range of days from this one")]
+    pub from: Option<Date>,
+    #[clap(
+        long,
+        value_parser = parse_date,
+        requires = "from",
+        help = "Last day of the range (defaults to today)"
+    )]
+    pub to: Option<Date>,
+}
+
+/// Rounding of the time of each project, as given on the command line.
+#[derive(Debug, clap::Args)]
+pub struct Rounding {
+    #[clap(
+        long,
+        value_name = "HH:MM",
+        value_parser = parse_round,
+        default_value = "00:01",
+        help = "Round the time of each project to a multiple of this, so that it still adds up to the rounded total"
+    )]
+    pub round: Duration,
+}
+
+fn parse_round(src: &str) -> Result<Duration> {
+    let round = parse_duration(src)?;
+    if round.whole_seconds() <= 0 {
+        bail!("Cannot round to nothing, pass --round 00:01 or more");
+    }
+    Ok(round)
+}
+
+/// Days from `first` to `last` (included), where each day starts at the
+/// midnight offset.
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub first: Date,
+    pub last: Date,
+    /// Monday of the calendar week, if one was asked for.
+    pub week: Option<Date>,
+    now: OffsetDateTime,
+    midnight_offset: Duration,
+}
+
+impl Days {
+    /// Resolve the days to cover, at `now`.  Without a week or range, this is
+    /// the past week, up to today (as the midnight offset has it).
+    pub fn range(&self, now: OffsetDateTime, midnight_offset: Duration) -> Result<Range> {
+        let today = (now - midnight_offset).date();
+        // Calendar weeks start on Monday
+        let week = self
+            .week_of
+            .map(|date| date - (date.weekday().number_days_from_monday() as i64).days());
+        let (first, last) = match (self.from, week) {
+            (Some(from), _) => (from, self.to.unwrap_or(today)),
+            (None, Some(monday)) => (monday, monday + 6.days()),
+            (None, None) => (today - 6.days(), today),
+        };
+        if first > last {
+            bail!("The first day is after the last one");
+        }
+        Ok(Range {
+            first,
+            last,
+            week,
+            now,
+            midnight_offset,
+        })
+    }
+}
+
+impl Range {
+    /// Whether a day is in the range.
+    pub fn contains(&self, date: Date) -> bool {
+        (self.first..=self.last).contains(&date)
+    }
+
+    /// Moment a day starts.
+    fn start_of(&self, date: Date) -> OffsetDateTime {
+        date.with_time(Time::MIDNIGHT)
+            .assume_offset(self.now.offset())
+            + self.midnight_offset
+    }
+
+    /// Read the entries overlapping the range.
+    pub fn read(&self, path: &Path, use_index: bool) -> Result<Vec<Entry>> {
+        storage::read_range(
+            path,
+            self.start_of(self.first),
+            self.start_of(self.last) + 1.days(),
+            use_index,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+
+    fn days(week_of: Option<Date>, from: Option<Date>, to: Option<Date>) -> Days {
+        Days { week_of, from, to }
+    }
+
+    #[test]
+    fn past_week_follows_the_midnight_offset() {
+        let now = datetime!(2021-09-16 02:00 +2);
+        let range = days(None, None, None).range(now, 4.hours()).unwrap();
+        assert_eq!(range.first, date!(2021 - 09 - 09));
+        assert_eq!(range.last, date!(2021 - 09 - 15));
+        assert_eq!(range.week, None);
+        assert_eq!(range.start_of(range.first), datetime!(2021-09-09 04:00 +2));
+    }
+
+    #[test]
+    fn calendar_week_starts_on_monday() {
+        let now = datetime!(2021-09-16 12:00 UTC);
+        let range = days(Some(date!(2021 - 09 - 16)), None, None)
+            .range(now, Duration::ZERO)
+            .unwrap();
+        assert_eq!(range.week, Some(date!(2021 - 09 - 13)));
+        assert_eq!(range.first, date!(2021 - 09 - 13));
+        assert_eq!(range.last, date!(2021 - 09 - 19));
+        assert!(range.contains(date!(2021 - 09 - 19)));
+        assert!(!range.contains(date!(2021 - 09 - 20)));
+    }
+
+    #[test]
+    fn range_of_days() {
+        let now = datetime!(2021-09-16 12:00 UTC);
+        let range = days(None, Some(date!(2021 - 09 - 01)), None)
+            .range(now, Duration::ZERO)
+            .unwrap();
+        assert_eq!(range.last, date!(2021 - 09 - 16));
+        assert!(days(None, Some(date!(2021 - 09 - 17)), None)
+            .range(now, Duration::ZERO)
+            .is_err());
+    }
+
+    #[test]
+    fn rounding_to_nothing() {
+        assert_eq!(parse_round("00:15").unwrap(), 15.minutes());
+        assert!(parse_round("00:00").is_err());
+    }
+}
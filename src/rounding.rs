@@ -0,0 +1,184 @@
+//! Rounding that keeps totals consistent, for reports.
+//!
+//! Rounding each project's time on its own makes the columns of a report add
+//! up to something else than its (rounded) total, which clients notice.  With
+//! the largest remainder method, every duration is rounded down, then the
+//! units left to reach the rounded total go to those that lost the most.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use time::Duration;
+
+use crate::summary::DailyTotals;
+
+/// Seconds in a hundredth of an hour, the precision of hours in templates and
+/// timesheets.
+pub const CENTIHOUR: i64 = 36;
+
+/// Round non-negative amounts to whole `unit`s, so that they add up to their
+/// sum rounded to the nearest `unit` (halves rounded up).  Returns the number
+/// of units of each amount.
+pub fn largest_remainder(amounts: &[i64], unit: i64) -> Vec<i64> {
+    let total = (amounts.iter().sum::<i64>() + unit / 2) / unit;
+    apportion(amounts, unit, total, &vec![0; amounts.len()], 0)
+}
+
+/// Round non-negative amounts to whole `unit`s adding up to `total` units,
+/// which is at most their sum rounded up.  The units left after rounding down
+/// go to the largest remainders plus `bias`, then to the largest amounts, then
+/// in turn from the amount at `rotation`, so that ties in successive calls
+/// don't always go to the same place.  Amounts that are already whole never
+/// get one, so none is rounded by a whole `unit` or more.
+fn apportion(amounts: &[i64], unit: i64, total: i64, bias: &[i64], rotation: usize) -> Vec<i64> {
+    let mut units = amounts
+        .iter()
+        .map(|amount| amount / unit)
+        .collect::<Vec<_>>();
+    let left = total - units.iter().sum::<i64>();
+    let len = amounts.len();
+    let mut order = (0..len)
+        .filter(|&i| amounts[i] % unit != 0)
+        .collect::<Vec<_>>();
+    order.sort_by_key(|&i| {
+        (
+            Reverse(amounts[i] % unit + bias[i]),
+            Reverse(amounts[i]),
+            (i + len - rotation % len) % len,
+        )
+    });
+    for &i in order.iter().take(left.max(0) as usize) {
+        units[i] += 1;
+    }
+    units
+}
+
+/// Round a table of durations to multiples of `unit`, where each row holds
+/// the time of a project in each column (e.g. day) of a report.
+///
+/// The rows' totals are rounded first, so that they add up to the grand total
+/// rounded to the nearest `unit`, then the cells of each row so that they add
+/// up to the row's total, favouring the columns rounded down the most by the
+/// rows before.  However many columns there are, no cell and no project total
+/// ends up a whole `unit` away from what was tracked, and the columns' totals
+/// stay close to it too.
+pub fn round_table(rows: &mut [Vec<Duration>], unit: Duration) {
+    let unit = unit.whole_seconds();
+    let seconds = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|duration| duration.whole_seconds().max(0))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let totals = largest_remainder(
+        &seconds
+            .iter()
+            .map(|row| row.iter().sum())
+            .collect::<Vec<_>>(),
+        unit,
+    );
+    // What each column lost to rounding so far (or gained, if negative)
+    let mut lost = vec![0; rows.first().map_or(0, Vec::len)];
+    for (i, ((row, seconds), total)) in rows.iter_mut().zip(&seconds).zip(totals).enumerate() {
+        let units = apportion(seconds, unit, total, &lost, i);
+        for (j, (duration, units)) in row.iter_mut().zip(units).enumerate() {
+            *duration = Duration::seconds(units * unit);
+            lost[j] += seconds[j] - units * unit;
+        }
+    }
+}
+
+/// Round the time tracked on each project and day to a multiple of `unit`,
+/// with [`round_table`], so that the projects' totals over all the days are
+/// as close as can be to what was tracked.
+pub fn round_daily_totals(totals: &mut DailyTotals, unit: Duration) {
+    let days = totals.keys().copied().collect::<Vec<_>>();
+    let mut rows = BTreeMap::<String, Vec<Duration>>::new();
+    for (i, projects) in totals.values().enumerate() {
+        for (project, duration) in projects {
+            rows.entry(project.clone())
+                .or_insert_with(|| vec![Duration::ZERO; days.len()])[i] = *duration;
+        }
+    }
+    let mut cells = rows.values().cloned().collect::<Vec<_>>();
+    round_table(&mut cells, unit);
+    for (project, durations) in rows.keys().zip(cells) {
+        for (day, duration) in days.iter().zip(durations) {
+            let projects = totals.get_mut(day).expect("days come from the totals");
+            if duration.is_zero() {
+                // Rounded away, like in the total
+                projects.remove(project);
+            } else if let Some(tracked) = projects.get_mut(project) {
+                *tracked = duration;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::ext::NumericalDuration;
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn rounding_to_the_total() {
+        assert_eq!(largest_remainder(&[10, 10, 10], 15), [1, 1, 0]);
+        assert_eq!(largest_remainder(&[4, 5, 6], 15), [0, 0, 1]);
+        assert_eq!(largest_remainder(&[], 15), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn ties_go_to_the_largest_amounts() {
+        assert_eq!(largest_remainder(&[10, 25, 10], 15), [1, 2, 0]);
+    }
+
+    #[test]
+    fn totals_stay_within_a_unit() {
+        // Four projects with 1h35m a day, for 22 days
+        let projects = ["break", "client", "internal", "sales"];
+        let mut totals = DailyTotals::new();
+        let mut day = date!(2026 - 09 - 01);
+        for _ in 0..22 {
+            totals.insert(
+                day,
+                projects
+                    .iter()
+                    .map(|project| (project.to_string(), 95.minutes()))
+                    .collect(),
+            );
+            day += 1.days();
+        }
+        round_daily_totals(&mut totals, 15.minutes());
+
+        let mut sums = BTreeMap::<String, Duration>::new();
+        for projects in totals.values() {
+            let day = projects.values().copied().sum::<Duration>();
+            assert!((day - (4 * 95).minutes()).abs() < 15.minutes());
+            for (project, duration) in projects {
+                assert_eq!(duration.whole_seconds() % 900, 0);
+                assert!((*duration - 95.minutes()).abs() < 15.minutes());
+                *sums.entry(project.clone()).or_default() += *duration;
+            }
+        }
+        for project in projects {
+            assert!((sums[project] - (22 * 95).minutes()).abs() < 15.minutes());
+        }
+        // 139h20m, rounded to the nearest quarter
+        let total = sums.values().copied().sum::<Duration>();
+        assert_eq!(total, (139.hours() + 15.minutes()));
+    }
+
+    #[test]
+    fn ties_take_turns() {
+        let mut rows = vec![vec![10.minutes(); 3]; 3];
+        round_table(&mut rows, 15.minutes());
+        let (quarter, zero) = (15.minutes(), Duration::ZERO);
+        assert_eq!(rows[0], [quarter, quarter, zero]);
+        assert_eq!(rows[1], [zero, quarter, quarter]);
+        assert_eq!(rows[2], [quarter, zero, quarter]);
+    }
+}
@@ -0,0 +1,202 @@
+//! The settings in effect, for `temps where`.
+//!
+//! Most of them can come from a flag, an environment variable, the
+//! configuration or a default, so each one is printed with where it comes
+//! from, to figure out why the output doesn't look as expected.
+
+use std::env;
+use std::path::Path;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use time::{Duration, UtcOffset};
+
+use crate::config::{Config, StorageConfig};
+use crate::locale::{self, DateOrder};
+use crate::parse::parse_duration;
+use crate::paths;
+use crate::table::Style;
+
+/// Print a setting, its value, and where it comes from.
+fn row(name: &str, value: impl AsRef<str>, origin: impl AsRef<str>) {
+    println!("{:17}{} ({})", name, value.as_ref(), origin.as_ref());
+}
+
+/// Where an option that can be passed as a flag or an environment variable
+/// comes from, given its value.  Flags win over variables, so a value that
+/// isn't the variable's comes from the flag, unless it's the default.
+fn origin<T: PartialEq>(
+    value: &T,
+    default: Option<&T>,
+    flag: &'static str,
+    var: &'static str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> &'static str {
+    if env::var(var).ok().and_then(|var| parse(&var)).as_ref() == Some(value) {
+        var
+    } else if default == Some(value) {
+        "default"
+    } else {
+        flag
+    }
+}
+
+/// Print the tracking file and configuration in use, the midnight offset, the
+/// timezone, how dates and times are displayed, how tables are drawn, and how
+/// entries are stored.
+///
+/// `temps_file`, `config_file`, `midnight_offset` and `table_style` are the
+/// command-line options (or their environment variables).
+pub fn print(
+    path: &Path,
+    temps_file: Option<&Path>,
+    config_file: &Path,
+    config: &Config,
+    midnight_offset: Duration,
+    table_style: Option<Style>,
+) -> Result<()> {
+    let path_origin = match temps_file {
+        Some(file) => origin(
+            &file.to_owned(),
+            None,
+            "--temps-file",
+            "TEMPS_FILE",
+            |var| Some(var.into()),
+        ),
+        None if config.storage.file.is_some() => "configuration",
+        None => "default",
+    };
+    row("Tracking file", path.display().to_string(), path_origin);
+    let config_origin = origin(
+        &config_file.to_owned(),
+        Some(&paths::config_file()),
+        "--config",
+        "TEMPS_CONFIG",
+        |var| Some(var.into()),
+    );
+    row(
+        "Configuration",
+        config_file.display().to_string(),
+        if config_file.exists() {
+            config_origin.to_owned()
+        } else {
+            format!("{}, missing so the defaults are used", config_origin)
+        },
+    );
+
+    row(
+        "Midnight offset",
+        format!(
+            "{:02}:{:02}",
+            midnight_offset.whole_hours(),
+            midnight_offset.whole_minutes() % 60
+        ),
+        origin(
+            &midnight_offset,
+            Some(&Duration::ZERO),
+            "--midnight-offset",
+            "TEMPS_MIDNIGHT_OFFSET",
+            |var| parse_duration(var).ok(),
+        ),
+    );
+
+    let timezone = UtcOffset::current_local_offset()?;
+    row(
+        "Timezone",
+        format!(
+            "UTC{}{:02}:{:02}",
+            if timezone.is_negative() { '-' } else { '+' },
+            timezone.whole_hours().abs(),
+            timezone.minutes_past_hour().abs()
+        ),
+        env::var("TZ").map_or_else(|_| "system".to_owned(), |tz| format!("TZ={}", tz)),
+    );
+
+    let locale = locale::current();
+    let detected = locale::variable().map_or_else(
+        || "default".to_owned(),
+        |(var, value)| format!("detected from {}={}", var, value),
+    );
+    let display = &config.display;
+    row(
+        "Clock",
+        locale.clock.name(),
+        if display.clock.is_some() {
+            "configuration"
+        } else {
+            &detected
+        },
+    );
+    let order = match locale.date_order {
+        DateOrder::Ymd => ["YYYY", "MM", "DD"],
+        DateOrder::Dmy => ["DD", "MM", "YYYY"],
+        DateOrder::Mdy => ["MM", "DD", "YYYY"],
+    };
+    row(
+        "Dates",
+        order.join(&locale.date_separator),
+        if display.date_order.is_none() && display.date_separator.is_none() {
+            &detected
+        } else {
+            "configuration"
+        },
+    );
+
+    row(
+        "Numbers",
+        locale::number(1234.5, 2),
+        if display.decimal_separator.is_none() && display.thousands_separator.is_none() {
+            &detected
+        } else {
+            "configuration"
+        },
+    );
+
+    let (style, style_origin) = match table_style {
+        Some(style) => (
+            style,
+            origin(&style, None, "--table-style", "TEMPS_TABLE_STYLE", |var| {
+                Style::from_str(var, true).ok()
+            }),
+        ),
+        None if config.table.style == Style::default() => (config.table.style, "default"),
+        None => (config.table.style, "configuration"),
+    };
+    let style = style
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_owned());
+    row("Table style", style, style_origin);
+
+    row(
+        "Storage",
+        storage(&config.storage),
+        if config_file.exists() {
+            "configuration"
+        } else {
+            "default"
+        },
+    );
+    Ok(())
+}
+
+/// How entries are stored, and what's kept alongside them.
+fn storage(storage: &StorageConfig) -> String {
+    let extras = [
+        (storage.index, "an index"),
+        (storage.cache, "cached daily totals"),
+        (storage.snapshot, "a snapshot"),
+        (storage.source, "the machine of each entry"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, extra)| enabled.then_some(extra))
+    .collect::<Vec<_>>();
+    match extras.as_slice() {
+        [] => "tab-separated file".to_owned(),
+        [extra] => format!("tab-separated file, with {}", extra),
+        [extras @ .., last] => format!(
+            "tab-separated file, with {} and {}",
+            extras.join(", "),
+            last
+        ),
+    }
+}
@@ -0,0 +1,42 @@
+//! Shell integration: functions to load in the shell's configuration.
+
+/// Shells there's an integration for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl From<InitShell> for clap_complete::Shell {
+    fn from(shell: InitShell) -> Self {
+        match shell {
+            InitShell::Bash => clap_complete::Shell::Bash,
+            InitShell::Zsh => clap_complete::Shell::Zsh,
+            InitShell::Fish => clap_complete::Shell::Fish,
+        }
+    }
+}
+
+/// The script to evaluate in a shell, with the directory hook if asked for.
+pub fn script(shell: InitShell, auto_switch: bool) -> String {
+    let (init, hook) = match shell {
+        InitShell::Bash => (
+            include_str!("shell/init.bash"),
+            include_str!("shell/auto-switch.bash"),
+        ),
+        InitShell::Zsh => (
+            include_str!("shell/init.zsh"),
+            include_str!("shell/auto-switch.zsh"),
+        ),
+        InitShell::Fish => (
+            include_str!("shell/init.fish"),
+            include_str!("shell/auto-switch.fish"),
+        ),
+    };
+    if auto_switch {
+        format!("{}{}", init, hook)
+    } else {
+        init.to_owned()
+    }
+}
@@ -0,0 +1,8 @@
+
+# Start the project of a directory when entering it (see `temps auto-switch`)
+__temps_auto_switch() {
+    [ "$PWD" = "${__temps_dir-}" ] && return
+    __temps_dir=$PWD
+    command temps auto-switch --dir "$PWD"
+}
+PROMPT_COMMAND="__temps_auto_switch${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
@@ -0,0 +1,5 @@
+
+# Start the project of a directory when entering it (see `temps auto-switch`)
+function __temps_auto_switch --on-variable PWD
+    command temps auto-switch --dir "$PWD"
+end
@@ -0,0 +1,12 @@
+# Shell integration for temps, load it with `eval "$(temps shell-init bash)"`
+
+# Start tracking a project (or the last one, without arguments)
+ts() {
+    command temps start "$@"
+}
+
+# Print the ongoing project and how long it's been going, e.g. in PS1 with
+# PS1='$(tp) \$ ', or nothing if there's none
+tp() {
+    command temps status 2>/dev/null
+}
@@ -0,0 +1,12 @@
+# Shell integration for temps, load it with `temps shell-init fish | source`
+
+# Start tracking a project (or the last one, without arguments)
+function ts --wraps 'temps start' --description 'Start tracking a project'
+    command temps start $argv
+end
+
+# Print the ongoing project and how long it's been going, e.g. in
+# fish_right_prompt, or nothing if there's none
+function tp --description 'Print the ongoing project'
+    command temps status 2>/dev/null
+end
@@ -0,0 +1,16 @@
+
+# Project names are completed from the tracking file, with `temps completions
+# projects`
+_temps_projects() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    local commands=" {{commands}} " options=" {{options}} "
+    if [[ "$cur" != -* && ("$commands" == *" $prev "* || "$options" == *" $prev "*) ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$(command temps completions projects 2>/dev/null)" -- "$cur"))
+        COMPREPLY=("${COMPREPLY[@]// /\\ }")
+        return 0
+    fi
+    _temps "$@"
+}
+
+complete -F _temps_projects -o bashdefault -o default temps
@@ -0,0 +1,8 @@
+
+# Project names are completed from the tracking file, with `temps completions
+# projects`
+function __temps_after_project_option
+    contains -- (commandline -opc)[-1] {{options}}
+end
+complete -c temps -n "__fish_seen_subcommand_from {{commands}}" -a "(command temps completions projects 2>/dev/null)"
+complete -c temps -n __temps_after_project_option -x -a "(command temps completions projects 2>/dev/null)"
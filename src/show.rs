@@ -0,0 +1,144 @@
+//! Detailed description of a single entry.
+
+use std::path::Path;
+
+use anyhow::Result;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+use crate::{duration_to_string, locale, Entry, Kind};
+
+/// Entries longer than this are probably a forgotten `temps stop`.
+const LONG_ENTRY: Duration = Duration::hours(16);
+
+/// Print everything about the `index`-th entry of a file, and what looks wrong
+/// about it.
+///
+/// Entries come with the line they start on.
+pub fn card(
+    path: &Path,
+    entries: &[(u64, Entry)],
+    index: usize,
+    now: OffsetDateTime,
+) -> Result<()> {
+    let (line, entry) = &entries[index];
+    println!("Entry {} ({}, line {})", index + 1, path.display(), line);
+    println!();
+
+    let field = |name: &str, value: &str| println!("  {:10}{}", format!("{}:", name), value);
+    field("Project", &entry.project);
+    match entry.kind {
+        Kind::Work => {}
+        Kind::DayOff => field("Kind", "day off"),
+        Kind::Unscheduled => field("Kind", "unscheduled (at no particular time of the day)"),
+    }
+    field(
+        "Start",
+        &format!(
+            "{} ({})",
+            entry.start.format(&Rfc3339)?,
+            entry.start.weekday()
+        ),
+    );
+    match entry.end {
+        Some(end) => field(
+            "End",
+            &format!("{} ({})", end.format(&Rfc3339)?, end.weekday()),
+        ),
+        None => field("End", "ongoing"),
+    }
+    field(
+        "Duration",
+        &duration_to_string(entry.end.unwrap_or(now) - entry.start)?,
+    );
+    if !entry.tags.is_empty() {
+        field("Tags", &entry.tags.join(", "));
+    }
+    if let Some(context) = &entry.context {
+        field("Context", context);
+    }
+    if let Some(source) = &entry.source {
+        field("Source", source);
+    }
+    if entry.locked {
+        field("Locked", "yes, changing it takes --force");
+    }
+    if !entry.notes.is_empty() {
+        println!("  Notes:");
+        for note in &entry.notes {
+            println!("    {}  {}", locale::time(note.at.time()), note.text);
+        }
+    }
+
+    let warnings = warnings(entries, index, now)?;
+    if !warnings.is_empty() {
+        println!();
+        println!("Warnings:");
+        for warning in warnings {
+            println!("  - {}", warning);
+        }
+    }
+    Ok(())
+}
+
+/// Describe what looks wrong about an entry, e.g. overlaps with its neighbours.
+pub fn warnings(
+    entries: &[(u64, Entry)],
+    index: usize,
+    now: OffsetDateTime,
+) -> Result<Vec<String>> {
+    let entry = &entries[index].1;
+    let end = entry.end.unwrap_or(now);
+    let mut warnings = vec![];
+
+    if end < entry.start {
+        warnings.push("Ends before it starts".to_owned());
+    } else if end == entry.start && entry.kind == Kind::Work {
+        warnings.push("Lasts no time at all".to_owned());
+    } else if end - entry.start > LONG_ENTRY {
+        warnings.push(format!(
+            "Lasts more than {} hours, was it stopped?",
+            LONG_ENTRY.whole_hours()
+        ));
+    }
+    if entry.start > now {
+        warnings.push("Starts in the future".to_owned());
+    }
+    if entry.end.is_none() && index + 1 < entries.len() {
+        warnings.push("Is ongoing, but isn't the last entry".to_owned());
+    }
+    // Unscheduled entries overlap anything tracked early in their day
+    let scheduled = |entry: &Entry| entry.kind != Kind::Unscheduled;
+    if let Some((_, previous)) = index
+        .checked_sub(1)
+        .map(|i| &entries[i])
+        .filter(|(_, previous)| scheduled(previous) && scheduled(entry))
+    {
+        if previous.start > entry.start {
+            warnings.push(format!(
+                "Starts before the previous entry (entry {}, from {})",
+                index,
+                locale::datetime(previous.start)
+            ));
+        } else if previous.end.unwrap_or(now) > entry.start {
+            warnings.push(format!(
+                "Overlaps with the previous entry (entry {}, until {})",
+                index,
+                locale::datetime(previous.end.unwrap_or(now))
+            ));
+        }
+    }
+    if let Some((_, next)) = entries
+        .get(index + 1)
+        .filter(|(_, next)| scheduled(next) && scheduled(entry))
+    {
+        if next.start < end && next.start >= entry.start {
+            warnings.push(format!(
+                "Overlaps with the next entry (entry {}, from {})",
+                index + 2,
+                locale::datetime(next.start)
+            ));
+        }
+    }
+    Ok(warnings)
+}
@@ -0,0 +1,188 @@
+//! Binary snapshot of the parsed entries of a tracking file.
+//!
+//! With `snapshot = true` in the `[storage]` section of the config, reading
+//! all entries loads this snapshot instead of parsing the tracking file, as
+//! long as the file hasn't changed since (same length and modification time).
+//! This helps with commands run over and over, e.g. by a prompt or a status
+//! bar.  The snapshot lives in the cache directory and is rebuilt whenever
+//! it's stale, so it's safe to delete at any time.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime, Time, UtcOffset};
+
+use crate::{cache, paths, Entry, Kind, Note};
+
+/// Bumped whenever the layout of [`Stored`] changes.
+const VERSION: u32 = 2;
+
+/// The tracking file the snapshot is used for, if it's enabled.
+static TRACKING_FILE: OnceLock<std::path::PathBuf> = OnceLock::new();
+
+/// Use a snapshot when reading all entries of a tracking file.
+pub fn enable(path: &Path) {
+    let _ = TRACKING_FILE.set(path.to_owned());
+}
+
+/// Whether entries of a file are read through the snapshot.
+pub fn is_enabled(path: &Path) -> bool {
+    TRACKING_FILE.get().is_some_and(|file| file == path)
+}
+
+/// Read all entries from the snapshot if it's fresh, or else with `parse`,
+/// saving them in a new snapshot.
+pub fn read(path: &Path, parse: impl FnOnce(&Path) -> Result<Vec<Entry>>) -> Result<Vec<Entry>> {
+    let snapshot = paths::snapshot(path);
+    let key = key(path)?;
+    if let Ok(Some(entries)) = load(&snapshot, key) {
+        return Ok(entries);
+    }
+    let entries = parse(path)?;
+    // The snapshot is only an optimisation, failing to write it is fine
+    let _ = save(&snapshot, key, &entries);
+    Ok(entries)
+}
+
+/// Save entries that were just written to a tracking file.
+pub fn update(path: &Path, entries: &[Entry]) -> Result<()> {
+    save(&paths::snapshot(path), key(path)?, entries)
+}
+
+/// Identify the version of the snapshot and the state of the tracking file.
+fn key(path: &Path) -> Result<(u32, u64, u128)> {
+    let (len, modified) = cache::fingerprint(path)?;
+    Ok((VERSION, len, modified))
+}
+
+fn load(path: &Path, key: (u32, u64, u128)) -> Result<Option<Vec<Entry>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let bytes = fs::read(path)?;
+    let mut reader = bytes.as_slice();
+    let saved: (u32, u64, u128) = bincode::deserialize_from(&mut reader)?;
+    if saved != key {
+        return Ok(None);
+    }
+    let stored: Vec<Stored> = bincode::deserialize(reader)?;
+    stored
+        .into_iter()
+        .map(Entry::try_from)
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+fn save(path: &Path, key: (u32, u64, u128), entries: &[Entry]) -> Result<()> {
+    paths::create_parent(path)?;
+    // Written aside then moved, so that readers never see half a snapshot
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    bincode::serialize_into(&mut writer, &key)?;
+    bincode::serialize_into(
+        &mut writer,
+        &entries.iter().map(Stored::from).collect::<Vec<_>>(),
+    )?;
+    writer.into_inner().map_err(|err| err.into_error())?;
+    fs::rename(&partial, path).context("Could not save snapshot")
+}
+
+/// An entry as it's stored in the snapshot, with timestamps as numbers so
+/// that nothing needs parsing.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    project: String,
+    start: Timestamp,
+    end: Option<Timestamp>,
+    tags: Vec<String>,
+    notes: Vec<(Timestamp, String)>,
+    kind: Kind,
+    context: Option<String>,
+    source: Option<String>,
+    locked: bool,
+}
+
+/// A date and time as it was recorded: the day (as a Julian day number), the
+/// nanoseconds since midnight, and the UTC offset in seconds.  Unlike a Unix
+/// timestamp, this takes no calendar arithmetic to load.
+#[derive(Serialize, Deserialize)]
+struct Timestamp(i32, u64, i32);
+
+impl From<OffsetDateTime> for Timestamp {
+    fn from(datetime: OffsetDateTime) -> Self {
+        let since_midnight = datetime.time() - Time::MIDNIGHT;
+        Timestamp(
+            datetime.date().to_julian_day(),
+            since_midnight.whole_nanoseconds() as u64,
+            datetime.offset().whole_seconds(),
+        )
+    }
+}
+
+impl TryFrom<Timestamp> for OffsetDateTime {
+    type Error = anyhow::Error;
+
+    fn try_from(Timestamp(day, nanos, offset): Timestamp) -> Result<Self> {
+        let (seconds, nanos) = (nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+        let time = Time::from_hms_nano(
+            (seconds / 3600) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+            nanos,
+        )?;
+        Ok(Date::from_julian_day(day)?
+            .with_time(time)
+            .assume_offset(UtcOffset::from_whole_seconds(offset)?))
+    }
+}
+
+impl From<&Entry> for Stored {
+    fn from(entry: &Entry) -> Self {
+        Stored {
+            project: entry.project.clone(),
+            start: entry.start.into(),
+            end: entry.end.map(Timestamp::from),
+            tags: entry.tags.clone(),
+            notes: entry
+                .notes
+                .iter()
+                .map(|note| (note.at.into(), note.text.clone()))
+                .collect(),
+            kind: entry.kind,
+            context: entry.context.clone(),
+            source: entry.source.clone(),
+            locked: entry.locked,
+        }
+    }
+}
+
+impl TryFrom<Stored> for Entry {
+    type Error = anyhow::Error;
+
+    fn try_from(stored: Stored) -> Result<Self> {
+        Ok(Entry {
+            project: stored.project,
+            start: stored.start.try_into()?,
+            end: stored.end.map(OffsetDateTime::try_from).transpose()?,
+            tags: stored.tags,
+            notes: stored
+                .notes
+                .into_iter()
+                .map(|(at, text)| {
+                    Ok(Note {
+                        at: at.try_into()?,
+                        text,
+                    })
+                })
+                .collect::<Result<_>>()?,
+            kind: stored.kind,
+            context: stored.context,
+            source: stored.source,
+            locked: stored.locked,
+        })
+    }
+}
@@ -0,0 +1,71 @@
+//! What was done on the last working day, to paste into a standup message.
+
+use std::path::Path;
+
+use anyhow::Result;
+use time::ext::NumericalDuration;
+use time::macros::format_description;
+use time::{Date, Duration, OffsetDateTime, Time, UtcOffset};
+
+use crate::config::Config;
+use crate::{duration_to_string, locale, storage, summary};
+
+/// How far back to look for the last working day.
+const LOOKBACK_DAYS: i64 = 30;
+
+/// Print the projects of a day, with the time spent and the notes of each,
+/// as a bullet list.
+///
+/// Without a date, the day is the last one before today with something
+/// tracked, e.g. Friday on Mondays.
+pub fn standup(
+    path: &Path,
+    config: &Config,
+    date: Option<Date>,
+    midnight_offset: Duration,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let today = (now - midnight_offset).date();
+    let offset = UtcOffset::current_local_offset()?;
+    let bound = |date: Date| date.with_time(Time::MIDNIGHT).assume_offset(offset) + midnight_offset;
+    let (from, to) = match date {
+        Some(date) => (date, date),
+        None => (today - LOOKBACK_DAYS.days(), today - 1.days()),
+    };
+    let entries = storage::read_range(
+        path,
+        bound(from),
+        bound(to + 1.days()),
+        config.storage.index,
+    )?
+    .into_iter()
+    .filter(|entry| !config.summary.exclude.contains(&entry.project))
+    .collect::<Vec<_>>();
+    let activities = summary::activities(&entries, midnight_offset, now);
+
+    let Some((&day, activities)) = activities.range(from..=to).next_back() else {
+        match date {
+            Some(date) => eprintln!("Nothing tracked on {}.", locale::date(date)),
+            None => eprintln!("Nothing tracked in the last {} days.", LOOKBACK_DAYS),
+        }
+        return Ok(());
+    };
+
+    let name = if day == today - 1.days() {
+        "Yesterday".to_owned()
+    } else {
+        day.format(format_description!("[weekday]"))?
+    };
+    println!("{} ({}):", name, locale::date(day));
+    for activity in activities {
+        println!(
+            "- {} ({})",
+            activity.project,
+            duration_to_string(activity.time)?
+        );
+        for note in &activity.notes {
+            println!("  - {}", note);
+        }
+    }
+    Ok(())
+}
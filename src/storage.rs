@@ -0,0 +1,884 @@
+//! Reading and writing the tracking file.
+//!
+//! Commands that only touch the last entry (`start`, `stop`, `cancel`) don't
+//! read or rewrite the whole file: new entries are appended, and the last one
+//! is rewritten in place.  Likewise, commands that only care about recent
+//! entries read the file backwards, and range queries may use an index (see
+//! [`crate::index`]) to skip straight to the relevant part of the file.
+//! Summaries don't keep entries around, but aggregate them as they're read
+//! (see [`Span`]).  Everything else goes through [`read_all`] and
+//! [`write_back`].
+
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use serde::Deserialize;
+use time::ext::NumericalDuration;
+use time::OffsetDateTime;
+
+use crate::error::Failure;
+use crate::index::{self, Index};
+use crate::{ongoing, paths, snapshot, Entry, Kind};
+
+/// Size of the chunks read when scanning the file backwards.
+const CHUNK_SIZE: u64 = 4096;
+
+/// Read all entries from a tracking file, if it exists.
+pub fn read_all(path: &Path) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    if snapshot::is_enabled(path) {
+        return snapshot::read(path, parse_all);
+    }
+    parse_all(path)
+}
+
+/// Parse all entries of a tracking file.
+fn parse_all(path: &Path) -> Result<Vec<Entry>> {
+    ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .context(Failure::DataFile("Could not open tracking file"))?
+        .into_deserialize()
+        .collect::<Result<Vec<Entry>, csv::Error>>()
+        .context(Failure::DataFile("Could not read entries"))
+}
+
+/// The columns of an entry that summaries depend on, borrowed from the record
+/// they're read from.
+#[derive(Debug, Deserialize)]
+pub struct Span<'a> {
+    pub project: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub end: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub kind: Kind,
+    #[serde(default)]
+    pub context: Option<&'a str>,
+    /// Comma-separated, like in the tracking file.
+    #[serde(default, borrow)]
+    pub tags: Cow<'a, str>,
+}
+
+impl<'a> From<&'a Entry> for Span<'a> {
+    fn from(entry: &'a Entry) -> Self {
+        Span {
+            project: &entry.project,
+            start: entry.start,
+            end: entry.end,
+            kind: entry.kind,
+            context: entry.context.as_deref(),
+            tags: Cow::Owned(entry.tags.join(",")),
+        }
+    }
+}
+
+impl Span<'_> {
+    /// Check whether the entry has a tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.split(',').any(|t| t == tag)
+    }
+}
+
+/// Call `f` with each entry of a tracking file, in order, if it exists.
+///
+/// Unlike [`read_all`], nothing is allocated per entry: each one is borrowed
+/// from a single record, which is reused for the next one.  This keeps memory
+/// use flat when aggregating large files.
+pub fn for_each_span(path: &Path, mut f: impl FnMut(Span)) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut file = open(path, false)?;
+    let header = read_header(&mut file)?;
+    let offset = header.len() as u64;
+    read_from(file, &header, offset, |record, headers| {
+        f(deserialize(record, headers)?);
+        Ok(true)
+    })
+}
+
+/// Call `f` with each entry that was still ongoing at or after `since`, in
+/// order, like [`read_since`] but without allocating them.
+pub fn for_each_span_since(
+    path: &Path,
+    since: OffsetDateTime,
+    mut f: impl FnMut(Span),
+) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut file = open(path, false)?;
+    let header = read_header(&mut file)?;
+    let offset = offset_since(&mut file, &header, since)?;
+    read_from(file, &header, offset, |record, headers| {
+        let span: Span = deserialize(record, headers)?;
+        if span.end.is_none_or(|end| end > since) {
+            f(span);
+        }
+        Ok(true)
+    })
+}
+
+/// Call `f` with each entry that overlaps with `[from, to)`, in order, like
+/// [`read_range`] but without allocating them.
+pub fn for_each_span_in(
+    path: &Path,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    use_index: bool,
+    mut f: impl FnMut(Span),
+) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let overlaps = |span: &Span| span.start < to && span.end.is_none_or(|end| end > from);
+    let RangeStart {
+        file,
+        header,
+        offset,
+        previous,
+    } = range_start(path, from, use_index)?;
+    let mut add = |record: &StringRecord, headers: &StringRecord| {
+        let span: Span = deserialize(record, headers)?;
+        // Without the index, entries aren't assumed to be sorted
+        if use_index && span.start >= to {
+            return Ok(false);
+        }
+        if overlaps(&span) {
+            f(span);
+        }
+        Ok(true)
+    };
+    if let Some(previous) = previous {
+        read_records(previous.as_slice(), &header, &mut add)?;
+    }
+    read_from(file, &header, offset, add)
+}
+
+/// Read all entries from a tracking file, with the line each one starts on.
+pub fn read_numbered(path: &Path) -> Result<Vec<(u64, Entry)>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .context(Failure::DataFile("Could not open tracking file"))?;
+    let header = reader
+        .headers()
+        .context(Failure::DataFile("Could not read entries"))?
+        .clone();
+    reader
+        .into_records()
+        .map(|record| {
+            let record = record.context(Failure::DataFile("Could not read entries"))?;
+            let line = record.position().map_or(0, |position| position.line());
+            Ok((line, record.deserialize(Some(&header))?))
+        })
+        .collect::<Result<Vec<_>>>()
+        .context(Failure::DataFile("Could not read entries"))
+}
+
+/// Write entries back to a time tracking file
+pub fn write_back<P: AsRef<Path>>(path: P, entries: &[Entry]) -> Result<()> {
+    let path = path.as_ref();
+    index::invalidate(path)?;
+    write_entries(path, entries)?;
+    if snapshot::is_enabled(path) {
+        // The entries are at hand, no need to parse them again next time
+        let _ = snapshot::update(path, entries);
+    }
+    ongoing::save(path, entries.last());
+    Ok(())
+}
+
+/// Write entries to a file, replacing its contents.
+fn write_entries(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .context(Failure::DataFile("Could not open tracking file"))?;
+    for entry in entries {
+        writer
+            .serialize(entry)
+            .context(Failure::DataFile("Could not write entry to file"))?;
+    }
+    writer
+        .flush()
+        .context(Failure::DataFile("Could not write entry to file"))
+}
+
+/// How long to wait for another command to be done with the tracking file.
+const LOCK_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+/// Exclusive right to change a tracking file, until it's dropped.
+///
+/// Commands changing the tracking file hold it from before they read it until
+/// they're done, so that two of them (e.g. a shell hook and `temps watch`)
+/// can't clobber each other's changes.  The lock is a separate file in the
+/// state directory, so that it also covers rewrites of the tracking file.
+///
+/// Changes are never routed through a long-running process instead: the only
+/// one is `temps watch`, which takes this lock like any other command.
+pub struct Lock {
+    _file: File,
+}
+
+/// Lock a tracking file, waiting for other commands to release it.
+pub fn lock(path: &Path) -> Result<Lock> {
+    let lock = paths::lock(path);
+    paths::create_parent(&lock).context("Could not create state directory")?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock)
+        .context(Failure::DataFile("Could not open lock file"))?;
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Lock { _file: file }),
+            Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                if !waiting {
+                    eprintln!("Waiting for another temps command to finish...");
+                    waiting = true;
+                }
+                thread::sleep(StdDuration::from_millis(50));
+            }
+            Err(TryLockError::WouldBlock) => bail!(Failure::DataFile(
+                "Another temps command is still changing the tracking file, try again later"
+            )),
+            Err(TryLockError::Error(err)) => {
+                return Err(err).context(Failure::DataFile("Could not lock tracking file"))
+            }
+        }
+    }
+}
+
+/// Fail if the tracking file exists but can't be written to.
+///
+/// Mutating commands check first, so that they fail before doing anything.
+pub fn check_writable(path: &Path) -> Result<()> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(());
+    };
+    let writable =
+        !metadata.permissions().readonly() && OpenOptions::new().append(true).open(path).is_ok();
+    if !writable {
+        bail!(Failure::ReadOnly(format!(
+            "'{}' is read-only, not changing it",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Write entries as they would be in a tracking file, e.g. to compare them.
+pub fn to_string(entries: &[Entry]) -> Result<String> {
+    let mut writer = WriterBuilder::new().delimiter(b'\t').from_writer(vec![]);
+    for entry in entries {
+        writer
+            .serialize(entry)
+            .context(Failure::DataFile("Could not write entry to file"))?;
+    }
+    let bytes = writer
+        .into_inner()
+        .context(Failure::DataFile("Could not write entry to file"))?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Read the last entry of a tracking file, without reading the whole file.
+pub fn read_last(path: &Path) -> Result<Option<Entry>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = open(path, false)?;
+    let header = read_header(&mut file)?;
+    match last_record(&mut file, header.len() as u64)? {
+        Some((_, record)) => parse(&header, &record).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Read the entries that were still ongoing at or after `since`.
+///
+/// The file is read backwards, and reading stops at the first entry that
+/// ended before `since`; this assumes entries are sorted and don't overlap.
+pub fn read_since(path: &Path, since: OffsetDateTime) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut file = open(path, false)?;
+    let header = read_header(&mut file)?;
+    let offset = offset_since(&mut file, &header, since)?;
+    let mut entries = vec![];
+    read_from(file, &header, offset, |record, headers| {
+        let entry: Entry = deserialize(record, headers)?;
+        if entry.end.is_none_or(|end| end > since) {
+            entries.push(entry);
+        }
+        Ok(true)
+    })?;
+    Ok(entries)
+}
+
+/// Find where the entries that were still ongoing at or after `since` start,
+/// reading the file backwards: right after the last one that ended before.
+///
+/// This assumes entries are sorted and don't overlap.
+fn offset_since(file: &mut File, header: &[u8], since: OffsetDateTime) -> Result<u64> {
+    let len = file
+        .seek(SeekFrom::End(0))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    let mut offset = len;
+    for record in ReverseRecords::new(file, header.len() as u64)? {
+        let (start, record) = record?;
+        let entry = parse(header, &record)?;
+        // Days off are recorded at the start of the day, possibly right after
+        // an entry that spans midnight
+        if entry.end.is_some_and(|end| end <= since) && entry.kind != Kind::DayOff {
+            break;
+        }
+        offset = start;
+    }
+    Ok(offset)
+}
+
+/// Read the entries that overlap with `[from, to)`.
+///
+/// With `use_index`, the index of the file is refreshed and used to only read
+/// the entries around that range; this assumes entries are sorted and don't
+/// overlap.  Otherwise, the whole file is read.
+pub fn read_range(
+    path: &Path,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    use_index: bool,
+) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let overlaps = |entry: &Entry| entry.start < to && entry.end.is_none_or(|end| end > from);
+    let RangeStart {
+        file,
+        header,
+        offset,
+        previous,
+    } = range_start(path, from, use_index)?;
+    let mut entries = vec![];
+    let mut add = |record: &StringRecord, headers: &StringRecord| {
+        let entry: Entry = deserialize(record, headers)?;
+        // Without the index, entries aren't assumed to be sorted
+        if use_index && entry.start >= to {
+            return Ok(false);
+        }
+        if overlaps(&entry) {
+            entries.push(entry);
+        }
+        Ok(true)
+    };
+    if let Some(previous) = previous {
+        read_records(previous.as_slice(), &header, &mut add)?;
+    }
+    read_from(file, &header, offset, add)?;
+    Ok(entries)
+}
+
+/// Where to read a tracking file from, to find the entries of a range.
+struct RangeStart {
+    file: File,
+    header: Vec<u8>,
+    /// Offset to read forward from.
+    offset: u64,
+    /// The record just before `offset`, which may still be ongoing at the
+    /// start of the range.
+    previous: Option<Vec<u8>>,
+}
+
+/// Open a tracking file to read the entries from `from` onwards.
+///
+/// With `use_index`, the index of the file is refreshed and used to skip the
+/// entries before; otherwise, everything is read.
+fn range_start(path: &Path, from: OffsetDateTime, use_index: bool) -> Result<RangeStart> {
+    let mut file = open(path, false)?;
+    let header = read_header(&mut file)?;
+    if !use_index {
+        let offset = header.len() as u64;
+        return Ok(RangeStart {
+            file,
+            header,
+            offset,
+            previous: None,
+        });
+    }
+
+    let index = Index::refresh(path)?;
+    let len = file
+        .seek(SeekFrom::End(0))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    // Leave a day of margin for entries recorded with another UTC offset
+    let offset = index.offset((from - 1.days()).date()).unwrap_or(len);
+    let previous = ReverseRecords::before(&mut file, header.len() as u64, offset)
+        .next()
+        .transpose()?
+        .map(|(_, record)| record);
+    Ok(RangeStart {
+        file,
+        header,
+        offset,
+        previous,
+    })
+}
+
+/// Append an entry at the end of a tracking file.
+pub fn append(path: &Path, entry: &Entry) -> Result<()> {
+    if !path.exists() {
+        return write_back(path, std::slice::from_ref(entry));
+    }
+    let mut file = open(path, true)?;
+    let header = read_header(&mut file)?;
+    let (entry_header, record) = serialize(entry)?;
+    if header != entry_header {
+        // The file uses another set of columns, rewrite it entirely
+        return rewrite(path, |entries| entries.push(entry.clone()));
+    }
+
+    let len = file
+        .seek(SeekFrom::End(0))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    if len > 0 && !ends_with_newline(&mut file, len)? {
+        write(&mut file, b"\n")?;
+    }
+    write(&mut file, &record)?;
+    ongoing::save(path, Some(entry));
+    Ok(())
+}
+
+/// Replace the last entry of a tracking file with some entries (possibly none).
+pub fn replace_last(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut file = open(path, true)?;
+    let header = read_header(&mut file)?;
+    let mut records = vec![];
+    for entry in entries {
+        let (entry_header, record) = serialize(entry)?;
+        if header != entry_header {
+            // The file uses another set of columns, rewrite it entirely
+            return rewrite(path, |all| {
+                all.pop();
+                all.extend(entries.iter().cloned());
+            });
+        }
+        records.extend(record);
+    }
+
+    let (offset, _) = last_record(&mut file, header.len() as u64)?
+        .context(Failure::DataFile("Tracking file has no entries"))?;
+    file.set_len(offset)
+        .context(Failure::DataFile("Could not write entry to file"))?;
+    file.seek(SeekFrom::Start(offset))
+        .context(Failure::DataFile("Could not write entry to file"))?;
+    write(&mut file, &records)?;
+    match entries.last() {
+        Some(last) => ongoing::save(path, Some(last)),
+        None => ongoing::save(path, read_last(path)?.as_ref()),
+    }
+    Ok(())
+}
+
+/// Insert an entry in a tracking file, keeping entries sorted by start date.
+///
+/// The ongoing entry, if any, must stay last.  `check` is called on each
+/// existing entry first, and may refuse the insertion.
+pub fn insert(path: &Path, entry: Entry, check: impl Fn(&Entry) -> Result<()>) -> Result<()> {
+    let mut entries = read_all(path)?;
+    for existing in &entries {
+        check(existing)?;
+    }
+    let position = entries
+        .iter()
+        .position(|existing| existing.start > entry.start)
+        .unwrap_or(entries.len());
+    if position == entries.len() {
+        if let Some(last) = entries.last().filter(|last| last.is_ongoing()) {
+            bail!(
+                "Cannot add an entry after the ongoing one ('{}'), stop it first",
+                last.project
+            );
+        }
+        return append(path, &entry);
+    }
+    entries.insert(position, entry);
+    write_back(path, &entries)
+}
+
+/// Keep a cancelled entry, so that it can be restored later.
+pub fn save_cancelled(path: &Path, entry: &Entry) -> Result<()> {
+    let cancelled = paths::cancelled(path);
+    paths::create_parent(&cancelled).context("Could not create state directory")?;
+    write_entries(&cancelled, std::slice::from_ref(entry))
+}
+
+/// Read the last cancelled entry, if any.
+pub fn read_cancelled(path: &Path) -> Result<Option<Entry>> {
+    Ok(read_all(&paths::cancelled(path))?.pop())
+}
+
+/// Forget the last cancelled entry, once it's been restored.
+pub fn clear_cancelled(path: &Path) -> Result<()> {
+    let cancelled = paths::cancelled(path);
+    if cancelled.exists() {
+        fs::remove_file(cancelled)
+            .context(Failure::DataFile("Could not remove cancelled entry"))?;
+    }
+    Ok(())
+}
+
+/// Read, modify, and write back all entries.
+fn rewrite(path: &Path, modify: impl FnOnce(&mut Vec<Entry>)) -> Result<()> {
+    let mut entries = read_all(path)?;
+    modify(&mut entries);
+    write_back(path, &entries)
+}
+
+fn open(path: &Path, write: bool) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(write)
+        .open(path)
+        .context(Failure::DataFile("Could not open tracking file"))
+}
+
+fn write(file: &mut File, bytes: &[u8]) -> Result<()> {
+    file.write_all(bytes)
+        .context(Failure::DataFile("Could not write entry to file"))
+}
+
+/// Read the header line of a file, including the line terminator.
+fn read_header(file: &mut File) -> Result<Vec<u8>> {
+    let mut header = vec![];
+    BufReader::new(&mut *file)
+        .read_until(b'\n', &mut header)
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    Ok(header)
+}
+
+/// Serialize an entry, returning the header and the record lines separately.
+fn serialize(entry: &Entry) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut writer = WriterBuilder::new().delimiter(b'\t').from_writer(vec![]);
+    writer
+        .serialize(entry)
+        .context(Failure::DataFile("Could not write entry to file"))?;
+    let mut bytes = writer
+        .into_inner()
+        .context(Failure::DataFile("Could not write entry to file"))?;
+    let header_len = bytes.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let record = bytes.split_off(header_len);
+    Ok((bytes, record))
+}
+
+/// Read the records of a file forward from `offset`, given its header, until
+/// `f` returns `false`.
+fn read_from(
+    mut file: File,
+    header: &[u8],
+    offset: u64,
+    f: impl FnMut(&StringRecord, &StringRecord) -> Result<bool>,
+) -> Result<()> {
+    file.seek(SeekFrom::Start(offset))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    read_records(BufReader::new(file), header, f)
+}
+
+/// Read records (without a header) until `f` returns `false`, passing it each
+/// one along with the header of the file.
+///
+/// The same record is reused for every line, so that nothing is allocated
+/// per entry unless `f` does it.
+fn read_records(
+    reader: impl Read,
+    header: &[u8],
+    mut f: impl FnMut(&StringRecord, &StringRecord) -> Result<bool>,
+) -> Result<()> {
+    let headers = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(header)
+        .headers()
+        .context(Failure::DataFile("Could not read entries"))?
+        .clone();
+    let mut reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_reader(reader);
+    let mut record = StringRecord::new();
+    while reader
+        .read_record(&mut record)
+        .context(Failure::DataFile("Could not read entries"))?
+    {
+        if !f(&record, &headers)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Deserialize a record read by [`read_records`], e.g. into an [`Entry`] or a
+/// [`Span`] borrowing from it.
+fn deserialize<'de, T: Deserialize<'de>>(
+    record: &'de StringRecord,
+    headers: &'de StringRecord,
+) -> Result<T> {
+    record
+        .deserialize(Some(headers))
+        .context(Failure::DataFile("Could not read entries"))
+}
+
+/// Parse a single record, given the header of the file.
+fn parse(header: &[u8], record: &[u8]) -> Result<Entry> {
+    ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader([header, record].concat().as_slice())
+        .into_deserialize()
+        .next()
+        .context(Failure::DataFile("Could not read entries"))?
+        .context(Failure::DataFile("Could not read entries"))
+}
+
+fn ends_with_newline(file: &mut File, len: u64) -> Result<bool> {
+    let mut last = [0];
+    file.seek(SeekFrom::Start(len - 1))
+        .and_then(|_| file.read_exact(&mut last))
+        .context(Failure::DataFile("Could not read tracking file"))?;
+    Ok(last[0] == b'\n')
+}
+
+/// Find the last record of a file, along with the offset at which it starts.
+fn last_record(file: &mut File, header_len: u64) -> Result<Option<(u64, Vec<u8>)>> {
+    ReverseRecords::new(file, header_len)?.next().transpose()
+}
+
+/// Iterator over the records of a file, from last to first.
+///
+/// Yields the offset at which each record starts, along with its contents
+/// (without the line terminator).
+struct ReverseRecords<'a> {
+    file: &'a mut File,
+    header_len: u64,
+    /// Offset in the file of the start of `tail`.
+    start: u64,
+    /// The part of the file, after `start`, that hasn't been yielded yet.
+    tail: Vec<u8>,
+}
+
+impl<'a> ReverseRecords<'a> {
+    fn new(file: &'a mut File, header_len: u64) -> Result<Self> {
+        let len = file
+            .seek(SeekFrom::End(0))
+            .context(Failure::DataFile("Could not read tracking file"))?;
+        Ok(Self::before(file, header_len, len))
+    }
+
+    /// Iterate over the records that precede `offset`.
+    fn before(file: &'a mut File, header_len: u64, offset: u64) -> Self {
+        Self {
+            file,
+            header_len,
+            start: offset.max(header_len),
+            tail: vec![],
+        }
+    }
+
+    /// Read the chunk of the file that precedes `tail`.
+    fn read_chunk(&mut self) -> Result<()> {
+        let chunk_len = CHUNK_SIZE.min(self.start - self.header_len);
+        self.start -= chunk_len;
+        let mut chunk = vec![0; chunk_len as usize];
+        self.file
+            .seek(SeekFrom::Start(self.start))
+            .and_then(|_| self.file.read_exact(&mut chunk))
+            .context(Failure::DataFile("Could not read tracking file"))?;
+        chunk.append(&mut self.tail);
+        self.tail = chunk;
+        Ok(())
+    }
+}
+
+/// Find the line terminator before the last record of some bytes.
+///
+/// Fields with line breaks (e.g. a project name) are quoted, and a quoted
+/// field has an even number of quotes, since those inside it are doubled.  So
+/// there's an odd number of quotes after a line break inside a field, and an
+/// even number after one that ends a record.
+fn last_record_start(bytes: &[u8]) -> Option<usize> {
+    let mut quotes = 0;
+    for (i, &byte) in bytes.iter().enumerate().rev() {
+        match byte {
+            b'"' => quotes += 1,
+            b'\n' if quotes % 2 == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Iterator for ReverseRecords<'_> {
+    type Item = Result<(u64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Drop line terminators (and empty lines) at the end
+            while matches!(self.tail.last(), Some(b'\n' | b'\r')) {
+                self.tail.pop();
+            }
+            if let Some(i) = last_record_start(&self.tail) {
+                let record = self.tail.split_off(i + 1);
+                return Some(Ok((self.start + i as u64 + 1, record)));
+            }
+            if self.start == self.header_len {
+                // The first record starts right after the header
+                if self.tail.is_empty() {
+                    return None;
+                }
+                return Some(Ok((self.header_len, std::mem::take(&mut self.tail))));
+            }
+            if let Err(err) = self.read_chunk() {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry(project: &str, start: OffsetDateTime, end: Option<OffsetDateTime>) -> Entry {
+        Entry {
+            project: project.to_owned(),
+            start,
+            end,
+            tags: vec![],
+            notes: vec![],
+            kind: Kind::Work,
+            context: None,
+            source: None,
+            locked: false,
+        }
+    }
+
+    /// A tracking file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, entries: &[Entry]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "temps-test-{}-{}.tsv",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, to_string(entries).unwrap()).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Records read backwards, with the offset of each.
+    fn reverse_records(path: &Path, before: Option<u64>) -> (Vec<u8>, Vec<(u64, Vec<u8>)>) {
+        let mut reader = open(path, false).unwrap();
+        let header_len = read_header(&mut reader).unwrap().len() as u64;
+        let records = match before {
+            Some(offset) => ReverseRecords::before(&mut reader, header_len, offset)
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+            None => ReverseRecords::new(&mut reader, header_len)
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+        };
+        (fs::read(path).unwrap(), records)
+    }
+
+    #[test]
+    fn reverse_records_across_chunks() {
+        let entries = (0..200)
+            .map(|i| {
+                let start = datetime!(2021-09-14 09:00 +2) + (i * 10).minutes();
+                entry(&format!("project {}", i), start, Some(start + 5.minutes()))
+            })
+            .collect::<Vec<_>>();
+        let file = TempFile::new("reverse-records", &entries);
+
+        let (contents, records) = reverse_records(&file.0, None);
+        assert!(contents.len() as u64 > 2 * CHUNK_SIZE);
+        assert_eq!(records.len(), entries.len());
+        for ((offset, record), entry) in records.iter().zip(entries.iter().rev()) {
+            let offset = *offset as usize;
+            assert_eq!(&contents[offset..offset + record.len()], &record[..]);
+            assert_eq!(contents[offset - 1], b'\n');
+            assert!(record.starts_with(format!("{}\t", entry.project).as_bytes()));
+        }
+
+        // Only the records before the third one
+        let (_, before) = reverse_records(&file.0, Some(records[records.len() - 3].0));
+        assert_eq!(before, records[records.len() - 2..]);
+    }
+
+    #[test]
+    fn reverse_records_without_any() {
+        let file = TempFile::new("no-records", &[]);
+        assert_eq!(reverse_records(&file.0, None).1, []);
+        assert_eq!(read_last(&file.0).unwrap(), None);
+    }
+
+    #[test]
+    fn read_last_with_line_breaks_in_fields() {
+        let entries = [
+            entry(
+                "first",
+                datetime!(2021-09-14 09:00 +2),
+                Some(datetime!(2021-09-14 10:00 +2)),
+            ),
+            entry(
+                "two\nlines",
+                datetime!(2021-09-14 10:00 +2),
+                Some(datetime!(2021-09-14 11:00 +2)),
+            ),
+            entry(
+                "three\r\n\"quoted\"\nlines",
+                datetime!(2021-09-14 11:00 +2),
+                None,
+            ),
+        ];
+        let file = TempFile::new("line-breaks", &entries);
+
+        assert_eq!(read_last(&file.0).unwrap().as_ref(), entries.last());
+        assert_eq!(parse_all(&file.0).unwrap(), entries);
+
+        let mut reader = open(&file.0, false).unwrap();
+        let header = read_header(&mut reader).unwrap();
+        let records = ReverseRecords::new(&mut reader, header.len() as u64)
+            .unwrap()
+            .map(|record| parse(&header, &record.unwrap().1).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records, entries.iter().rev().cloned().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,1089 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::iter;
+
+use anyhow::Result;
+use time::ext::NumericalDuration;
+use time::macros::format_description;
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+
+use crate::locale;
+use crate::rounding;
+use crate::storage::Span;
+use crate::table::{Alignment, Color, Options, Table, GRID_NAME_WIDTH, NAME_WIDTH};
+use crate::{duration_to_string, signed_duration_to_string, Entry, Kind};
+
+/// Width of the longest bar of [`trend`].
+const TREND_WIDTH: usize = 40;
+
+/// What the time of weekly summaries is split by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// The project of each entry
+    #[default]
+    Project,
+    /// Each tag of each entry, leaving out entries without tags
+    Tag,
+}
+
+impl GroupBy {
+    /// Heading of the column of names.
+    fn heading(self) -> &'static str {
+        match self {
+            GroupBy::Project => "Project",
+            GroupBy::Tag => "Tag",
+        }
+    }
+}
+
+/// How the time of a report over a range of days is split into columns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Breakdown {
+    /// A column per day
+    Day,
+    /// A column per calendar week
+    Week,
+    /// A column per month
+    Month,
+    /// Only the total of each project
+    #[default]
+    Project,
+}
+
+/// Time tracked on each project, for each day.
+pub type DailyTotals = BTreeMap<Date, BTreeMap<String, Duration>>;
+
+/// Split entries into the time tracked on each project, for each day.
+///
+/// Ongoing entries are counted up to `now`.
+pub fn daily_totals(
+    entries: &[Entry],
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> DailyTotals {
+    let mut totals = DailyTotals::new();
+    for entry in entries {
+        add_to_daily_totals(&mut totals, &entry.into(), midnight_offset, now);
+    }
+    totals
+}
+
+/// Split an entry between the days it spans, adding its time to their totals.
+pub fn add_to_daily_totals(
+    totals: &mut DailyTotals,
+    span: &Span,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) {
+    let date_of = |dt: OffsetDateTime| (dt - midnight_offset).to_offset(now.offset()).date();
+    let (first, last) = (date_of(span.start), date_of(span.end.unwrap_or(now)));
+    for day in iter::successors(Some(first), |day| day.next_day()).take_while(|day| *day <= last) {
+        let duration = overlap(span, now, midnight_offset, day, day + 1.days());
+        if duration.is_positive() {
+            let day_totals = totals.entry(day).or_default();
+            // Only allocate the name the first time the project shows up that day
+            match day_totals.get_mut(span.project) {
+                Some(total) => *total += duration,
+                None => {
+                    day_totals.insert(span.project.to_owned(), duration);
+                }
+            }
+        }
+    }
+}
+
+/// Count how many times the project changed on each day.
+///
+/// Entries are counted on the day they start; consecutive entries of the same
+/// project (e.g. around an excluded break) aren't a switch.
+pub fn switches(entries: &[Entry], midnight_offset: Duration) -> BTreeMap<Date, usize> {
+    let mut switches = Switches::default();
+    for entry in entries {
+        switches.add(&entry.into(), midnight_offset);
+    }
+    switches.counts
+}
+
+/// Counts of [`switches`], one entry at a time.
+#[derive(Default)]
+pub struct Switches {
+    pub counts: BTreeMap<Date, usize>,
+    /// Day and project of the previous entry.
+    previous: Option<(Date, String)>,
+}
+
+impl Switches {
+    /// Count the entry that follows the ones added so far.
+    pub fn add(&mut self, span: &Span, midnight_offset: Duration) {
+        if span.kind != Kind::Work {
+            return;
+        }
+        let day = (span.start - midnight_offset).date();
+        let count = self.counts.entry(day).or_default();
+        match &mut self.previous {
+            Some((previous_day, project)) if project == span.project => *previous_day = day,
+            Some((previous_day, project)) => {
+                if *previous_day == day {
+                    *count += 1;
+                }
+                *previous_day = day;
+                // Reuse the allocation of the previous name
+                project.clear();
+                project.push_str(span.project);
+            }
+            None => self.previous = Some((day, span.project.to_owned())),
+        }
+    }
+}
+
+/// Print the total time tracked on each project, forever.
+pub fn full(totals: &DailyTotals, ongoing: Option<&Entry>, options: Options) -> Result<()> {
+    // BTreeMap instead of HashMap so the keys are sorted :>
+    let mut summary = BTreeMap::new();
+
+    let now = OffsetDateTime::now_local()?;
+
+    // Collect total time on each project
+    for (project, duration) in totals.values().flatten() {
+        *summary.entry(project.clone()).or_insert(Duration::ZERO) += *duration;
+    }
+
+    // Display summary as a table
+    let mut table = Table::new(["Project", "Time"]);
+    table
+        .align([Alignment::Left, Alignment::Right])
+        .color([Some(Color::Hashed), None])
+        .max_width(0, NAME_WIDTH)
+        .options(options);
+    for (project, duration) in summary {
+        table.row([project, duration_to_string(duration)?]);
+    }
+    print!("{}", table);
+
+    print_ongoing(ongoing, now)
+}
+
+/// A summary, e.g. to send by email.
+pub struct Report {
+    pub title: String,
+    pub table: Table,
+    pub footer: String,
+    /// What was done each day, if it's part of the report (see
+    /// [`activity_log`]).
+    pub activity: Option<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.title)?;
+        writeln!(f)?;
+        write!(f, "{}", self.table)?;
+        writeln!(f)?;
+        writeln!(f, "{}", self.footer)?;
+        if let Some(activity) = &self.activity {
+            writeln!(f)?;
+            write!(f, "{}", activity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Time spent on a project during a day, with the notes taken along the way.
+pub struct Activity {
+    pub project: String,
+    pub time: Duration,
+    pub notes: Vec<String>,
+}
+
+/// Group entries by the day they started on, then by project, in the order
+/// they were first worked on that day.
+///
+/// Ongoing entries are counted up to `now`, and days off are left out.
+pub fn activities(
+    entries: &[Entry],
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> BTreeMap<Date, Vec<Activity>> {
+    let mut days = BTreeMap::<Date, Vec<Activity>>::new();
+    for entry in entries.iter().filter(|entry| entry.kind != Kind::DayOff) {
+        let day = days
+            .entry((entry.start - midnight_offset).date())
+            .or_default();
+        let time = entry.duration(now);
+        let notes = entry.notes.iter().map(|note| note.text.clone());
+        match day
+            .iter_mut()
+            .find(|activity| activity.project == entry.project)
+        {
+            Some(activity) => {
+                activity.time += time;
+                activity.notes.extend(notes);
+            }
+            None => day.push(Activity {
+                project: entry.project.clone(),
+                time,
+                notes: notes.collect(),
+            }),
+        }
+    }
+    days
+}
+
+/// Write what was done each day, with the time spent on each project and its
+/// notes in a bullet list under it.
+///
+/// With `relative_to` (today), days are named after it, e.g. `Yesterday`.
+pub fn activity_log<'a>(
+    days: impl IntoIterator<Item = (&'a Date, &'a Vec<Activity>)>,
+    relative_to: Option<Date>,
+) -> Result<String> {
+    let mut log = String::new();
+    for (i, (day, activities)) in days.into_iter().enumerate() {
+        if i > 0 {
+            log.push('\n');
+        }
+        log.push_str(&match relative_to {
+            Some(today) => locale::relative_weekday_date(*day, today),
+            None => locale::weekday_date(*day),
+        });
+        log.push('\n');
+        for activity in activities {
+            log.push_str(&format!(
+                "  {:>7}  {}\n",
+                duration_to_string(activity.time)?,
+                activity.project
+            ));
+            for note in &activity.notes {
+                log.push_str(&format!("{:11}- {}\n", "", note));
+            }
+        }
+    }
+    Ok(log)
+}
+
+/// Time to work each day, in the weekly grid.
+#[derive(Debug, Clone, Copy)]
+pub struct DailyTarget<'a> {
+    pub target: Duration,
+    /// Holidays and days taken off, which are never short of the target.
+    pub days_off: &'a BTreeSet<Date>,
+}
+
+/// Print the time tracked on each project (or tag, depending on `by`) for
+/// each day of a week.
+///
+/// If `week` is the Monday of a calendar week, the summary is for that week;
+/// otherwise, it's for the seven days up to `today`.  With a `daily_target`,
+/// the daily totals are marked with `▲` or `▼` when they're above or below it.
+pub fn weekly(
+    totals: &DailyTotals,
+    ongoing: Option<&Entry>,
+    week: Option<Date>,
+    today: Date,
+    daily_target: Option<DailyTarget>,
+    by: GroupBy,
+    options: Options,
+) -> Result<()> {
+    print!(
+        "{}",
+        weekly_report(totals, week, today, daily_target, by, options)?
+    );
+
+    print_ongoing(ongoing, OffsetDateTime::now_local()?)
+}
+
+/// Summarize the time tracked on each project for each day of a week (see
+/// [`weekly`]).
+pub fn weekly_report(
+    totals: &DailyTotals,
+    week: Option<Date>,
+    today: Date,
+    daily_target: Option<DailyTarget>,
+    by: GroupBy,
+    options: Options,
+) -> Result<Report> {
+    // BTreeMap instead of HashMap so the keys are sorted :>
+    let mut summary = BTreeMap::<String, [Duration; 7]>::new();
+    let mut daily_total = [Duration::ZERO; 7];
+
+    // Last day of the summary
+    let last_day = week.map_or(today, |monday| monday + 6.days());
+
+    // Collect daily total time on each project
+    for (delta, total) in daily_total.iter_mut().enumerate() {
+        let day = last_day - (delta as i64).days();
+        for (project, duration) in totals.get(&day).into_iter().flatten() {
+            summary.entry(project.clone()).or_default()[delta] += *duration;
+            *total += *duration;
+        }
+    }
+
+    let title = if week.is_some() {
+        let (year, week, _) = last_day.to_iso_week_date();
+        format!(
+            "Summary for week {}-W{:02} ({} – {})",
+            year,
+            week,
+            locale::month_day(last_day - 6.days()),
+            locale::month_day(last_day),
+        )
+    } else {
+        // The past seven days may straddle two ISO weeks
+        let (first_week, last_week) = ((today - 6.days()).iso_week(), today.iso_week());
+        if first_week == last_week {
+            format!("Summary for the past week (week {})", last_week)
+        } else {
+            format!(
+                "Summary for the past week (weeks {}–{})",
+                first_week, last_week
+            )
+        }
+    };
+
+    // Display summary as a table, one column per day followed by the total
+    let days = (0..7)
+        .rev()
+        .map(|i| last_day - Duration::days(i))
+        .collect::<Vec<_>>();
+    let mut table = Table::new(
+        iter::once(Ok(by.heading().to_owned()))
+            .chain(
+                days.iter()
+                    .map(|d| d.format(&format_description!("[weekday]"))),
+            )
+            .chain(iter::once(Ok("Total".to_owned())))
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+    table
+        .header(
+            iter::once(Ok(String::new()))
+                .chain(
+                    days.iter()
+                        .map(|d| Ok::<_, time::error::Format>(locale::month_day(*d))),
+                )
+                .chain(iter::once(Ok(String::new())))
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+        .align(iter::once(Alignment::Left).chain([Alignment::Right; 8]))
+        .color(iter::once(Some(Color::Hashed)).chain([None; 8]))
+        .max_width(0, GRID_NAME_WIDTH)
+        .options(options);
+    for (project, durations) in summary {
+        let total = durations.iter().copied().sum();
+        table.row(
+            iter::once(Ok(project))
+                .chain(
+                    durations
+                        .into_iter()
+                        .rev()
+                        .chain(iter::once(total))
+                        .map(grid_cell),
+                )
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    table.separator();
+    let total = daily_total.iter().copied().sum();
+    let mut total_cells = vec!["TOTAL".to_owned()];
+    for (day, duration) in days.iter().zip(daily_total.into_iter().rev()) {
+        let mut cell = grid_cell(duration)?;
+        if let Some(DailyTarget { target, days_off }) = daily_target {
+            // Weekends and days off are never short of the target, and days
+            // to come aren't marked
+            let off = day.weekday().number_days_from_monday() >= 5 || days_off.contains(day);
+            match duration.cmp(&target) {
+                _ if *day > today => {}
+                Ordering::Less if !off => cell.push_str(" ▼"),
+                Ordering::Greater => cell.push_str(" ▲"),
+                _ => {}
+            }
+        }
+        total_cells.push(cell);
+    }
+    total_cells.push(grid_cell(total)?);
+    table.total(total_cells);
+
+    Ok(Report {
+        title,
+        table,
+        footer: format!("Weekly total: {}", duration_to_string(total)?),
+        activity: None,
+    })
+}
+
+/// Summarize the time tracked on each project from `first` to `last` (both
+/// included), with a column per day, week or month of the range, or only the
+/// totals.
+///
+/// The first and last weeks or months may be cut short by the range, in which
+/// case their columns only count the days in it.  Each column is rounded to a
+/// multiple of `round` as a whole, with [`rounding::round_table`].
+pub fn range_report(
+    totals: &DailyTotals,
+    first: Date,
+    last: Date,
+    breakdown: Breakdown,
+    round: Duration,
+    options: Options,
+) -> Result<Report> {
+    // First day of each column, with its heading and the line under it
+    let mut columns = vec![];
+    let mut day = first;
+    while day <= last {
+        let (heading, subheading, next) = match breakdown {
+            Breakdown::Day => (
+                day.format(format_description!("[weekday repr:short]"))?,
+                locale::month_day(day),
+                day + 1.days(),
+            ),
+            Breakdown::Week => {
+                let (year, week, _) = day.to_iso_week_date();
+                (
+                    format!("{}-W{:02}", year, week),
+                    locale::month_day(day),
+                    day + (7 - day.weekday().number_days_from_monday() as i64).days(),
+                )
+            }
+            Breakdown::Month => {
+                let (year, month) = match day.month() {
+                    Month::December => (day.year() + 1, Month::January),
+                    month => (day.year(), month.next()),
+                };
+                (
+                    day.format(format_description!("[month repr:short]"))?,
+                    day.year().to_string(),
+                    Date::from_calendar_date(year, month, 1)?,
+                )
+            }
+            Breakdown::Project => break,
+        };
+        columns.push((day, heading, subheading));
+        day = next;
+    }
+
+    // BTreeMap instead of HashMap so the keys are sorted :>  Without columns,
+    // there's only the total of each project to round
+    let mut summary = BTreeMap::<String, Vec<Duration>>::new();
+    for (day, projects) in totals.range(first..=last) {
+        // Last column starting on or before the day (the first one starts
+        // with the range)
+        let column = columns
+            .partition_point(|(start, _, _)| start <= day)
+            .saturating_sub(1);
+        for (project, duration) in projects {
+            summary
+                .entry(project.clone())
+                .or_insert_with(|| vec![Duration::ZERO; columns.len().max(1)])[column] += *duration;
+        }
+    }
+    let (projects, mut rows): (Vec<_>, Vec<_>) = summary.into_iter().unzip();
+    rounding::round_table(&mut rows, round);
+    let mut column_totals = vec![Duration::ZERO; columns.len()];
+    let mut total = Duration::ZERO;
+    for durations in &mut rows {
+        let sum = durations.iter().copied().sum::<Duration>();
+        if !columns.is_empty() {
+            for (column_total, duration) in column_totals.iter_mut().zip(durations.iter()) {
+                *column_total += *duration;
+            }
+            durations.push(sum);
+        }
+        total += sum;
+    }
+
+    // Display summary as a table, one column per period followed by the total
+    let width = columns.len() + 2;
+    let mut table = Table::new(
+        iter::once("Project".to_owned())
+            .chain(columns.iter().map(|(_, heading, _)| heading.clone()))
+            .chain(iter::once("Total".to_owned())),
+    );
+    if !columns.is_empty() {
+        table.header(
+            iter::once(String::new())
+                .chain(columns.iter().map(|(_, _, subheading)| subheading.clone()))
+                .chain(iter::once(String::new())),
+        );
+    }
+    table
+        .align(iter::once(Alignment::Left).chain(iter::repeat_n(Alignment::Right, width - 1)))
+        .color(iter::once(Some(Color::Hashed)).chain(iter::repeat_n(None, width - 1)))
+        .max_width(
+            0,
+            if columns.is_empty() {
+                NAME_WIDTH
+            } else {
+                GRID_NAME_WIDTH
+            },
+        )
+        .options(options);
+    for (project, durations) in projects.into_iter().zip(rows) {
+        // Rounded away, like in the total
+        if durations.last().is_some_and(|total| total.is_zero()) {
+            continue;
+        }
+        table.row(
+            iter::once(Ok(project))
+                .chain(durations.into_iter().map(grid_cell))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    table.separator();
+    table.total(
+        iter::once(Ok("TOTAL".to_owned()))
+            .chain(
+                column_totals
+                    .into_iter()
+                    .chain(iter::once(total))
+                    .map(grid_cell),
+            )
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+
+    Ok(Report {
+        title: format!(
+            "Summary from {} to {}",
+            locale::date(first),
+            locale::date(last)
+        ),
+        table,
+        footer: format!("Total: {}", duration_to_string(total)?),
+        activity: None,
+    })
+}
+
+/// Print a bar chart of the time tracked each calendar week, from the week of
+/// `first`.
+///
+/// Bars are scaled to the busiest week, in eighths of a character (or whole
+/// characters with `ascii`).
+pub fn trend(
+    totals: &DailyTotals,
+    project: Option<&str>,
+    first: Date,
+    weeks: u16,
+    ascii: bool,
+    options: Options,
+) -> Result<()> {
+    const EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+    let weekly_totals = (0..weeks as i64)
+        .map(|week| {
+            let monday = first + week.weeks();
+            let total = totals
+                .range(monday..monday + 7.days())
+                .flat_map(|(_, projects)| projects.values())
+                .sum::<Duration>();
+            (monday, total)
+        })
+        .collect::<Vec<_>>();
+    let busiest = weekly_totals
+        .iter()
+        .map(|(_, total)| *total)
+        .max()
+        .unwrap_or_default();
+
+    match project {
+        Some(project) => println!("Weekly time tracked on '{}'", project),
+        None => println!("Weekly time tracked"),
+    }
+    println!();
+
+    let mut table = Table::new(["Week", "Time", ""]);
+    table
+        .align([Alignment::Left, Alignment::Right, Alignment::Left])
+        .options(options);
+    for (monday, total) in weekly_totals {
+        let (year, week, _) = monday.to_iso_week_date();
+        let eighths = if busiest.is_zero() {
+            0
+        } else {
+            (total.whole_seconds() * TREND_WIDTH as i64 * 8 / busiest.whole_seconds()) as usize
+        };
+        let bar = if ascii {
+            "#".repeat(eighths / 8)
+        } else {
+            "█".repeat(eighths / 8) + EIGHTHS[eighths % 8]
+        };
+        table.row([format!("{}-W{:02}", year, week), grid_cell(total)?, bar]);
+    }
+    print!("{}", table);
+    Ok(())
+}
+
+/// Print the time tracked on each project for each month of a year.
+pub fn yearly(
+    totals: &DailyTotals,
+    ongoing: Option<&Entry>,
+    year: i32,
+    options: Options,
+) -> Result<()> {
+    // BTreeMap instead of HashMap so the keys are sorted :>
+    let mut summary = BTreeMap::<String, [Duration; 12]>::new();
+    let mut monthly_total = [Duration::ZERO; 12];
+
+    let now = OffsetDateTime::now_local()?;
+
+    // First day of each month, followed by the first day of the next year
+    let months = (1..=12)
+        .map(|month| Date::from_calendar_date(year, Month::try_from(month)?, 1))
+        .chain(iter::once(Date::from_calendar_date(
+            year + 1,
+            Month::January,
+            1,
+        )))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Collect monthly total time on each project
+    for (day, projects) in totals.range(months[0]..months[12]) {
+        let month = day.month() as usize - 1;
+        for (project, duration) in projects {
+            summary.entry(project.clone()).or_default()[month] += *duration;
+            monthly_total[month] += *duration;
+        }
+    }
+
+    println!("Summary for {}", year);
+    println!();
+
+    // Display summary as a table, one column per month
+    let mut table = Table::new(
+        iter::once(Ok("Project".to_owned()))
+            .chain(
+                months[..12]
+                    .iter()
+                    .map(|d| d.format(&format_description!("[month repr:short]"))),
+            )
+            .chain(iter::once(Ok("Total".to_owned())))
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+    table
+        .align(iter::once(Alignment::Left).chain([Alignment::Right; 13]))
+        .color(iter::once(Some(Color::Hashed)).chain([None; 13]))
+        .max_width(0, GRID_NAME_WIDTH)
+        .options(options);
+    for (project, durations) in summary {
+        let total = durations.iter().copied().sum();
+        table.row(
+            iter::once(Ok(project))
+                .chain(
+                    durations
+                        .into_iter()
+                        .chain(iter::once(total))
+                        .map(grid_cell),
+                )
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    table.separator();
+    let total = monthly_total.iter().copied().sum();
+    table.total(
+        iter::once(Ok("TOTAL".to_owned()))
+            .chain(
+                monthly_total
+                    .into_iter()
+                    .chain(iter::once(total))
+                    .map(grid_cell),
+            )
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+
+    print!("{}", table);
+
+    println!();
+    println!("Yearly total: {}", duration_to_string(total)?);
+
+    print_ongoing(ongoing, now)
+}
+
+/// Print the time tracked on each project on a day, e.g. `today`.
+pub fn daily(
+    totals: &DailyTotals,
+    ongoing: Option<&Entry>,
+    day: Date,
+    today: Date,
+    switches: usize,
+    options: Options,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let summary = totals.get(&day).cloned().unwrap_or_default();
+    let daily_total: Duration = summary.values().copied().sum();
+
+    let date = locale::month_day(day);
+    if day == today {
+        println!("Summary for today ({})", date);
+    } else {
+        println!("Summary for {} ({})", day.weekday(), date);
+    }
+    println!();
+
+    // Display summary as a table
+    let mut table = Table::new(["Project", "Time"]);
+    table
+        .align([Alignment::Left, Alignment::Right])
+        .color([Some(Color::Hashed), None])
+        .max_width(0, NAME_WIDTH)
+        .options(options);
+    for (project, duration) in summary {
+        table.row([project, duration_to_string(duration)?]);
+    }
+    table.separator();
+    table.total(["TOTAL".to_owned(), duration_to_string(daily_total)?]);
+    print!("{}", table);
+
+    if !daily_total.is_zero() {
+        println!();
+        println!("Project switches: {}", switches);
+    }
+
+    // The ongoing entry is only relevant today
+    print_ongoing(ongoing.filter(|_| day == today), now)
+}
+
+/// Print the time tracked on each project over the past few days up to
+/// `today`, with daily averages.
+pub fn last(
+    totals: &DailyTotals,
+    ongoing: Option<&Entry>,
+    days: u16,
+    today: Date,
+    options: Options,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let last_day = today;
+    let first_day = last_day - (days as i64 - 1).days();
+
+    // BTreeMap instead of HashMap so the keys are sorted :>
+    let mut summary = BTreeMap::<&str, Duration>::new();
+    for (project, duration) in totals
+        .range(first_day..=last_day)
+        .flat_map(|(_, projects)| projects)
+    {
+        *summary.entry(project).or_default() += *duration;
+    }
+    let total = summary.values().copied().sum::<Duration>();
+
+    println!(
+        "Summary for the last {} days ({} – {})",
+        days,
+        locale::month_day(first_day),
+        locale::month_day(last_day)
+    );
+    println!();
+
+    let mut table = Table::new(["Project", "Time", "Per day"]);
+    table
+        .align([Alignment::Left, Alignment::Right, Alignment::Right])
+        .color([Some(Color::Hashed), None, None])
+        .max_width(0, NAME_WIDTH)
+        .options(options);
+    for (project, duration) in summary {
+        table.row([
+            project.to_owned(),
+            duration_to_string(duration)?,
+            duration_to_string(duration / days as u32)?,
+        ]);
+    }
+    table.separator();
+    table.total([
+        "TOTAL".to_owned(),
+        duration_to_string(total)?,
+        duration_to_string(total / days as u32)?,
+    ]);
+    print!("{}", table);
+
+    print_ongoing(ongoing, now)
+}
+
+/// Print the total time tracked on each project over some days, e.g. those
+/// shown in a chart.
+pub fn days(totals: &DailyTotals, first: Date, last: Date, options: Options) -> Result<()> {
+    // BTreeMap instead of HashMap so the keys are sorted :>
+    let mut summary = BTreeMap::<&str, Duration>::new();
+    for (project, duration) in totals
+        .range(first..=last)
+        .flat_map(|(_, projects)| projects)
+    {
+        *summary.entry(project).or_default() += *duration;
+    }
+    let total = summary.values().copied().sum();
+
+    let mut table = Table::new(["Project", "Time"]);
+    table
+        .align([Alignment::Left, Alignment::Right])
+        .color([Some(Color::Hashed), None])
+        .max_width(0, NAME_WIDTH)
+        .options(options);
+    for (project, duration) in summary {
+        table.row([project.to_owned(), duration_to_string(duration)?]);
+    }
+    table.separator();
+    table.total(["TOTAL".to_owned(), duration_to_string(total)?]);
+    print!("{}", table);
+    Ok(())
+}
+
+/// Print the cumulative difference between the time tracked and a weekly target.
+///
+/// The target is spread evenly over the working days (Monday to Friday) from
+/// `since` to today, both included.  There's no target on `days_off`, which
+/// are counted in their own column.
+pub fn balance(
+    totals: &DailyTotals,
+    ongoing: Option<&Entry>,
+    since: Date,
+    weekly_target: Duration,
+    days_off: &BTreeSet<Date>,
+    options: Options,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+
+    println!("Balance since {}", locale::date(since));
+    println!();
+
+    // One row per calendar week
+    let mut table = Table::new([
+        "Week",
+        "Days off",
+        "Tracked",
+        "Target",
+        "Difference",
+        "Balance",
+    ]);
+    table
+        .align(iter::once(Alignment::Left).chain([Alignment::Right; 5]))
+        .options(options);
+    let mut balance = Duration::ZERO;
+    for week in balance_weeks(totals, since, now.date(), weekly_target, days_off) {
+        balance += week.tracked - week.target;
+        let (year, number, _) = week.monday.to_iso_week_date();
+        table.row([
+            format!("{}-W{:02}", year, number),
+            if week.days_off > 0 {
+                week.days_off.to_string()
+            } else {
+                "·".to_owned()
+            },
+            duration_to_string(week.tracked)?,
+            duration_to_string(week.target)?,
+            signed_duration_to_string(week.tracked - week.target)?,
+            signed_duration_to_string(balance)?,
+        ]);
+    }
+    print!("{}", table);
+
+    println!();
+    println!("Balance: {}", signed_duration_to_string(balance)?);
+
+    print_ongoing(ongoing, now)
+}
+
+/// Time tracked and target of a calendar week, for [`balance`].
+pub struct BalanceWeek {
+    pub monday: Date,
+    pub days_off: u32,
+    pub tracked: Duration,
+    pub target: Duration,
+}
+
+/// Compute the time tracked and the target of each calendar week from `since`
+/// to `today`.
+///
+/// The target is spread evenly over Monday to Friday, except on days off.
+pub fn balance_weeks(
+    totals: &DailyTotals,
+    since: Date,
+    today: Date,
+    weekly_target: Duration,
+    days_off: &BTreeSet<Date>,
+) -> Vec<BalanceWeek> {
+    let daily_target = weekly_target / 5;
+    let first_monday = since - (since.weekday().number_days_from_monday() as i64).days();
+    iter::successors(Some(first_monday), |monday| monday.checked_add(7.days()))
+        .take_while(|monday| *monday <= today)
+        .map(|monday| {
+            let mut week = BalanceWeek {
+                monday,
+                days_off: 0,
+                tracked: Duration::ZERO,
+                target: Duration::ZERO,
+            };
+            let days = (0..7)
+                .map(|i| monday + i.days())
+                .filter(|day| (since..=today).contains(day));
+            for day in days {
+                week.tracked += totals
+                    .get(&day)
+                    .map_or(Duration::ZERO, |projects| projects.values().copied().sum());
+                if day.weekday().number_days_from_monday() >= 5 {
+                    continue;
+                }
+                if days_off.contains(&day) {
+                    week.days_off += 1;
+                } else {
+                    week.target += daily_target;
+                }
+            }
+            week
+        })
+        .collect()
+}
+
+/// Moment a day starts, i.e. midnight shifted by `midnight_offset`, in the
+/// timezone of `now`.
+pub fn start_of_day(date: Date, now: OffsetDateTime, midnight_offset: Duration) -> OffsetDateTime {
+    date.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + midnight_offset
+}
+
+/// Compute how much of an entry falls within a range of days.
+///
+/// Days start at midnight shifted by `midnight_offset`, so that an entry
+/// spanning a day boundary is split between both days.  `to` is exclusive.
+fn overlap(
+    entry: &Span,
+    now: OffsetDateTime,
+    midnight_offset: Duration,
+    from: Date,
+    to: Date,
+) -> Duration {
+    let day_start = |date: Date| date.with_time(Time::MIDNIGHT).assume_offset(now.offset());
+    let start = entry.start - midnight_offset;
+    let end = entry.end.unwrap_or(now) - midnight_offset;
+    (end.min(day_start(to)) - start.max(day_start(from))).max(Duration::ZERO)
+}
+
+/// Format a cell of a weekly or yearly grid.
+///
+/// Empty cells are shown as a dot, so that it's easy to see at a glance which
+/// days a project was worked on.
+fn grid_cell(duration: Duration) -> Result<String, std::fmt::Error> {
+    if duration.is_zero() {
+        Ok("·".to_owned())
+    } else {
+        duration_to_string(duration)
+    }
+}
+
+/// Print the ongoing entry, if there is one.
+fn print_ongoing(ongoing: Option<&Entry>, now: OffsetDateTime) -> Result<()> {
+    if let Some(entry) = ongoing {
+        println!();
+        println!(
+            "Ongoing: {} ({})",
+            entry.project,
+            duration_to_string(now - entry.start)?
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+
+    #[test]
+    fn today_starts_at_the_midnight_offset() {
+        // Before the offset, it's still yesterday
+        let now = datetime!(2026-10-17 04:34 +2);
+        let offset = 5.hours();
+        let today = (now - offset).date();
+        let start = start_of_day(today, now, offset);
+        assert_eq!(start, datetime!(2026-10-16 05:00 +2));
+
+        // An entry from that morning is read, and counts for that day
+        let span = Span {
+            project: "a",
+            start: datetime!(2026-10-16 10:00 +2),
+            end: Some(datetime!(2026-10-16 12:00 +2)),
+            kind: Kind::Work,
+            context: None,
+            tags: "".into(),
+        };
+        assert!(span.start >= start);
+        let mut totals = DailyTotals::new();
+        add_to_daily_totals(&mut totals, &span, offset, now);
+        assert_eq!(totals[&today]["a"], 2.hours());
+    }
+
+    #[test]
+    fn balance_of_partial_weeks() {
+        let totals = DailyTotals::from([
+            // Before the first day
+            (
+                date!(2021 - 09 - 14),
+                BTreeMap::from([("a".to_owned(), 5.hours())]),
+            ),
+            (
+                date!(2021 - 09 - 15),
+                BTreeMap::from([("a".to_owned(), 8.hours())]),
+            ),
+            // On a weekend
+            (
+                date!(2021 - 09 - 18),
+                BTreeMap::from([("b".to_owned(), 2.hours())]),
+            ),
+            (
+                date!(2021 - 09 - 20),
+                BTreeMap::from([("a".to_owned(), 3.hours()), ("b".to_owned(), 4.hours())]),
+            ),
+        ]);
+        let days_off = BTreeSet::from([date!(2021 - 09 - 16)]);
+        let weeks = balance_weeks(
+            &totals,
+            date!(2021 - 09 - 15),
+            date!(2021 - 09 - 21),
+            40.hours(),
+            &days_off,
+        );
+
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].monday, date!(2021 - 09 - 13));
+        assert_eq!(weeks[0].days_off, 1);
+        assert_eq!(weeks[0].tracked, 10.hours());
+        // Wednesday and Friday
+        assert_eq!(weeks[0].target, 16.hours());
+        assert_eq!(weeks[1].monday, date!(2021 - 09 - 20));
+        assert_eq!(weeks[1].days_off, 0);
+        assert_eq!(weeks[1].tracked, 7.hours());
+        // Monday and Tuesday
+        assert_eq!(weeks[1].target, 16.hours());
+    }
+
+    #[test]
+    fn days_off_are_not_short_of_the_target() {
+        let day = |date, hours: i64| (date, BTreeMap::from([("a".to_owned(), hours.hours())]));
+        let totals = DailyTotals::from([
+            day(date!(2021 - 09 - 13), 4),
+            day(date!(2021 - 09 - 14), 9),
+            // Holiday, and a day off
+            day(date!(2021 - 09 - 15), 2),
+            day(date!(2021 - 09 - 16), 0),
+            day(date!(2021 - 09 - 17), 8),
+        ]);
+        let days_off = BTreeSet::from([date!(2021 - 09 - 15), date!(2021 - 09 - 16)]);
+        let report = weekly_report(
+            &totals,
+            Some(date!(2021 - 09 - 13)),
+            date!(2021 - 09 - 20),
+            Some(DailyTarget {
+                target: 8.hours(),
+                days_off: &days_off,
+            }),
+            GroupBy::Project,
+            Options::default(),
+        )
+        .unwrap();
+        let table = report.table.to_string();
+        let total = table
+            .lines()
+            .find(|line| line.starts_with("TOTAL"))
+            .unwrap();
+        // Only Monday is short, and Tuesday is over
+        assert_eq!(total.matches('▼').count(), 1);
+        assert_eq!(total.matches('▲').count(), 1);
+        assert!(total.contains("4h 00m ▼"));
+    }
+}
@@ -1,81 +1,552 @@
+use std::borrow::Cow;
 use std::fmt;
+use std::iter;
 
-pub struct Table<const N: usize> {
-    headers: [String; N],
-    rows: Vec<[String; N]>,
-    widths: [usize; N],
-    alignments: [Alignment; N],
+use serde::Deserialize;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Maximum width of names (e.g. projects or tags) in tables.
+pub const NAME_WIDTH: usize = 40;
+/// Maximum width of names in grids, where horizontal space is scarce.
+pub const GRID_NAME_WIDTH: usize = 20;
+
+/// A table with any number of columns.
+///
+/// Every row must have as many cells as there are headers.
+pub struct Table {
+    headers: Vec<Vec<String>>,
+    rows: Vec<Vec<String>>,
+    /// Indices of the rows that contain totals.
+    totals: Vec<usize>,
+    /// Indices of the rows that are over budget.
+    over_budget: Vec<usize>,
+    widths: Vec<usize>,
+    max_widths: Vec<Option<usize>>,
+    alignments: Vec<Alignment>,
+    colors: Vec<Option<Color>>,
+    options: Options,
 }
 
-impl<const N: usize> Table<N> {
-    pub fn new(headers: [impl Into<String>; N]) -> Self {
-        let headers = headers.map(Into::into);
-        let mut widths = [0; N];
-        for (i, width) in widths.iter_mut().enumerate() {
-            *width = headers[i].len();
-        }
+/// How tables are displayed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    pub style: Style,
+    /// Whether to use ANSI colors (never in Markdown tables).
+    pub colored: bool,
+    /// Whether to ignore maximum column widths.
+    pub full_width: bool,
+}
+
+impl Table {
+    pub fn new<S: Into<String>>(headers: impl IntoIterator<Item = S>) -> Self {
+        let headers = headers.into_iter().map(Into::into).collect::<Vec<_>>();
+        let columns = headers.len();
         Table {
-            headers,
+            widths: headers.iter().map(|header| display_width(header)).collect(),
+            max_widths: vec![None; columns],
+            headers: vec![headers],
             rows: vec![],
-            widths,
-            alignments: [Alignment::Left; N],
+            totals: vec![],
+            over_budget: vec![],
+            alignments: vec![Alignment::Left; columns],
+            colors: vec![None; columns],
+            options: Options::default(),
         }
     }
 
-    pub fn align(&mut self, alignments: [Alignment; N]) -> &mut Self {
-        self.alignments = alignments;
+    pub fn align(&mut self, alignments: impl IntoIterator<Item = Alignment>) -> &mut Self {
+        self.alignments = alignments.into_iter().collect();
+        assert_eq!(self.alignments.len(), self.widths.len());
         self
     }
 
-    pub fn row(&mut self, row: [impl Into<String>; N]) -> &mut Self {
-        let row = row.map(Into::into);
-        for (i, width) in self.widths.iter_mut().enumerate() {
-            *width = (*width).max(row[i].len());
-        }
+    /// Set the color of the cells of each column (headers and totals aside).
+    pub fn color(&mut self, colors: impl IntoIterator<Item = Option<Color>>) -> &mut Self {
+        self.colors = colors.into_iter().collect();
+        assert_eq!(self.colors.len(), self.widths.len());
+        self
+    }
+
+    /// Limit the width of a column; longer cells are cut off with an ellipsis.
+    pub fn max_width(&mut self, column: usize, width: usize) -> &mut Self {
+        self.max_widths[column] = Some(width);
+        self
+    }
+
+    pub fn options(&mut self, options: Options) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    /// Add an extra line to the headers, displayed under the previous ones.
+    pub fn header<S: Into<String>>(&mut self, header: impl IntoIterator<Item = S>) -> &mut Self {
+        let header = self.fit(header);
+        self.headers.push(header);
+        self
+    }
+
+    /// Add a row.  A row of empty cells is displayed as a separator.
+    pub fn row<S: Into<String>>(&mut self, row: impl IntoIterator<Item = S>) -> &mut Self {
+        let row = self.fit(row);
         self.rows.push(row);
         self
     }
 
+    /// Add a row of empty cells, displayed as a separator.
+    pub fn separator(&mut self) -> &mut Self {
+        self.row(vec![""; self.widths.len()])
+    }
+
+    /// Add a row of totals, emphasized when colors are enabled.
+    pub fn total<S: Into<String>>(&mut self, row: impl IntoIterator<Item = S>) -> &mut Self {
+        self.totals.push(self.rows.len());
+        self.row(row)
+    }
+
+    /// Add a row that's over budget, shown in red when colors are enabled.
+    pub fn over_budget<S: Into<String>>(&mut self, row: impl IntoIterator<Item = S>) -> &mut Self {
+        self.over_budget.push(self.rows.len());
+        self.row(row)
+    }
+
+    /// Render the table as HTML, e.g. for emails.
+    ///
+    /// Cells are never cut off, totals are emphasized, and rows over budget
+    /// are red.
+    pub fn html(&self) -> String {
+        let align = |i: usize| match self.alignments[i] {
+            Alignment::Left => "left",
+            Alignment::Right => "right",
+        };
+        let row = |row: &[String], tag: &str, red: bool| {
+            let mut html = "<tr>".to_owned();
+            for (i, cell) in row.iter().enumerate() {
+                html.push_str(&format!(
+                    "<{tag} style=\"text-align: {}{}\">{}</{tag}>",
+                    align(i),
+                    if red { "; color: #c00" } else { "" },
+                    escape(cell),
+                ));
+            }
+            html.push_str("</tr>\n");
+            html
+        };
+
+        let mut html = "<table>\n<thead>\n".to_owned();
+        for header in &self.headers {
+            html.push_str(&row(header, "th", false));
+        }
+        html.push_str("</thead>\n<tbody>\n");
+        for (i, cells) in self.rows.iter().enumerate() {
+            if cells.iter().all(String::is_empty) {
+                continue;
+            }
+            html.push_str(&row(
+                cells,
+                if self.totals.contains(&i) { "th" } else { "td" },
+                self.over_budget.contains(&i),
+            ));
+        }
+        html.push_str("</tbody>\n</table>\n");
+        html
+    }
+
+    /// Convert a row to strings and widen the columns to fit it.
+    fn fit<S: Into<String>>(&mut self, row: impl IntoIterator<Item = S>) -> Vec<String> {
+        let row = row.into_iter().map(Into::into).collect::<Vec<_>>();
+        assert_eq!(row.len(), self.widths.len(), "wrong number of cells");
+        for (width, cell) in self.widths.iter_mut().zip(&row) {
+            *width = (*width).max(display_width(cell));
+        }
+        row
+    }
+
+    /// Cut off a cell that doesn't fit in its column.
+    fn truncate<'a>(&self, column: usize, cell: &'a str) -> Cow<'a, str> {
+        match self.max_widths[column] {
+            Some(max_width) if !self.options.full_width && display_width(cell) > max_width => {
+                let mut truncated = String::new();
+                let mut width = 0;
+                for c in cell.chars() {
+                    width += c.width().unwrap_or(0);
+                    if width + 1 > max_width {
+                        break;
+                    }
+                    truncated.push(c);
+                }
+                truncated.push('…');
+                Cow::Owned(truncated)
+            }
+            _ => Cow::Borrowed(cell),
+        }
+    }
+
+    /// Width of each column, once cells have been cut off.
+    fn column_widths(&self) -> Vec<usize> {
+        self.widths
+            .iter()
+            .zip(&self.max_widths)
+            .map(|(&width, max_width)| match max_width {
+                Some(max_width) if !self.options.full_width => width.min(*max_width),
+                _ => width,
+            })
+            .collect()
+    }
+
+    /// Colors of the cells of a row, given whether it's a header or a total,
+    /// and whether it's over budget.
+    fn row_colors(&self, emphasized: bool, over_budget: bool) -> Vec<Option<Color>> {
+        let columns = self.widths.len();
+        if !self.options.colored || self.options.style == Style::Markdown {
+            vec![None; columns]
+        } else if emphasized {
+            vec![Some(Color::Bold); columns]
+        } else if over_budget {
+            vec![Some(Color::OverBudget); columns]
+        } else {
+            self.colors.clone()
+        }
+    }
+
     #[inline(always)]
     fn fmt_row(
         &self,
         f: &mut fmt::Formatter<'_>,
-        row: &[String; N],
+        row: &[String],
+        widths: &[usize],
+        colors: &[Option<Color>],
     ) -> Result<(), std::fmt::Error> {
+        let (left, middle, right) = match self.options.style {
+            Style::Plain => ("", "  ", "  "),
+            Style::Ascii | Style::Markdown => ("| ", " | ", " |"),
+            Style::Rounded => ("│ ", " │ ", " │"),
+        };
+        write!(f, "{}", left)?;
         for (i, column) in row.iter().enumerate() {
-            match self.alignments[i] {
-                Alignment::Left => write!(f, "{: <width$}  ", column, width = self.widths[i])?,
-                Alignment::Center => write!(f, "{: ^width$}  ", column, width = self.widths[i])?,
-                Alignment::Right => write!(f, "{: >width$}  ", column, width = self.widths[i])?,
-            }
+            let column = self.truncate(i, column);
+            // Pad by display width, since wide characters take up two columns
+            let padding = widths[i] - display_width(&column);
+            let (before, after) = match self.alignments[i] {
+                Alignment::Left => (0, padding),
+                Alignment::Right => (padding, 0),
+            };
+            let separator = if i + 1 < row.len() { middle } else { right };
+            let column = match colors[i] {
+                Some(color) if !column.is_empty() => color.paint(&column),
+                _ => column.into_owned(),
+            };
+            write!(f, "{:before$}{}{:after$}{}", "", column, "", separator)?;
         }
         writeln!(f)?;
         Ok(())
     }
-}
 
-impl<const N: usize> fmt::Display for Table<N> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        self.fmt_row(f, &self.headers)?;
-        for i in 0..self.headers.len() {
-            write!(f, "{:-<width$}  ", "", width = self.widths[i])?;
+    /// Write a horizontal line, made of a fill character and junctions.
+    fn fmt_line(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        [left, fill, middle, right]: [char; 4],
+    ) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", left)?;
+        let widths = self.column_widths();
+        for (i, width) in widths.iter().enumerate() {
+            // Cells are padded with a space on each side
+            let junction = if i + 1 < widths.len() { middle } else { right };
+            write!(f, "{}{}", fill.to_string().repeat(width + 2), junction)?;
+        }
+        writeln!(f)?;
+        Ok(())
+    }
+
+    /// Write the rows, with separators between groups of rows.
+    fn fmt_rows(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        separator: Option<[char; 4]>,
+    ) -> Result<(), std::fmt::Error> {
+        let widths = self.column_widths();
+        for (i, row) in self.rows.iter().enumerate() {
+            let colors = self.row_colors(self.totals.contains(&i), self.over_budget.contains(&i));
+            match separator {
+                Some(line) if row.iter().all(String::is_empty) => self.fmt_line(f, line)?,
+                _ => self.fmt_row(f, row, &widths, &colors)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn fmt_headers(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for header in &self.headers {
+            self.fmt_row(
+                f,
+                header,
+                &self.column_widths(),
+                &self.row_colors(true, false),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn fmt_plain(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        self.fmt_headers(f)?;
+        let widths = self.column_widths();
+        for width in &widths {
+            write!(f, "{:-<width$}  ", "", width = width)?;
         }
         writeln!(f)?;
-        for row in &self.rows {
-            self.fmt_row(f, row)?;
+        self.fmt_rows(f, None)?;
+        for width in &widths {
+            write!(f, "{:-<width$}  ", "", width = width)?;
         }
-        for i in 0..self.headers.len() {
-            write!(f, "{:-<width$}  ", "", width = self.widths[i])?;
+        writeln!(f)?;
+        self.fmt_headers(f)
+    }
+
+    fn fmt_boxed(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        [top, middle, bottom]: [[char; 4]; 3],
+    ) -> Result<(), std::fmt::Error> {
+        self.fmt_line(f, top)?;
+        self.fmt_headers(f)?;
+        self.fmt_line(f, middle)?;
+        self.fmt_rows(f, Some(middle))?;
+        self.fmt_line(f, bottom)
+    }
+
+    fn fmt_markdown(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        // Pipes would end the cell early
+        let escape = |row: &Vec<String>| {
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| self.truncate(i, cell).replace('|', "\\|"))
+                .collect::<Vec<_>>()
+        };
+        // Markdown tables have a single header line, so merge them
+        let header = (0..self.widths.len())
+            .map(|i| {
+                self.headers
+                    .iter()
+                    .map(|header| header[i].as_str())
+                    .filter(|cell| !cell.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        let header = escape(&header);
+        let rows = self
+            .rows
+            .iter()
+            .filter(|row| !row.iter().all(String::is_empty))
+            .map(escape)
+            .collect::<Vec<_>>();
+
+        // Delimiter cells need at least three characters
+        let mut widths = vec![3; self.widths.len()];
+        for row in iter::once(&header).chain(&rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(display_width(cell));
+            }
+        }
+
+        let colors = vec![None; widths.len()];
+        self.fmt_row(f, &header, &widths, &colors)?;
+        write!(f, "|")?;
+        for (width, alignment) in widths.iter().zip(&self.alignments) {
+            let dashes = "-".repeat(*width);
+            match alignment {
+                Alignment::Left => write!(f, " :{} |", &dashes[1..])?,
+                Alignment::Right => write!(f, " {}: |", &dashes[1..])?,
+            }
         }
         writeln!(f)?;
-        self.fmt_row(f, &self.headers)?;
+        for row in &rows {
+            self.fmt_row(f, row, &widths, &colors)?;
+        }
         Ok(())
     }
 }
 
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.options.style {
+            Style::Plain => self.fmt_plain(f),
+            Style::Ascii => self.fmt_boxed(
+                f,
+                [
+                    ['+', '-', '+', '+'],
+                    ['+', '-', '+', '+'],
+                    ['+', '-', '+', '+'],
+                ],
+            ),
+            Style::Rounded => self.fmt_boxed(
+                f,
+                [
+                    ['╭', '─', '┬', '╮'],
+                    ['├', '─', '┼', '┤'],
+                    ['╰', '─', '┴', '╯'],
+                ],
+            ),
+            Style::Markdown => self.fmt_markdown(f),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Alignment {
     Left,
-    Center,
     Right,
 }
+
+/// How tables are drawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Style {
+    /// Columns separated by spaces, with the headers repeated at the bottom
+    #[default]
+    Plain,
+    /// Borders drawn with `+`, `-` and `|`
+    Ascii,
+    /// Borders drawn with box-drawing characters
+    Rounded,
+    /// A GitHub-flavoured Markdown table
+    Markdown,
+}
+
+/// ANSI colors and attributes, for terminals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Bold,
+    /// One of a few colors, picked from the text itself so that it's stable
+    /// across runs (e.g. for project names).
+    Hashed,
+    /// Green, for added lines
+    Added,
+    /// Red, for removed lines
+    Removed,
+    /// Red, for rows over budget
+    OverBudget,
+}
+
+impl Color {
+    /// Wrap some text in the escape sequences for this color.
+    pub fn paint(self, text: &str) -> String {
+        self.paint_as(text, text)
+    }
+
+    /// Wrap some text in the escape sequences for this color, as picked for
+    /// another text (e.g. to draw a project's bar in the project's color).
+    pub fn paint_as(self, text: &str, key: &str) -> String {
+        // Red is left out, so that it can mean something's wrong
+        const PALETTE: [u8; 10] = [32, 33, 34, 35, 36, 92, 93, 94, 95, 96];
+        let code = match self {
+            Color::Bold => 1,
+            Color::Hashed => {
+                let hash = key
+                    .bytes()
+                    .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+                PALETTE[hash as usize % PALETTE.len()]
+            }
+            Color::Added => 32,
+            Color::Removed | Color::OverBudget => 31,
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+/// Display width of some text, ignoring ANSI escape sequences.
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        width += rest[..start].width();
+        // Sequences end with a letter, e.g. `\x1b[1m`
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| c.is_ascii_alphabetic())
+            .map_or(rest.len(), |i| i + 1);
+        rest = &rest[end..];
+    }
+    width + rest.width()
+}
+
+/// Escape text to put it in HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(style: Style, colored: bool) -> Options {
+        Options {
+            style,
+            colored,
+            full_width: false,
+        }
+    }
+
+    #[test]
+    fn width_without_escape_sequences() {
+        assert_eq!(display_width(&Color::Hashed.paint("project")), 7);
+        assert_eq!(display_width("\x1b[1mTOTAL\x1b[0m ▲"), 7);
+        // Wide characters take up two columns
+        assert_eq!(display_width("日本"), 4);
+    }
+
+    #[test]
+    fn colored_cells_are_aligned() {
+        let mut table = Table::new(["Project", "Time"]);
+        table
+            .align([Alignment::Left, Alignment::Right])
+            .color([Some(Color::Hashed), None])
+            .options(options(Style::Rounded, true));
+        table.row(["a", "1h 00m"]);
+        // Already painted, like the lines of a diff
+        table.row([Color::Added.paint("longer"), "10h 00m".to_owned()]);
+        let text = table.to_string();
+        let widths = text.lines().map(display_width).collect::<Vec<_>>();
+        assert!(widths.iter().all(|&width| width == widths[0]), "{}", text);
+        assert!(text.contains("\x1b[32mlonger\x1b[0m"));
+    }
+
+    #[test]
+    fn long_cells_are_cut_off() {
+        let mut table = Table::new(["Project"]);
+        table.max_width(0, 5).options(options(Style::Plain, false));
+        table.row(["abcdefgh"]).row(["日本語です"]).row(["short"]);
+        let text = table.to_string();
+        assert!(text.contains("abcd…"));
+        assert!(text.contains("日本…"));
+        assert!(text.contains("short"));
+        assert!(!text.contains("abcde"));
+
+        table.options(Options {
+            full_width: true,
+            ..options(Style::Plain, false)
+        });
+        assert!(table.to_string().contains("abcdefgh"));
+    }
+
+    #[test]
+    fn pipes_are_escaped_in_markdown() {
+        let mut table = Table::new(["Project", "Notes"]);
+        table
+            .header(["", "so far"])
+            .align([Alignment::Left, Alignment::Right])
+            .options(options(Style::Markdown, true));
+        table.row(["a|b", "x || y"]);
+        let text = table.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "| Project | Notes so far |");
+        assert_eq!(lines[1], "| :------ | -----------: |");
+        assert!(lines[2].starts_with("| a\\|b "));
+        assert!(lines[2].contains("x \\|\\| y"));
+        // Never colored
+        assert!(!text.contains('\x1b'));
+    }
+}
@@ -0,0 +1,377 @@
+//! Reports written from templates.
+//!
+//! Templates use the Jinja syntax, and live in the `templates` directory next
+//! to the config file (e.g. `~/.config/temps/templates/timesheet.txt`).  They
+//! are given a [`Context`] describing the time tracked during the report, and
+//! can write numbers and amounts of money the way the locale and the
+//! `[invoice]` section of the config say, with the `number` and `money`
+//! filters.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use minijinja::Environment;
+use serde::Serialize;
+use time::ext::NumericalDuration;
+use time::macros::format_description;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::config::{CurrencyPosition, InvoiceConfig};
+use crate::rounding::{self, CENTIHOUR};
+use crate::summary::{self, DailyTotals};
+use crate::{duration_to_string, locale, Entry};
+
+/// Starter templates, to be edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Starter {
+    /// The time spent on each project, day by day
+    Timesheet,
+    /// What was worked on, with the notes taken along the way
+    Standup,
+    /// The hours spent on each project, at an hourly rate
+    Invoice,
+}
+
+impl Starter {
+    fn name(self) -> &'static str {
+        match self {
+            Starter::Timesheet => "timesheet",
+            Starter::Standup => "standup",
+            Starter::Invoice => "invoice",
+        }
+    }
+
+    fn contents(self) -> &'static str {
+        match self {
+            Starter::Timesheet => include_str!("templates/timesheet.txt"),
+            Starter::Standup => include_str!("templates/standup.txt"),
+            Starter::Invoice => include_str!("templates/invoice.txt"),
+        }
+    }
+}
+
+/// What templates know about a report.
+#[derive(Serialize)]
+pub struct Context {
+    /// First and last days of the report, as `YYYY-MM-DD`.
+    from: String,
+    to: String,
+    days: Vec<Day>,
+    /// Time spent on each project during the whole report.
+    projects: Vec<Total>,
+    total: Span,
+    entries: Vec<Item>,
+    /// Default currency of amounts, if one is configured.
+    currency: Option<String>,
+    /// What the projects with a rate add up to, if any has one.
+    amount: Option<f64>,
+    /// Only given by `temps invoice`.
+    invoice: Option<Invoice>,
+}
+
+/// The invoice being written.
+#[derive(Serialize)]
+struct Invoice {
+    number: u32,
+    /// Day the invoice is written, as `YYYY-MM-DD`.
+    date: String,
+    client: String,
+}
+
+#[derive(Serialize)]
+struct Day {
+    date: String,
+    weekday: String,
+    projects: Vec<Total>,
+    total: Span,
+}
+
+#[derive(Serialize)]
+struct Total {
+    project: String,
+    time: Span,
+    /// Notes of the project's entries, in the order they were taken.
+    notes: Vec<String>,
+    /// Currency of the project's amounts, if one is configured.
+    currency: Option<String>,
+    /// Hourly rate, and what the hours amount to, if a rate is configured.
+    rate: Option<f64>,
+    amount: Option<f64>,
+}
+
+/// A duration, written out for people (`text`) and for computations.
+#[derive(Serialize)]
+struct Span {
+    text: String,
+    hours: f64,
+    seconds: i64,
+}
+
+#[derive(Serialize)]
+struct Item {
+    project: String,
+    date: String,
+    start: String,
+    /// Missing for the ongoing entry.
+    end: Option<String>,
+    time: Span,
+    tags: Vec<String>,
+    notes: Vec<String>,
+}
+
+impl Span {
+    fn new(duration: Duration) -> Result<Self> {
+        // Rounded to the minute, like the text
+        let centihours = (duration.whole_minutes() as f64 / 60.0 * 100.0).round() as i64;
+        Self::with_centihours(duration, centihours)
+    }
+
+    fn with_centihours(duration: Duration, centihours: i64) -> Result<Self> {
+        Ok(Span {
+            text: duration_to_string(duration)?,
+            hours: centihours as f64 / 100.0,
+            seconds: duration.whole_seconds(),
+        })
+    }
+}
+
+impl Context {
+    /// Describe the time tracked from `first` to `last` (both included).
+    ///
+    /// `totals` are the daily totals of `entries`, up to `now`.
+    pub fn new(
+        entries: &[Entry],
+        totals: &DailyTotals,
+        first: Date,
+        last: Date,
+        midnight_offset: Duration,
+        now: OffsetDateTime,
+        invoice: &InvoiceConfig,
+    ) -> Result<Self> {
+        let rates = |project: &str| {
+            (
+                invoice.currency_of(project).map(str::to_owned),
+                invoice.rate_of(project),
+            )
+        };
+        let activities = summary::activities(entries, midnight_offset, now);
+        let notes_of = |day: Date, project: &str| {
+            activities
+                .get(&day)
+                .into_iter()
+                .flatten()
+                .filter(|activity| activity.project == project)
+                .flat_map(|activity| activity.notes.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        let mut days = vec![];
+        let mut projects = BTreeMap::<String, Duration>::new();
+        let mut total = Duration::ZERO;
+        let mut day = first;
+        while day <= last {
+            let day_totals = totals.get(&day).cloned().unwrap_or_default();
+            for (project, duration) in &day_totals {
+                *projects.entry(project.clone()).or_default() += *duration;
+            }
+            let day_total = day_totals.values().copied().sum();
+            total += day_total;
+            days.push(Day {
+                date: day.to_string(),
+                weekday: day.weekday().to_string(),
+                projects: totals_of(day_totals, |project| notes_of(day, project), rates)?,
+                total: Span::new(day_total)?,
+            });
+            day += 1.days();
+        }
+
+        let hhmm = format_description!("[hour]:[minute]");
+        let entries = entries
+            .iter()
+            .filter(|entry| (first..=last).contains(&(entry.start - midnight_offset).date()))
+            .map(|entry| {
+                Ok(Item {
+                    project: entry.project.clone(),
+                    date: (entry.start - midnight_offset).date().to_string(),
+                    start: entry.start.format(hhmm)?,
+                    end: entry.end.map(|end| end.format(hhmm)).transpose()?,
+                    time: Span::new(entry.duration(now))?,
+                    tags: entry.tags.clone(),
+                    notes: entry.notes.iter().map(|note| note.text.clone()).collect(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let projects = totals_of(
+            projects,
+            |project| {
+                let mut notes = vec![];
+                let mut day = first;
+                while day <= last {
+                    notes.extend(notes_of(day, project));
+                    day += 1.days();
+                }
+                notes
+            },
+            rates,
+        )?;
+        let amounts = projects.iter().filter_map(|total| total.amount);
+        let amount = amounts.clone().next().map(|_| cents(amounts.sum()));
+        Ok(Context {
+            from: first.to_string(),
+            to: last.to_string(),
+            days,
+            projects,
+            total: Span::new(total)?,
+            entries,
+            currency: invoice.currency.clone(),
+            amount,
+            invoice: None,
+        })
+    }
+
+    /// Make the report an invoice to a client, whose amounts are in its
+    /// currency.
+    pub fn invoice(&mut self, number: u32, date: Date, client: &str, config: &InvoiceConfig) {
+        self.currency = config.currency_of(client).map(str::to_owned);
+        self.invoice = Some(Invoice {
+            number,
+            date: date.to_string(),
+            client: client.to_owned(),
+        });
+    }
+
+    /// Hours of the whole report.
+    pub fn hours(&self) -> f64 {
+        self.total.hours
+    }
+
+    /// What the projects with a rate add up to, if any has one.
+    pub fn amount(&self) -> Option<f64> {
+        self.amount
+    }
+
+    /// Currency of the report's amounts, if there's one.
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+}
+
+/// An amount rounded to the cent.
+fn cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Describe the time spent on each project, with hours that add up to the
+/// hours of their total (see [`rounding`]).
+fn totals_of(
+    totals: impl IntoIterator<Item = (String, Duration)>,
+    notes_of: impl Fn(&str) -> Vec<String>,
+    rates: impl Fn(&str) -> (Option<String>, Option<f64>),
+) -> Result<Vec<Total>> {
+    let totals = totals.into_iter().collect::<Vec<_>>();
+    let seconds = totals
+        .iter()
+        .map(|(_, duration)| duration.whole_minutes().max(0) * 60)
+        .collect::<Vec<_>>();
+    let centihours = rounding::largest_remainder(&seconds, CENTIHOUR);
+    totals
+        .into_iter()
+        .zip(centihours)
+        .map(|((project, duration), centihours)| {
+            let (currency, rate) = rates(&project);
+            let time = Span::with_centihours(duration, centihours)?;
+            Ok(Total {
+                notes: notes_of(&project),
+                currency,
+                rate,
+                amount: rate.map(|rate| cents(time.hours * rate)),
+                project,
+                time,
+            })
+        })
+        .collect()
+}
+
+/// An amount of money with two decimals, in a currency if there's one, e.g.
+/// `€1,234.50`, `USD 1,234.50` or `1.234,50 €`.
+fn money(amount: f64, currency: Option<&str>, position: Option<CurrencyPosition>) -> String {
+    let number = locale::number(amount.abs(), 2);
+    let sign = if locale::number(amount, 2).starts_with('-') {
+        "-"
+    } else {
+        ""
+    };
+    let Some(currency) = currency else {
+        return format!("{}{}", sign, number);
+    };
+    // Where the decimal mark is a comma, the currency usually comes after
+    let position = position.unwrap_or(if locale::current().decimal_separator == "," {
+        CurrencyPosition::After
+    } else {
+        CurrencyPosition::Before
+    });
+    match position {
+        // Codes like `USD` are kept apart from the digits, symbols aren't
+        CurrencyPosition::Before if currency.chars().all(char::is_alphabetic) => {
+            format!("{}{} {}", sign, currency, number)
+        }
+        CurrencyPosition::Before => format!("{}{}{}", sign, currency, number),
+        CurrencyPosition::After => format!("{}{} {}", sign, number, currency),
+    }
+}
+
+/// Write a starter template to the templates directory, unless there's one
+/// already.
+pub fn init(dir: &Path, starter: Starter) -> Result<PathBuf> {
+    let path = dir.join(format!("{}.txt", starter.name()));
+    if path.exists() {
+        bail!(
+            "Template '{}' already exists at {}",
+            starter.name(),
+            path.display()
+        );
+    }
+    fs::create_dir_all(dir).context("Could not create templates directory")?;
+    fs::write(&path, starter.contents()).context("Could not write template")?;
+    Ok(path)
+}
+
+/// Render a template of the templates directory.
+pub fn render(
+    dir: &Path,
+    name: &str,
+    context: &Context,
+    invoice: &InvoiceConfig,
+) -> Result<String> {
+    let path = dir.join(format!("{}.txt", name));
+    let source = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Could not read template '{}' (expected at {}, see `temps report --init-template`)",
+            name,
+            path.display()
+        )
+    })?;
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    // `{{ hours | number }}`, or `number(1)` for a single decimal
+    env.add_filter("number", |value: f64, decimals: Option<usize>| {
+        locale::number(value, decimals.unwrap_or(2))
+    });
+    // `{{ amount | money }}` in the default currency, or `money(currency)`
+    let default = invoice.currency.clone();
+    let position = invoice.currency_position;
+    env.add_filter("money", move |amount: f64, currency: Option<String>| {
+        money(
+            amount,
+            currency.or_else(|| default.clone()).as_deref(),
+            position,
+        )
+    });
+    env.add_template(name, &source)
+        .with_context(|| format!("Could not parse template '{}'", name))?;
+    env.get_template(name)?
+        .render(context)
+        .with_context(|| format!("Could not render template '{}'", name))
+}
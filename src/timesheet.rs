@@ -0,0 +1,95 @@
+//! Timesheets for HR systems, for `temps export`.
+//!
+//! A timesheet has a row for each project and day, with the columns of
+//! `[timesheet]` in the config, so that it can be laid out the way an HR
+//! system imports it.  Hours of each day are rounded to hundredths so that
+//! they still add up to the day's total.
+
+use std::env;
+
+use anyhow::{bail, Context, Result};
+use csv::WriterBuilder;
+
+use crate::config::{TimesheetConfig, TimesheetField};
+use crate::export::Output;
+use crate::rounding::{self, CENTIHOUR};
+use crate::summary::DailyTotals;
+
+/// Format of exported timesheets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One row per project and day, with the columns of `[timesheet]`
+    #[default]
+    TimesheetCsv,
+}
+
+/// Write a timesheet of daily totals to the output.
+pub fn write(totals: &DailyTotals, config: &TimesheetConfig, output: &Output) -> Result<()> {
+    if !config.delimiter.is_ascii() {
+        bail!(
+            "Timesheet delimiter '{}' isn't an ASCII character",
+            config.delimiter
+        );
+    }
+    if config.columns.is_empty() {
+        bail!("No timesheet columns in the config, see `[[timesheet.columns]]`");
+    }
+    for column in &config.columns {
+        if column.field.is_some() == column.value.is_some() {
+            bail!(
+                "Timesheet column '{}' needs either a `field` or a `value`",
+                column.header
+            );
+        }
+    }
+    let employee = match &config.employee {
+        Some(employee) => employee.clone(),
+        None => env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .ok()
+            .context("No employee for the timesheet, set `employee` in `[timesheet]`")?,
+    };
+
+    let mut contents = vec![];
+    let mut writer = WriterBuilder::new()
+        .delimiter(config.delimiter as u8)
+        .from_writer(&mut contents);
+    writer.write_record(config.columns.iter().map(|column| &column.header))?;
+    for (day, projects) in totals {
+        let seconds = projects
+            .values()
+            .map(|duration| duration.whole_seconds().max(0))
+            .collect::<Vec<_>>();
+        let centihours = rounding::largest_remainder(&seconds, CENTIHOUR);
+        let (year, week, _) = day.to_iso_week_date();
+        for ((project, duration), centihours) in projects.iter().zip(centihours) {
+            if duration.whole_seconds() <= 0 {
+                continue;
+            }
+            let row = config.columns.iter().map(|column| {
+                let Some(field) = column.field else {
+                    return column.value.clone().unwrap_or_default();
+                };
+                match field {
+                    TimesheetField::Employee => employee.clone(),
+                    TimesheetField::Week => format!("{}-W{:02}", year, week),
+                    TimesheetField::Date => day.to_string(),
+                    TimesheetField::Weekday => day.weekday().to_string(),
+                    TimesheetField::Project => project.clone(),
+                    TimesheetField::Code => config.code_of(project).to_owned(),
+                    TimesheetField::Hours => format!(
+                        "{}{}{:02}",
+                        centihours / 100,
+                        config.decimal_separator,
+                        centihours % 100
+                    ),
+                    TimesheetField::Minutes => (duration.whole_seconds() / 60).to_string(),
+                }
+            });
+            writer.write_record(row)?;
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+    output.write(&contents)
+}
@@ -0,0 +1,848 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use time::ext::NumericalDuration;
+use time::macros::format_description;
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::export::{self, Output};
+use crate::table::{display_width, Color};
+use crate::{duration_to_string, locale, Entry, Format, Kind};
+
+/// Characters used to draw charts.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyphs {
+    /// Two quarter-hours tracked
+    pub full: char,
+    /// Only the first of two quarter-hours tracked
+    pub upper_half: char,
+    /// Only the second of two quarter-hours tracked
+    pub lower_half: char,
+    /// Line under the time labels, every two hours
+    pub border: char,
+    /// Nothing tracked, in the compact timeline
+    pub empty: char,
+    /// Between the start and end of an interval
+    pub dash: char,
+    /// End of an ongoing interval
+    pub ellipsis: &'static str,
+    /// Two quarter-hours of the ongoing entry
+    pub ongoing: char,
+    /// Pointing at the current time, next to the ongoing entry
+    pub arrow: &'static str,
+}
+
+impl Glyphs {
+    /// Block characters, for terminals and fonts that support them.
+    pub const UNICODE: Glyphs = Glyphs {
+        full: '█',
+        upper_half: '▀',
+        lower_half: '▄',
+        border: '▁',
+        empty: '·',
+        dash: '–',
+        ellipsis: "…",
+        ongoing: '▒',
+        arrow: "←",
+    };
+
+    /// Plain ASCII characters, for everything else.
+    pub const ASCII: Glyphs = Glyphs {
+        full: '#',
+        upper_half: '=',
+        lower_half: '=',
+        border: '_',
+        empty: '.',
+        dash: '-',
+        ellipsis: "...",
+        ongoing: '+',
+        arrow: "<-",
+    };
+}
+
+/// Where the names of projects go in charts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Labels {
+    /// After the bars
+    #[default]
+    Right,
+    /// Before the bars
+    Left,
+    /// Nowhere
+    None,
+}
+
+/// How charts look.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub glyphs: Glyphs,
+    /// Width of the bars
+    pub bar_width: usize,
+    pub labels: Labels,
+    /// Whether each project gets its own color, with a legend under the chart
+    pub colored: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            glyphs: Glyphs::UNICODE,
+            bar_width: 8,
+            labels: Labels::Right,
+            colored: false,
+        }
+    }
+}
+
+/// Number of lines of the chart in a day, i.e. half-hours.
+const LINES_PER_DAY: i64 = 48;
+
+/// Width of the time labels on the left of the chart.
+const TIMES_WIDTH: usize = 6;
+
+/// Width of the longest label kept on one line, when its block leaves room
+/// to wrap it.
+const LABEL_WIDTH: usize = 20;
+
+/// Number of lines a label can be moved up or down when several projects
+/// start on the same line.
+const LABEL_NUDGE: usize = 2;
+
+/// Braille dots of each row of a character, both columns at once.
+const BRAILLE_ROWS: [u32; 4] = [0x09, 0x12, 0x24, 0xC0];
+
+/// How finely the chart shows time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Quarter-hours, drawn with half-blocks
+    Quarter,
+    /// Eighths of an hour, drawn with Braille patterns
+    ///
+    /// Any time tracked in a slot shows up, so that even very short entries
+    /// are visible.
+    Fine,
+}
+
+impl Resolution {
+    /// Number of slots in each line of the chart.
+    fn slots_per_line(self) -> usize {
+        match self {
+            Resolution::Quarter => 2,
+            Resolution::Fine => 4,
+        }
+    }
+}
+
+/// Print a vertical chart of the time spent on some days, side by side.
+///
+/// Each line of the chart covers half an hour, which is split in slots
+/// depending on the resolution.  Days start at midnight shifted by
+/// `midnight_offset`.  The hours are shared between days, and span from the
+/// first to the last tracked hour over all days, unless a `window` of time of
+/// day is given.  Unscheduled entries are listed under the chart, in a lane of
+/// their own.
+pub fn visualize(
+    entries: &[Entry],
+    dates: &[Date],
+    midnight_offset: Duration,
+    window: Option<(Duration, Duration)>,
+    theme: Theme,
+    resolution: Resolution,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let per_line = resolution.slots_per_line();
+    let days = dates
+        .iter()
+        .map(|date| {
+            slots(
+                entries,
+                date.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + midnight_offset,
+                now,
+                resolution,
+            )
+        })
+        .collect::<Vec<_>>();
+    let lanes = dates
+        .iter()
+        .map(|date| unscheduled(entries, *date, midnight_offset, now).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let tracked = || {
+        days.iter().flat_map(|slots| {
+            slots
+                .iter()
+                .enumerate()
+                .filter(|(_, project)| project.is_some())
+                .map(|(i, _)| (i / per_line) as i64)
+        })
+    };
+    let lines = if let Some((start, end)) = window {
+        // Lines overlapping with the window
+        let line = |time: Duration| (time - midnight_offset).whole_minutes() as f64 / 30.;
+        (line(start).floor() as i64..line(end).ceil() as i64).collect::<Vec<_>>()
+    } else {
+        let (Some(first), Some(last)) = (tracked().min(), tracked().max()) else {
+            if lanes.iter().all(Vec::is_empty) {
+                return Ok(());
+            }
+            return unscheduled_lane(&lanes, &[], now, theme);
+        };
+        // Start a bit before the first line, rounded at a half hour, that way
+        // the time is displayed properly
+        let first = (first / 4) * 4 - 1;
+        // Add an empty line at the end if we're close to a two-hour mark
+        // This makes the display slightly prettier :>
+        let last = if last % 4 == 3 {
+            (last / 4 + 1) * 4
+        } else {
+            last
+        };
+        (first..=last).collect::<Vec<_>>()
+    };
+
+    // Render each day as a column of bars and labels
+    let columns = days
+        .iter()
+        .map(|slots| {
+            let mut previous_project = None;
+            // The ongoing entry ends with an arrow pointing at now
+            let now_line = slots
+                .iter()
+                .rposition(|slot| slot.is_some_and(Entry::is_ongoing))
+                .map(|i| (i / per_line) as i64);
+            let mut bars = vec![];
+            let mut labels = vec![];
+            let mut projects = vec![];
+            for &line in &lines {
+                let slots = (0..per_line as i64)
+                    .map(|i| {
+                        usize::try_from(line * per_line as i64 + i)
+                            .ok()
+                            .and_then(|i| slots.get(i).copied().flatten())
+                    })
+                    .collect::<Vec<_>>();
+                let (bar, line_labels) = cell(&slots, &mut previous_project, theme, resolution);
+                bars.push(bar);
+                labels.push(line_labels);
+                projects.push(
+                    slots
+                        .iter()
+                        .flatten()
+                        .map(|entry| entry.project.as_str())
+                        .collect::<Vec<_>>(),
+                );
+            }
+            let mut labels = place_labels(labels, &projects);
+            if let Some(n) = now_line.and_then(|now| lines.iter().position(|&line| line == now)) {
+                let label = &mut labels[n];
+                if !label.is_empty() {
+                    label.push(' ');
+                }
+                label.push_str(theme.glyphs.arrow);
+                label.push_str(" now");
+            }
+            layout(bars.into_iter().zip(labels).collect(), theme)
+        })
+        .collect::<Vec<_>>();
+
+    // Side by side, columns are headed by their date, and as wide as their
+    // longest line
+    let headers = if dates.len() > 1 {
+        dates
+            .iter()
+            .map(|date| {
+                Ok::<_, time::error::Format>(format!(
+                    "{} {}",
+                    date.format(&format_description!("[weekday repr:short]"))?,
+                    locale::month_day(*date)
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![]
+    };
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(day, cells)| {
+            cells
+                .iter()
+                .chain(headers.get(day))
+                .map(|cell| display_width(cell))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    if !headers.is_empty() {
+        let mut line = " ".repeat(TIMES_WIDTH);
+        for (header, width) in headers.iter().zip(&widths) {
+            line.push_str(&format!("{:width$}  ", header, width = *width));
+        }
+        println!("{}", line.trim_end());
+    }
+
+    for (n, &line) in lines.iter().enumerate() {
+        // Display the time every two hours
+        let times = if line.rem_euclid(4) == 0 {
+            format!(
+                "{:width$} ",
+                locale::hour(Time::MIDNIGHT + midnight_offset + (line * 30).minutes()),
+                width = TIMES_WIDTH - 1
+            )
+        } else if line.rem_euclid(4) == 3 {
+            theme.glyphs.border.to_string().repeat(TIMES_WIDTH)
+        } else {
+            " ".repeat(TIMES_WIDTH)
+        };
+
+        let mut cells = String::new();
+        for (cells_of_day, width) in columns.iter().zip(&widths) {
+            let cell = &cells_of_day[n];
+            let padding = width.saturating_sub(display_width(cell));
+            cells.push_str(cell);
+            cells.push_str(&" ".repeat(padding + 2));
+        }
+        println!("{}{}", times, cells.trim_end());
+    }
+    if lanes.iter().any(|lane| !lane.is_empty()) {
+        println!();
+        unscheduled_lane(&lanes, &widths, now, theme)?;
+    }
+    if dates.len() > 1 {
+        // The time tracked each day, under its column
+        let mut line = format!("{:width$}", "Total", width = TIMES_WIDTH);
+        for (date, width) in dates.iter().zip(&widths) {
+            let total = duration_to_string(day_total(entries, *date, midnight_offset, now))?;
+            line.push_str(&format!("{:width$}  ", total, width = *width));
+        }
+        println!();
+        println!("{}", line.trim_end());
+    }
+
+    if theme.colored {
+        // Projects in the order they first appear
+        let mut projects: Vec<&String> = vec![];
+        for entry in days
+            .iter()
+            .flatten()
+            .flatten()
+            .chain(lanes.iter().flatten())
+        {
+            if !projects.contains(&&entry.project) {
+                projects.push(&entry.project);
+            }
+        }
+        println!();
+        for project in projects {
+            println!(
+                "{} {}",
+                Color::Hashed.paint_as(&theme.glyphs.full.to_string().repeat(2), project),
+                project
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the unscheduled entries of each day, under the columns of the chart
+/// (at least as wide as `widths`).
+fn unscheduled_lane(
+    lanes: &[Vec<&Entry>],
+    widths: &[usize],
+    now: OffsetDateTime,
+    theme: Theme,
+) -> Result<()> {
+    let bar = theme.glyphs.full.to_string().repeat(theme.bar_width);
+    let columns = lanes
+        .iter()
+        .map(|lane| {
+            lane.iter()
+                .map(|entry| {
+                    let bar = if theme.colored {
+                        Color::Hashed.paint_as(&bar, &entry.project)
+                    } else {
+                        bar.clone()
+                    };
+                    Ok(format!(
+                        "{} {} ({})",
+                        bar,
+                        entry.project,
+                        duration_to_string(entry.end.unwrap_or(now) - entry.start)?
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(day, cells)| {
+            cells
+                .iter()
+                .map(|cell| display_width(cell))
+                .chain(widths.get(day).copied())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    println!("{:width$}unscheduled", "", width = TIMES_WIDTH);
+    let rows = columns.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..rows {
+        let mut line = " ".repeat(TIMES_WIDTH);
+        for (cells, width) in columns.iter().zip(&widths) {
+            let cell = cells.get(row).map_or("", String::as_str);
+            line.push_str(cell);
+            line.push_str(&" ".repeat(width.saturating_sub(display_width(cell)) + 2));
+        }
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// Entry tracked during each slot of a day, if any.
+fn slots(
+    entries: &[Entry],
+    date: OffsetDateTime,
+    now: OffsetDateTime,
+    resolution: Resolution,
+) -> Vec<Option<&Entry>> {
+    let next_date = date + Duration::days(1);
+    let count = LINES_PER_DAY as usize * resolution.slots_per_line();
+    let slot_seconds = Duration::DAY.whole_seconds() as f64 / count as f64;
+    let mut slots = vec![None; count];
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.kind != Kind::Unscheduled)
+    {
+        let start = entry.start.max(date);
+        let end = entry.end.unwrap_or(now).min(next_date);
+        if start >= end {
+            continue;
+        }
+        let slot = |dt: OffsetDateTime| (dt - date).whole_seconds() as f64 / slot_seconds;
+        let (s, e) = match resolution {
+            // Round to the nearest slot, skipping very short entries
+            Resolution::Quarter => (slot(start).round(), slot(end).round()),
+            // Every slot the entry overlaps with
+            Resolution::Fine => (slot(start).floor(), slot(end).ceil()),
+        };
+        slots[s as usize..(e as usize).min(count)].fill(Some(entry));
+    }
+    slots
+}
+
+/// Render a line of the chart, i.e. a bar and the name of the projects that
+/// started on that line.
+fn cell<'a>(
+    slots: &[Option<&'a Entry>],
+    previous_project: &mut Option<&'a str>,
+    theme: Theme,
+    resolution: Resolution,
+) -> (String, Vec<&'a str>) {
+    // Name each project when it starts
+    let mut labels: Vec<&str> = vec![];
+    for slot in slots {
+        let project = slot.map(|entry| entry.project.as_str());
+        if let Some(project) = project {
+            if *previous_project != Some(project) && labels.last() != Some(&project) {
+                labels.push(project);
+            }
+        }
+        *previous_project = project;
+    }
+
+    let glyphs = theme.glyphs;
+    let bar = match resolution {
+        Resolution::Quarter => match slots {
+            [None, None] => ' ',
+            [None, Some(_)] => glyphs.lower_half,
+            [Some(_), None] => glyphs.upper_half,
+            // The ongoing entry stands out from the finished ones
+            [Some(p0), Some(p1)] if p0.is_ongoing() && p1.is_ongoing() => glyphs.ongoing,
+            _ => glyphs.full,
+        },
+        Resolution::Fine => {
+            let dots = slots
+                .iter()
+                .zip(BRAILLE_ROWS)
+                .filter(|(slot, _)| slot.is_some())
+                .map(|(_, dots)| dots)
+                .sum::<u32>();
+            if dots == 0 {
+                ' '
+            } else {
+                char::from_u32(0x2800 + dots).unwrap()
+            }
+        }
+    };
+    let bar = bar.to_string().repeat(theme.bar_width);
+    // Lines shared by two projects are in the color of the latest one
+    let bar = match slots.iter().rev().flatten().next() {
+        Some(entry) if theme.colored => Color::Hashed.paint_as(&bar, &entry.project),
+        _ => bar,
+    };
+    (bar, labels)
+}
+
+/// Place the labels of the lines of a chart, given the projects on each line,
+/// so that every block can be told apart.
+///
+/// When several projects start on the same line, the last ones are moved to
+/// the next lines and the first ones to the previous lines, as long as those
+/// are at most [`LABEL_NUDGE`] lines away and have no label of their own;
+/// those that can't be moved share the line, separated by ` / `.  Names
+/// longer than [`LABEL_WIDTH`] are then wrapped onto the next lines, if those
+/// are still part of their block and have no label.
+fn place_labels(mut labels: Vec<Vec<&str>>, projects: &[Vec<&str>]) -> Vec<String> {
+    let n = labels.len();
+    let mut free = labels.iter().map(Vec::is_empty).collect::<Vec<_>>();
+    let mut placed = vec![vec![]; n];
+    for i in 0..n {
+        let mut here = std::mem::take(&mut labels[i]);
+        while here.len() > 1 {
+            let Some(j) = (i + 1..n.min(i + 1 + LABEL_NUDGE)).find(|&j| free[j]) else {
+                break;
+            };
+            free[j] = false;
+            placed[j].extend(here.pop());
+        }
+        while here.len() > 1 {
+            let Some(j) = (i.saturating_sub(LABEL_NUDGE)..i).rev().find(|&j| free[j]) else {
+                break;
+            };
+            free[j] = false;
+            placed[j].push(here.remove(0));
+        }
+        placed[i].extend(here);
+    }
+
+    let mut lines = placed
+        .iter()
+        .map(|labels| labels.join(" / "))
+        .collect::<Vec<_>>();
+    let mut i = 0;
+    while i < n {
+        let [project] = placed[i][..] else {
+            i += 1;
+            continue;
+        };
+        let wrapped = wrap(project, LABEL_WIDTH);
+        let room = (1..wrapped.len()).all(|k| {
+            placed
+                .get(i + k)
+                .is_some_and(|labels| labels.is_empty() && projects[i + k].contains(&project))
+        });
+        if wrapped.len() > 1 && room {
+            let len = wrapped.len();
+            lines.splice(i..i + len, wrapped);
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Split a name at spaces into lines of at most `width` columns (or a single
+/// longer word).
+fn wrap(name: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for word in name.split(' ') {
+        match lines.last_mut() {
+            Some(line) if display_width(line) + 1 + display_width(word) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    lines
+}
+
+/// Put together the bars and labels of a column of the chart.
+fn layout(cells: Vec<(String, String)>, theme: Theme) -> Vec<String> {
+    let label_width = cells
+        .iter()
+        .map(|(_, label)| display_width(label))
+        .max()
+        .unwrap_or(0);
+    cells
+        .into_iter()
+        .map(|(bar, label)| match theme.labels {
+            Labels::Right if label.is_empty() => bar,
+            Labels::Right => format!("{} {}", bar, label),
+            // Right-aligned, so that labels are next to their bar
+            Labels::Left if label_width > 0 => {
+                format!("{:>width$} {}", label, bar, width = label_width)
+            }
+            Labels::Left | Labels::None => bar,
+        })
+        .collect()
+}
+
+/// Print a compact, horizontal timeline of the time tracked on a given day,
+/// which starts at `midnight_offset`.
+///
+/// Each character is a quarter-hour, from the first to the last tracked hour.
+pub fn timeline(
+    entries: &[Entry],
+    date: Date,
+    midnight_offset: Duration,
+    glyphs: Glyphs,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let date = date.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + midnight_offset;
+    let next_date = date + Duration::days(1);
+
+    // Mark every quarter-hour of the day during which something was tracked
+    let mut tracked = [false; 96];
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.kind != Kind::Unscheduled)
+    {
+        let start = entry.start.max(date);
+        let end = entry.end.unwrap_or(now).min(next_date);
+        if start >= end {
+            continue;
+        }
+        let s = (start - date).whole_minutes() as usize / 15;
+        let e = ((end - date).whole_minutes() as usize).div_ceil(15);
+        tracked[s..e.min(96)].fill(true);
+    }
+
+    let (Some(first), Some(last)) = (
+        tracked.iter().position(|&t| t),
+        tracked.iter().rposition(|&t| t),
+    ) else {
+        return Ok(());
+    };
+
+    // Display the hours, each one four characters wide, above the timeline
+    let hours = (first / 4)..(last / 4 + 1);
+    for hour in hours.clone() {
+        let time = Time::MIDNIGHT + midnight_offset + (hour as i64).hours();
+        print!("{:<4}", locale::short_hour(time));
+    }
+    println!();
+    for slot in &tracked[hours.start * 4..hours.end * 4] {
+        print!("{}", if *slot { glyphs.full } else { glyphs.empty });
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Print the intervals tracked on some days, one per line.
+///
+/// This is the same information as [`visualize`], as plain text (e.g. for
+/// screen readers).
+pub fn intervals(
+    entries: &[Entry],
+    dates: &[Date],
+    midnight_offset: Duration,
+    glyphs: Glyphs,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    for (i, date) in dates.iter().enumerate() {
+        if dates.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{}", locale::weekday_date(*date));
+        }
+
+        let next_date = bounds(*date, midnight_offset, now).1;
+        for (entry, start, end) in clip(entries, *date, midnight_offset, now) {
+            let end = match end {
+                // Midnight is shown as 24:00 at the end of the day
+                Some(end) if end == next_date && end.time() == Time::MIDNIGHT => {
+                    locale::end_of_day()
+                }
+                Some(end) => locale::time(end.time()),
+                None => glyphs.ellipsis.to_owned(),
+            };
+            println!(
+                "{}{}{:width$} {}",
+                locale::time(start.time()),
+                glyphs.dash,
+                end,
+                entry.project,
+                width = locale::time_width()
+            );
+        }
+        for entry in unscheduled(entries, *date, midnight_offset, now) {
+            println!(
+                "{:width$} {} ({})",
+                "unscheduled",
+                entry.project,
+                duration_to_string(entry.end.unwrap_or(now) - entry.start)?,
+                width = 2 * locale::time_width() + 1
+            );
+        }
+    }
+    Ok(())
+}
+
+/// One of the intervals listed by [`json`].
+#[derive(Serialize)]
+struct Interval<'a> {
+    date: String,
+    project: &'a str,
+    tags: &'a [String],
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    end: OffsetDateTime,
+    ongoing: bool,
+    /// Whether the entry started before the day, or ended after it.
+    clipped: bool,
+}
+
+/// Print the intervals tracked on some days as a JSON array.
+///
+/// Entries spanning several days are split at the day boundaries, and the
+/// ongoing entry ends now, like in [`visualize`].  Unscheduled entries aren't
+/// intervals, and are left out.
+pub fn json(
+    entries: &[Entry],
+    dates: &[Date],
+    midnight_offset: Duration,
+    output: &Output,
+) -> Result<()> {
+    let now = OffsetDateTime::now_local()?;
+    let mut intervals = vec![];
+    for date in dates {
+        let (day_start, day_end) = bounds(*date, midnight_offset, now);
+        for (entry, start, end) in clip(entries, *date, midnight_offset, now) {
+            intervals.push(Interval {
+                date: date.format(format_description!("[year]-[month]-[day]"))?,
+                project: &entry.project,
+                tags: &entry.tags,
+                start,
+                end: end.unwrap_or_else(|| now.min(day_end)),
+                ongoing: entry.is_ongoing(),
+                clipped: entry.start < day_start || entry.end.unwrap_or(now) > day_end,
+            });
+        }
+    }
+    export::write(&intervals, Format::Json, output)
+}
+
+/// Start and end of a day, accounting for the midnight offset.
+fn bounds(
+    date: Date,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> (OffsetDateTime, OffsetDateTime) {
+    let start = date.with_time(Time::MIDNIGHT).assume_offset(now.offset()) + midnight_offset;
+    (start, start + Duration::days(1))
+}
+
+/// The time tracked on a day, including unscheduled entries.
+fn day_total(
+    entries: &[Entry],
+    date: Date,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> Duration {
+    let day_end = bounds(date, midnight_offset, now).1;
+    let scheduled = clip(entries, date, midnight_offset, now)
+        .map(|(_, start, end)| end.unwrap_or(now).min(day_end) - start)
+        .sum::<Duration>();
+    let unscheduled = unscheduled(entries, date, midnight_offset, now)
+        .map(|entry| entry.duration(now))
+        .sum::<Duration>();
+    scheduled + unscheduled
+}
+
+/// The entries recorded on a day without start or end times.
+fn unscheduled(
+    entries: &[Entry],
+    date: Date,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> impl Iterator<Item = &Entry> {
+    entries.iter().filter(move |entry| {
+        entry.kind == Kind::Unscheduled
+            && (entry.start - midnight_offset)
+                .to_offset(now.offset())
+                .date()
+                == date
+    })
+}
+
+/// The entries tracked on a day, with their start and end clipped to it.
+///
+/// Unscheduled entries are left out, and the end of the ongoing entry is
+/// `None`.
+fn clip(
+    entries: &[Entry],
+    date: Date,
+    midnight_offset: Duration,
+    now: OffsetDateTime,
+) -> impl Iterator<Item = (&Entry, OffsetDateTime, Option<OffsetDateTime>)> {
+    let (day_start, day_end) = bounds(date, midnight_offset, now);
+    entries.iter().filter_map(move |entry| {
+        if entry.kind == Kind::Unscheduled {
+            return None;
+        }
+        let start = entry.start.max(day_start);
+        let end = entry.end.map(|end| end.min(day_end));
+        (start < end.unwrap_or(now).min(day_end)).then_some((entry, start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_move_to_free_lines() {
+        let projects = [vec!["a", "b"], vec!["b"], vec!["b"]];
+        assert_eq!(
+            place_labels(vec![vec!["a", "b"], vec![], vec![]], &projects),
+            ["a", "b", ""]
+        );
+
+        // Last ones down, first ones up
+        let labels = vec![vec![], vec!["a", "b", "c"], vec![]];
+        let projects = [vec!["a"], vec!["a", "b", "c"], vec!["c"]];
+        assert_eq!(place_labels(labels, &projects), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn labels_share_a_line_without_room() {
+        assert_eq!(
+            place_labels(vec![vec!["a", "b", "c"]], &[vec!["a", "b", "c"]]),
+            ["a / b / c"]
+        );
+
+        // Lines with a label of their own are skipped
+        let labels = vec![vec!["a", "b"], vec!["c"], vec![], vec![], vec![]];
+        let projects = [vec!["a", "b"], vec!["c"], vec!["c"], vec!["c"], vec!["c"]];
+        assert_eq!(place_labels(labels, &projects), ["a", "c", "b", "", ""]);
+    }
+
+    #[test]
+    fn long_labels_wrap_within_their_block() {
+        let name = "a rather long project name";
+        assert_eq!(
+            place_labels(
+                vec![vec![name], vec![], vec![]],
+                &[vec![name], vec![name], vec![]]
+            ),
+            ["a rather long", "project name", ""]
+        );
+        // Not past the end of the block
+        assert_eq!(
+            place_labels(vec![vec![name], vec![]], &[vec![name], vec!["b"]]),
+            [name, ""]
+        );
+        assert_eq!(
+            place_labels(vec![vec![name], vec!["b"]], &[vec![name], vec![name, "b"]]),
+            [name, "b"]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Tracking time from the focused window.
+//!
+//! The watcher looks at the focused window every few seconds, and switches to
+//! the project of the first rule matching its app and title.  Windows that no
+//! rule matches leave the ongoing entry alone, e.g. a terminal or a chat.
+//!
+//! If idle time is watched too, the ongoing entry is stopped when the user
+//! goes away (or replaced with an `afk` entry), and resumed when they're back.
+//!
+//! At the end of the workday, if there's one, the ongoing entry is stopped
+//! (after asking, if there's a command to ask with), and nothing is started
+//! again that day unless the user starts something themselves.
+//!
+//! Since it runs all the time, the watcher only locks the tracking file while
+//! it changes it, so that other commands can run in the meantime.
+
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::config::{Config, Provider, WatchConfig, AFK};
+use crate::mqtt::{self, Event};
+use crate::{datetime_to_human_string, storage, switch_to};
+
+/// The focused window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub app: String,
+    pub title: String,
+}
+
+/// Watch the focused window, forever.
+///
+/// With `dry_run`, the projects the windows map to are printed instead of
+/// being tracked.
+pub fn run(path: &Path, config: &Config, dry_run: bool) -> Result<()> {
+    let watch = &config.watch;
+    if watch.rules.is_empty() && watch.idle_after.is_none() && watch.stop_at.is_none() {
+        bail!("No rules to match windows with, see [[watch.rules]] in the config");
+    }
+
+    // The project of the focused window, and since when it's been focused
+    let mut candidate: Option<(String, OffsetDateTime)> = None;
+    let mut last_window = None;
+    // The project to resume once the user is back, while they're away
+    let mut away: Option<String> = None;
+    // The last day whose end was handled, and the last one tracking stopped at
+    let mut handled: Option<Date> = None;
+    let mut stopped: Option<Date> = None;
+    loop {
+        let now = OffsetDateTime::now_local()?;
+        if let Some(stop_at) = &watch.stop_at {
+            let end = now.replace_time(Time::MIDNIGHT) + stop_at.0;
+            if now >= end && handled != Some(now.date()) {
+                handled = Some(now.date());
+                if stop_for_the_day(path, config, end, dry_run)? {
+                    stopped = handled;
+                    away = None;
+                }
+            }
+        }
+        if let Some(idle_after) = &watch.idle_after {
+            match idle(watch) {
+                Ok(idle) if idle >= idle_after.0 => {
+                    if away.is_none() {
+                        away = leave(path, config, now - idle, dry_run)?;
+                    }
+                    thread::sleep(StdDuration::from_secs(watch.interval.max(1)));
+                    continue;
+                }
+                Ok(idle) => {
+                    if let Some(project) = away.take() {
+                        come_back(path, config, project, now - idle, dry_run)?;
+                    }
+                }
+                Err(err) => eprintln!("Warning: {:#}", err),
+            }
+        }
+
+        // Without rules, there's no need to look at windows at all
+        let window = if watch.rules.is_empty() {
+            None
+        } else {
+            focused(watch).unwrap_or_else(|err| {
+                eprintln!("Warning: {:#}", err);
+                None
+            })
+        };
+        let project = window.as_ref().and_then(|window| {
+            watch
+                .rules
+                .iter()
+                .find(|rule| rule.matches(&window.app, &window.title))
+                .map(|rule| rule.project.clone())
+        });
+
+        if dry_run {
+            if window != last_window {
+                if let Some(window) = &window {
+                    println!(
+                        "{}  {}: {} -> {}",
+                        datetime_to_human_string(now)?,
+                        window.app,
+                        window.title,
+                        project.as_deref().unwrap_or("(no rule)")
+                    );
+                }
+            }
+        } else if let Some(project) = project {
+            let since = match candidate {
+                Some((candidate, since)) if candidate == project => since,
+                _ => now,
+            };
+            if now - since >= watch.switch_after.0 {
+                let _lock = storage::lock(path)?;
+                let ongoing = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+                let after_hours = stopped == Some(now.date()) && ongoing.is_none();
+                if !after_hours && ongoing.is_none_or(|ongoing| ongoing.project != project) {
+                    switch_to(path, config, project.clone(), since)?;
+                }
+            }
+            candidate = Some((project, since));
+        } else {
+            candidate = None;
+        }
+        last_window = window;
+
+        thread::sleep(StdDuration::from_secs(watch.interval.max(1)));
+    }
+}
+
+/// Stop the ongoing entry at the end of the workday, if it started before,
+/// and return whether it was stopped.
+///
+/// With a command to ask with, the entry is only stopped if it succeeds.
+fn stop_for_the_day(
+    path: &Path,
+    config: &Config,
+    end: OffsetDateTime,
+    dry_run: bool,
+) -> Result<bool> {
+    let ongoing = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+    let Some(ongoing) = ongoing.filter(|ongoing| ongoing.start < end) else {
+        return Ok(false);
+    };
+    if dry_run {
+        println!("{}  end of the workday", datetime_to_human_string(end)?);
+        return Ok(true);
+    }
+    if let Some(command) = &config.watch.ask_command {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .with_context(|| format!("Could not run '{}'", command))?;
+        if !status.success() {
+            eprintln!("Kept '{}' going.", ongoing.project);
+            return Ok(false);
+        }
+    }
+
+    // The entry may have changed while asking
+    let _lock = storage::lock(path)?;
+    let Some(mut ongoing) = storage::read_last(path)?
+        .filter(|entry| entry.is_ongoing() && entry.start == ongoing.start)
+    else {
+        return Ok(false);
+    };
+    ongoing.stop_at(end, false)?;
+    eprintln!("Stopped '{}' at the end of the workday.", ongoing.project);
+    storage::replace_last(path, &[ongoing.clone()])?;
+    mqtt::publish(&config.mqtt, Event::Stop, &ongoing);
+    Ok(true)
+}
+
+/// Stop tracking (or switch to `afk`) from when the user went away, and
+/// return the project to resume when they're back.
+fn leave(
+    path: &Path,
+    config: &Config,
+    since: OffsetDateTime,
+    dry_run: bool,
+) -> Result<Option<String>> {
+    let _lock = (!dry_run).then(|| storage::lock(path)).transpose()?;
+    let Some(mut ongoing) = storage::read_last(path)?.filter(|entry| entry.is_ongoing()) else {
+        return Ok(None);
+    };
+    if ongoing.project == AFK {
+        return Ok(None);
+    }
+    if dry_run {
+        println!("{}  away", datetime_to_human_string(since)?);
+    } else if config.watch.afk {
+        switch_to(path, config, AFK.to_owned(), since)?;
+    } else {
+        ongoing.stop_at(since.max(ongoing.start), false)?;
+        eprintln!("Stopped '{}'.", ongoing.project);
+        storage::replace_last(path, &[ongoing.clone()])?;
+        mqtt::publish(&config.mqtt, Event::Stop, &ongoing);
+    }
+    Ok(Some(ongoing.project))
+}
+
+/// Resume a project from when the user came back, unless they started
+/// something else in the meantime.
+fn come_back(
+    path: &Path,
+    config: &Config,
+    project: String,
+    since: OffsetDateTime,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        println!("{}  back", datetime_to_human_string(since)?);
+        return Ok(());
+    }
+    let _lock = storage::lock(path)?;
+    let ongoing = storage::read_last(path)?.filter(|entry| entry.is_ongoing());
+    if ongoing.is_none_or(|ongoing| ongoing.project == AFK) {
+        switch_to(path, config, project, since)?;
+    }
+    Ok(())
+}
+
+/// How long since the last input.
+fn idle(watch: &WatchConfig) -> Result<Duration> {
+    let output = run_command("sh", &["-c", &watch.idle_command])?;
+    let milliseconds = output.trim().parse::<i64>().with_context(|| {
+        format!(
+            "Could not read idle time from '{}', expected milliseconds",
+            watch.idle_command
+        )
+    })?;
+    Ok(Duration::milliseconds(milliseconds))
+}
+
+/// The focused window, if there's one.
+pub fn focused(watch: &WatchConfig) -> Result<Option<Window>> {
+    match watch.provider {
+        Provider::X11 => {
+            let output = run_command(
+                "xdotool",
+                &["getactivewindow", "getwindowclassname", "getwindowname"],
+            )?;
+            Ok(two_lines(&output))
+        }
+        Provider::Sway => {
+            let tree = run_command("swaymsg", &["-t", "get_tree"])?;
+            let tree: Value = serde_json::from_str(&tree).context("Could not parse Sway tree")?;
+            Ok(sway_focused(&tree))
+        }
+        Provider::Hyprland => {
+            let window = run_command("hyprctl", &["activewindow", "-j"])?;
+            let window: Value =
+                serde_json::from_str(&window).context("Could not parse Hyprland window")?;
+            Ok(window_from_json(&window, "class", "title"))
+        }
+        Provider::Command => {
+            let Some(command) = &watch.command else {
+                bail!("No command to find the focused window, see `command` in [watch]");
+            };
+            Ok(two_lines(&run_command("sh", &["-c", command])?))
+        }
+    }
+}
+
+/// Run a command, and return what it printed.
+fn run_command(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Could not run '{}'", program))?;
+    if !output.status.success() {
+        bail!("'{}' failed ({})", program, output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A window from the app and the title, on the first two lines.
+fn two_lines(output: &str) -> Option<Window> {
+    let mut lines = output.lines();
+    let app = lines.next()?.trim().to_owned();
+    let title = lines.next().unwrap_or_default().trim().to_owned();
+    Some(Window { app, title })
+}
+
+/// The focused window in Sway's tree of containers.
+fn sway_focused(node: &Value) -> Option<Window> {
+    if node["focused"].as_bool() == Some(true) {
+        // Native Wayland apps have an app ID, others (through Xwayland) a class
+        return if node["app_id"].is_string() {
+            window_from_json(node, "app_id", "name")
+        } else {
+            node["window_properties"]["class"]
+                .as_str()
+                .map(|class| Window {
+                    app: class.to_owned(),
+                    title: node["name"].as_str().unwrap_or_default().to_owned(),
+                })
+        };
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_focused)
+}
+
+fn window_from_json(window: &Value, app: &str, title: &str) -> Option<Window> {
+    Some(Window {
+        app: window[app].as_str()?.to_owned(),
+        title: window[title].as_str().unwrap_or_default().to_owned(),
+    })
+}